@@ -114,15 +114,16 @@ def verbose_off() -> None:
 
 
 @contextmanager
-def new_mcap_file(fname: str) -> Iterator[None]:
+def new_mcap_file(fname: str) -> Iterator[MCAPWriter]:
     """
     Create an MCAP file at the given path for recording.
 
-    This is the context-managed equivalent of :py:func:`record_file`.
+    This is the context-managed equivalent of :py:func:`record_file`. The writer is yielded so
+    that metadata and attachments can be written to the file.
     """
     writer = record_file(fname)
     try:
-        yield
+        yield writer
     finally:
         writer.close()
 
@@ -1,5 +1,5 @@
 from enum import Enum
-from typing import Any, List, Optional, Protocol, Tuple
+from typing import Any, Dict, List, Optional, Protocol, Tuple
 
 class MCAPWriter:
     """
@@ -18,6 +18,21 @@ class MCAPWriter:
         """
         ...
 
+    def write_metadata(self, name: str, metadata: Dict[str, str]) -> None:
+        """
+        Write a metadata record to the MCAP file.
+        """
+        ...
+
+    def attach(self, name: str, media_type: str, log_time: int, data: bytes) -> None:
+        """
+        Write an attachment record to the MCAP file.
+
+        :param log_time: The time at which the attachment was recorded, in nanoseconds since the
+            unix epoch.
+        """
+        ...
+
 class WebSocketServer:
     """
     A websocket server for live visualization.
@@ -0,0 +1,107 @@
+import os
+import struct
+import tempfile
+import unittest
+from typing import Dict, Iterator, Tuple
+
+from foxglove import new_mcap_file
+
+MCAP_MAGIC = b"\x89MCAP0\r\n"
+OP_ATTACHMENT = 0x09
+OP_METADATA = 0x0C
+
+
+def read_records(data: bytes) -> Iterator[Tuple[int, bytes]]:
+    """
+    Yields the opcode and content of each top-level record in an MCAP file.
+    """
+    assert data.startswith(MCAP_MAGIC)
+    assert data.endswith(MCAP_MAGIC)
+    offset = len(MCAP_MAGIC)
+    end = len(data) - len(MCAP_MAGIC)
+    while offset < end:
+        opcode, length = struct.unpack_from("<BQ", data, offset)
+        offset += 9
+        yield opcode, data[offset : offset + length]
+        offset += length
+
+
+class Reader:
+    def __init__(self, data: bytes) -> None:
+        self.data = data
+        self.offset = 0
+
+    def u32(self) -> int:
+        (value,) = struct.unpack_from("<I", self.data, self.offset)
+        self.offset += 4
+        return int(value)
+
+    def u64(self) -> int:
+        (value,) = struct.unpack_from("<Q", self.data, self.offset)
+        self.offset += 8
+        return int(value)
+
+    def read_bytes(self, length: int) -> bytes:
+        value = self.data[self.offset : self.offset + length]
+        self.offset += length
+        return value
+
+    def string(self) -> str:
+        return self.read_bytes(self.u32()).decode()
+
+
+def parse_metadata(content: bytes) -> Tuple[str, Dict[str, str]]:
+    reader = Reader(content)
+    name = reader.string()
+    end = reader.u32() + reader.offset
+    metadata: Dict[str, str] = {}
+    while reader.offset < end:
+        key = reader.string()
+        metadata[key] = reader.string()
+    return name, metadata
+
+
+def parse_attachment(content: bytes) -> Tuple[int, str, str, bytes]:
+    reader = Reader(content)
+    log_time = reader.u64()
+    _create_time = reader.u64()
+    name = reader.string()
+    media_type = reader.string()
+    data = reader.read_bytes(reader.u64())
+    return log_time, name, media_type, data
+
+
+class TestMcap(unittest.TestCase):
+    def test_write_metadata_and_attachment(self) -> None:
+        with tempfile.TemporaryDirectory() as tmpdir:
+            path = os.path.join(tmpdir, "test.mcap")
+            with new_mcap_file(path) as writer:
+                writer.write_metadata("calibration", {"fx": "500", "fy": "501"})
+                writer.attach("notes.txt", "text/plain", 42, b"hello")
+
+            with open(path, "rb") as f:
+                records = list(read_records(f.read()))
+
+        metadata = [
+            parse_metadata(content)
+            for opcode, content in records
+            if opcode == OP_METADATA
+        ]
+        attachments = [
+            parse_attachment(content)
+            for opcode, content in records
+            if opcode == OP_ATTACHMENT
+        ]
+        self.assertEqual(metadata, [("calibration", {"fx": "500", "fy": "501"})])
+        self.assertEqual(attachments, [(42, "notes.txt", "text/plain", b"hello")])
+
+    def test_write_after_close(self) -> None:
+        with tempfile.TemporaryDirectory() as tmpdir:
+            with new_mcap_file(os.path.join(tmpdir, "test.mcap")) as writer:
+                pass
+            self.assertRaises(Exception, writer.write_metadata, "name", {})
+            self.assertRaises(Exception, writer.attach, "name", "text/plain", 0, b"")
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -1,5 +1,7 @@
 use errors::PyFoxgloveError;
-use foxglove::{Channel, ChannelBuilder, LogContext, McapWriter, McapWriterHandle, Schema};
+use foxglove::{
+    Channel, ChannelBuilder, FoxgloveError, LogContext, McapWriter, McapWriterHandle, Schema,
+};
 use generated::channels;
 use generated::schemas;
 use log::LevelFilter;
@@ -53,6 +55,39 @@ impl PyMcapWriter {
         }
         Ok(())
     }
+
+    /// Write a metadata record to the MCAP file.
+    ///
+    /// :param name: The name of the metadata record.
+    /// :param metadata: A dictionary of string key-value pairs.
+    fn write_metadata(&self, name: &str, metadata: BTreeMap<String, String>) -> PyResult<()> {
+        self.handle()?
+            .write_metadata(name, metadata)
+            .map_err(PyFoxgloveError::from)?;
+        Ok(())
+    }
+
+    /// Write an attachment record to the MCAP file.
+    ///
+    /// :param name: The name of the attachment, e.g. a file name.
+    /// :param media_type: The media type of the attachment, e.g. "text/plain".
+    /// :param log_time: The time at which the attachment was recorded, in nanoseconds since the
+    ///     unix epoch.
+    /// :param data: The content of the attachment.
+    fn attach(&self, name: &str, media_type: &str, log_time: u64, data: &[u8]) -> PyResult<()> {
+        self.handle()?
+            .attach(name, media_type, log_time, data)
+            .map_err(PyFoxgloveError::from)?;
+        Ok(())
+    }
+}
+
+impl PyMcapWriter {
+    fn handle(&self) -> PyResult<&McapWriterHandle<BufWriter<File>>> {
+        self.0
+            .as_ref()
+            .ok_or_else(|| PyFoxgloveError::from(FoxgloveError::SinkClosed).into())
+    }
 }
 
 #[pymethods]
@@ -32,21 +32,42 @@ pub async fn main(config: Config) -> Result<()> {
         ])
         .context("Failed to register services")?;
 
-    // Services that need to sleep (or do heavy computation) should use `tokio::spawn()`
-    // (or `tokio::task::spawn_blocking()`) to avoid blocking the client's main event loop.
-    // Unlike the `SyncHandler` implementations, the generic `Handler` is responsible for
-    // invoking `resp.respond()` to complete the request.
+    // Services that need to sleep (or await other work) can use an async handler, which is
+    // spawned on the server's runtime to avoid blocking the client's main event loop. An optional
+    // timeout fails calls that take too long. The handler receives an owned snapshot of the client,
+    // which can be moved into the future.
     server
         .add_services([
-            Service::builder("/sleep", empty_schema()).handler_fn(|_, _, resp| {
-                tokio::spawn(async move {
-                    tokio::time::sleep(Duration::from_secs(1)).await;
-                    resp.respond(Ok(Bytes::new()))
-                });
+            Service::builder("/sleep", empty_schema()).async_handler_fn(|client, _| async move {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                info!("woke up client {}", client.addr());
+                anyhow::Ok(Bytes::new())
             }),
+            Service::builder("/sleep_forever", empty_schema())
+                .timeout(Duration::from_secs(1))
+                .async_handler_fn(|_, _| async {
+                    std::future::pending::<()>().await;
+                    anyhow::Ok(Bytes::new())
+                }),
         ])
         .context("Failed to register services")?;
 
+    // Services that do heavy computation should use `tokio::task::spawn_blocking()`. Unlike the
+    // `SyncHandler` and `AsyncHandler` implementations, the generic `Handler` is responsible for
+    // invoking `resp.respond()` to complete the request. Concurrency limits can serialize calls,
+    // with a bounded queue for calls that arrive while another call is in progress.
+    server
+        .add_services([Service::builder("/blocking_sleep", empty_schema())
+            .max_concurrent_calls(1)
+            .max_queued_calls(4)
+            .handler_fn(|_, _, resp| {
+                tokio::task::spawn_blocking(move || {
+                    std::thread::sleep(Duration::from_secs(1));
+                    resp.respond(Ok(Bytes::new()))
+                });
+            })])
+        .context("Failed to register services")?;
+
     // A single handler function can be shared by multiple services.
     server
         .add_services(
@@ -58,6 +79,18 @@ pub async fn main(config: Config) -> Result<()> {
         )
         .context("Failed to register services")?;
 
+    // Typed services derive their schemas from the request and response types, and take care of
+    // decoding the request and encoding the response.
+    server
+        .add_services([Service::typed::<IntBinRequest, IntBinResponse>(
+            "/IntBin/pow",
+            |_, req| {
+                let result = req.a.checked_pow(u32::try_from(req.b)?).ok_or("overflow")?;
+                Ok(IntBinResponse { result })
+            },
+        )])
+        .context("Failed to register services")?;
+
     // A stateful handler might be written as a type that implements `Handler` (or `SyncHandler`).
     let flag_a = Flag::default();
     let flag_b = Flag::default();
@@ -128,7 +128,7 @@ fn generate_impls(out_dir: &Path, fds: &FileDescriptorSet) -> anyhow::Result<()>
         module,
         "use crate::schemas::{{descriptors, foxglove::*}};"
     ));
-    result = result.and(writeln!(module, "use crate::{{Schema, Encode}};"));
+    result = result.and(writeln!(module, "use crate::{{Schema, Encode, Decode}};"));
     result = result.and(writeln!(module, "use bytes::BufMut;"));
     result.context("Failed to write impls.rs")?;
 
@@ -169,6 +169,14 @@ fn generate_impls(out_dir: &Path, fds: &FileDescriptorSet) -> anyhow::Result<()>
     }}
 
     fn encoded_len(&self) -> Option<usize> {{ Some(::prost::Message::encoded_len(self)) }}
+}}
+
+impl Decode for {name} {{
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {{
+        ::prost::Message::decode(buf)
+    }}
 }}"
         )
         .context("Failed to write trait impl in impls.rs")?;
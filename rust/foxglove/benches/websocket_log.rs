@@ -0,0 +1,94 @@
+//! Measures the cost of logging a message to a websocket server with several subscribed clients.
+//!
+//! Run with `cargo bench -p foxglove --bench websocket_log`.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use foxglove::{Channel, ChannelBuilder, LogContext, Schema, WebSocketServer};
+use futures_util::{SinkExt, StreamExt};
+use tokio::runtime::Runtime;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Message;
+
+const CLIENT_COUNTS: [usize; 3] = [1, 4, 10];
+const PAYLOAD_SIZES: [usize; 2] = [1024, 2 * 1024 * 1024];
+
+/// Connects a client which subscribes to the channel, and then reads messages until the server
+/// stops.
+async fn subscribe_client(port: u16, channel: &Channel) {
+    let mut request = format!("ws://127.0.0.1:{port}/")
+        .into_client_request()
+        .unwrap();
+    request.headers_mut().insert(
+        "sec-websocket-protocol",
+        HeaderValue::from_static("foxglove.sdk.v1"),
+    );
+    let (mut client, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+
+    // Wait for the serverInfo and advertise messages.
+    for _ in 0..2 {
+        client.next().await.unwrap().unwrap();
+    }
+    let subscribe = format!(
+        r#"{{"op":"subscribe","subscriptions":[{{"id":1,"channelId":{}}}]}}"#,
+        u64::from(channel.id())
+    );
+    client.send(Message::text(subscribe)).await.unwrap();
+
+    tokio::spawn(async move { while let Some(Ok(_)) = client.next().await {} });
+}
+
+fn bench_log(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("websocket_log");
+
+    for clients in CLIENT_COUNTS {
+        let ctx = Arc::new(LogContext::new());
+        let channel = ChannelBuilder::new("/image")
+            .message_encoding("raw")
+            .schema(Schema::new("raw", "raw", b"".as_slice()))
+            .context(&ctx)
+            .build()
+            .unwrap();
+
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let server = runtime.block_on(async {
+            let server = WebSocketServer::new()
+                .bind("127.0.0.1", port)
+                .context(&ctx)
+                .start()
+                .await
+                .unwrap();
+            for _ in 0..clients {
+                subscribe_client(port, &channel).await;
+            }
+            // Allow the server to process the subscriptions.
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            server
+        });
+
+        for size in PAYLOAD_SIZES {
+            let payload = vec![0u8; size];
+            group.throughput(Throughput::Bytes(size as u64));
+            group.bench_with_input(
+                BenchmarkId::new(format!("{clients}_clients"), size),
+                &payload,
+                |b, payload| b.iter(|| channel.log(payload)),
+            );
+        }
+
+        runtime.block_on(server.stop());
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_log);
+criterion_main!(benches);
@@ -98,6 +98,8 @@ pub struct Channel {
     pub(crate) message_encoding: String,
     pub(crate) schema: Option<Schema>,
     pub(crate) metadata: BTreeMap<String, String>,
+    /// The number of recent messages replayed to new subscribers.
+    pub(crate) latched: usize,
 }
 
 impl Channel {
@@ -116,6 +118,13 @@ impl Channel {
         self.schema.as_ref()
     }
 
+    /// Returns the number of recent messages which are replayed to new subscribers.
+    ///
+    /// See [`ChannelBuilder::latched`](crate::ChannelBuilder::latched).
+    pub fn latched(&self) -> usize {
+        self.latched
+    }
+
     /// Atomically increments and returns the next message sequence number.
     pub fn next_sequence(&self) -> u32 {
         self.message_sequence.fetch_add(1, Relaxed)
@@ -213,6 +222,7 @@ mod test {
                 }"#,
             )),
             metadata: collection! {"key".to_string() => "value".to_string()},
+            latched: 0,
         })
     }
 
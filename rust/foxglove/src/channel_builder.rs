@@ -13,7 +13,8 @@ pub struct ChannelBuilder<'a> {
     message_encoding: Option<String>,
     schema: Option<Schema>,
     metadata: BTreeMap<String, String>,
-    context: Option<&'a LogContext>,
+    latched: usize,
+    context: Option<&'a Arc<LogContext>>,
 }
 
 impl<'a> ChannelBuilder<'a> {
@@ -24,6 +25,7 @@ impl<'a> ChannelBuilder<'a> {
             message_encoding: None,
             schema: None,
             metadata: BTreeMap::new(),
+            latched: 0,
             context: None,
         }
     }
@@ -58,14 +60,31 @@ impl<'a> ChannelBuilder<'a> {
         self
     }
 
-    #[doc(hidden)]
-    pub fn with_context(mut self, ctx: &'a LogContext) -> Self {
+    /// Latch the last `n` messages logged to the channel.
+    ///
+    /// When a client subscribes to a latched channel, the websocket server immediately sends it
+    /// the last `n` messages, with their original log times. This is useful for data which is
+    /// logged infrequently, such as maps or calibrations, so that clients which connect late
+    /// still receive it. By default, channels are not latched.
+    ///
+    /// Messages are latched by the server as they are logged, so messages logged before the
+    /// server is started are not replayed.
+    pub fn latched(mut self, n: usize) -> Self {
+        self.latched = n;
+        self
+    }
+
+    /// Set the log context for the channel.
+    ///
+    /// By default, channels are registered with the [global context](LogContext::global).
+    pub fn context(mut self, ctx: &'a Arc<LogContext>) -> Self {
         self.context = Some(ctx);
         self
     }
 
     /// Build the channel and return it in an [`Arc`] as a Result.
-    /// Returns FoxgloveError::DuplicateChannel if a channel with the same topic already exists.
+    /// Returns FoxgloveError::DuplicateChannel if a channel with the same topic already exists
+    /// in the log context.
     pub fn build(self) -> Result<Arc<Channel>, FoxgloveError> {
         static CHANNEL_ID: AtomicU64 = AtomicU64::new(1);
         let channel = Arc::new(Channel {
@@ -78,16 +97,19 @@ impl<'a> ChannelBuilder<'a> {
                 .ok_or_else(|| FoxgloveError::MessageEncodingRequired)?,
             schema: self.schema,
             metadata: self.metadata,
+            latched: self.latched,
         });
-        self.context
-            .unwrap_or_else(|| LogContext::global())
-            .add_channel(channel.clone())?;
+        match self.context {
+            Some(ctx) => ctx.add_channel(channel.clone())?,
+            None => LogContext::global().add_channel(channel.clone())?,
+        }
         Ok(channel)
     }
 
     /// Build the channel and return it as a [`TypedChannel`] as a Result.
     /// `T` must implement [`Encode`].
-    /// Returns FoxgloveError::DuplicateChannel if a channel with the same topic already exists.
+    /// Returns FoxgloveError::DuplicateChannel if a channel with the same topic already exists
+    /// in the log context.
     pub fn build_typed<T: Encode>(mut self) -> Result<TypedChannel<T>, FoxgloveError> {
         if self.message_encoding.is_none() {
             self.message_encoding = Some(<T as Encode>::get_message_encoding());
@@ -1,7 +1,7 @@
 use crate::{Channel, ChannelBuilder, FoxgloveError, PartialMetadata, Schema};
 use bytes::BufMut;
 use schemars::{gen::SchemaSettings, JsonSchema};
-use serde::Serialize;
+use serde::{de::DeserializeOwned, Serialize};
 use std::{borrow::Cow, sync::Arc};
 
 const STACK_BUFFER_SIZE: usize = 128 * 1024;
@@ -67,6 +67,30 @@ impl<T: Serialize + JsonSchema> Encode for T {
     }
 }
 
+/// A trait representing a message that can be decoded from message data.
+///
+/// This is the counterpart to [`Encode`], for messages which are received rather than logged,
+/// such as service call requests.
+pub trait Decode: Sized {
+    /// The error type returned by methods in this trait.
+    type Error: std::error::Error;
+
+    /// Decodes message data.
+    fn decode(buf: &[u8]) -> Result<Self, Self::Error>;
+}
+
+/// Automatically implements [`Decode`] for any type that implements
+/// [`DeserializeOwned`](serde::de::DeserializeOwned) and
+/// [`JsonSchema`](https://docs.rs/schemars/latest/schemars/trait.JsonSchema.html), to match the
+/// JSON encoding used by [`Encode`].
+impl<T: DeserializeOwned + JsonSchema> Decode for T {
+    type Error = serde_json::Error;
+
+    fn decode(buf: &[u8]) -> Result<Self, Self::Error> {
+        serde_json::from_slice(buf)
+    }
+}
+
 /// A typed [`Channel`] for messages that implement [`Encode`].
 ///
 /// Channels are immutable, returned as `Arc<Channel>` and can be shared between threads.
@@ -251,4 +275,32 @@ mod test {
         let json: Value = serde_json::from_slice(&schema.data).expect("failed to parse schema");
         assert_eq!(json["properties"]["foo"]["enum"], json!(["A"]));
     }
+
+    #[test]
+    fn test_json_decode() {
+        #[derive(Debug, PartialEq, serde::Deserialize, Serialize, JsonSchema)]
+        struct Foo {
+            x: u32,
+        }
+
+        let mut buf = Vec::new();
+        Foo { x: 1 }.encode(&mut buf).expect("failed to encode");
+        assert_eq!(Foo::decode(&buf).expect("failed to decode"), Foo { x: 1 });
+        assert!(Foo::decode(b"{}").is_err());
+    }
+
+    #[test]
+    fn test_protobuf_decode() {
+        use crate::schemas::Vector3;
+
+        let vec = Vector3 {
+            x: 1.0,
+            y: 2.0,
+            z: 3.0,
+        };
+        let mut buf = Vec::new();
+        vec.encode(&mut buf).expect("failed to encode");
+        assert_eq!(Vector3::decode(&buf).expect("failed to decode"), vec);
+        assert!(Vector3::decode(b"\xff").is_err());
+    }
 }
@@ -146,6 +146,35 @@
 //! # }
 //! ```
 //!
+//! ## Log contexts
+//!
+//! Channels and sinks are associated with each other through a [`LogContext`]. By default,
+//! everything is registered with the [global context](LogContext::global), so every sink
+//! receives messages from every channel.
+//!
+//! If you need several independent pipelines in the same process, you can create your own
+//! contexts. Each context has its own channel namespace and its own sinks:
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use foxglove::{ChannelBuilder, LogContext, McapWriter};
+//!
+//! # fn func() -> Result<(), foxglove::FoxgloveError> {
+//! let sim = Arc::new(LogContext::new());
+//! let mcap = McapWriter::new()
+//!     .context(&sim)
+//!     .create_new_buffered_file("sim.mcap")?;
+//!
+//! let channel = ChannelBuilder::new("/log")
+//!     .message_encoding("json")
+//!     .context(&sim)
+//!     .build()?;
+//! channel.log(br#"{"msg": "only in sim.mcap"}"#);
+//!
+//! mcap.close()?;
+//! # Ok(()) }
+//! ```
+//!
 //! # Requirements
 //!
 //! The Foxglove SDK depends on [tokio] as its async runtime with the `rt-multi-thread`
@@ -153,6 +182,11 @@
 //! your application to use tokio.
 //!
 //! [tokio]: https://docs.rs/tokio/latest/tokio/
+//!
+//! # Feature flags
+//!
+//! - `tls`: Enables TLS for the websocket server, with `WebSocketServer::tls` and
+//!   `WebSocketServer::tls_files`.
 
 #![warn(missing_docs)]
 
@@ -168,9 +202,11 @@ mod log_sink;
 mod log_sink_set;
 mod mcap_writer;
 mod metadata;
+pub mod playback;
 mod runtime;
 pub mod schemas;
 mod time;
+mod topic_filter;
 pub mod websocket;
 mod websocket_server;
 
@@ -181,15 +217,18 @@ mod testutil;
 
 pub use channel::{Channel, Schema};
 pub use channel_builder::ChannelBuilder;
-pub use encode::{Encode, TypedChannel};
-#[doc(hidden)]
+pub use encode::{Decode, Encode, TypedChannel};
 pub use log_context::LogContext;
 pub use log_sink::LogSink;
-pub use mcap_writer::{McapWriter, McapWriterHandle};
+pub use mcap_writer::{
+    mcap_recover, FlightRecorder, FlightRecorderHandle, McapRotatingWriterHandle,
+    McapStreamWriterHandle, McapWriter, McapWriterHandle, OverflowPolicy, RotationOptions,
+};
 pub use metadata::{Metadata, PartialMetadata};
 pub(crate) use runtime::get_runtime_handle;
 pub use runtime::shutdown_runtime;
 pub(crate) use time::nanoseconds_since_epoch;
+pub use topic_filter::{TopicFilter, TopicPattern};
 pub use websocket_server::{WebSocketServer, WebSocketServerBlockingHandle, WebSocketServerHandle};
 
 /// An error type for errors generated by this crate.
@@ -226,10 +265,31 @@ pub enum FoxgloveError {
     /// Services are not supported on this server instance.
     #[error("Services are not supported on this server instance")]
     ServicesNotSupported,
+    /// A parameter with the same name has already been declared.
+    #[error("Parameter {0} already exists in store")]
+    DuplicateParameter(String),
+    /// The parameter has not been declared.
+    #[error("Unknown parameter {0}")]
+    UnknownParameter(String),
+    /// The parameter value was rejected.
+    #[error("Invalid value for parameter {0}")]
+    InvalidParameter(String),
+    /// The connection graph is not supported on this server instance.
+    #[error("Connection graph is not supported on this server instance")]
+    ConnectionGraphNotSupported,
     /// An I/O error.
     #[error(transparent)]
     IoError(#[from] std::io::Error),
     /// An error related to MCAP encoding.
     #[error("MCAP error: {0}")]
     McapError(#[from] mcap::McapError),
+    /// A topic pattern could not be compiled.
+    #[error("Invalid topic pattern: {0}")]
+    InvalidTopicPattern(String),
+    /// The TLS certificate chain or private key could not be loaded.
+    #[error("Invalid TLS configuration: {0}")]
+    TlsConfig(String),
+    /// The playback speed factor is not positive and finite.
+    #[error("Invalid playback speed: {0}")]
+    InvalidPlaybackSpeed(f64),
 }
@@ -5,7 +5,22 @@ use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::sync::{Arc, OnceLock};
 
-/// A thread-safe wrapper around one or more Sinks, that writes to all of them.
+/// A collection of channels and sinks.
+///
+/// Each log context has its own channel namespace and its own set of sinks. Channels created in a
+/// context are only delivered to the sinks registered with that same context. Most applications
+/// only need the [global context](LogContext::global), which is used by default by
+/// [`ChannelBuilder`](crate::ChannelBuilder), [`McapWriter`](crate::McapWriter) and
+/// [`WebSocketServer`](crate::WebSocketServer).
+///
+/// If you need to run several independent pipelines in the same process, you can create
+/// additional contexts with [`LogContext::new`], and pass them to
+/// [`ChannelBuilder::context`](crate::ChannelBuilder::context),
+/// [`McapWriter::context`](crate::McapWriter::context), and
+/// [`WebSocketServer::context`](crate::WebSocketServer::context).
+///
+/// When a log context is dropped or [cleared](LogContext::clear), all of its channels are
+/// unregistered from all of its sinks.
 pub struct LogContext {
     // Map of channels by topic.
     channels: RwLock<HashMap<String, Arc<Channel>>>,
@@ -25,8 +40,13 @@ impl LogContext {
     ///
     /// If there is no global log context, this function instantiates one.
     pub fn global() -> &'static LogContext {
-        static DEFAULT_CONTEXT: OnceLock<LogContext> = OnceLock::new();
-        DEFAULT_CONTEXT.get_or_init(LogContext::new)
+        Self::global_arc()
+    }
+
+    /// Returns the global log context, for sinks which need to hold a reference to it.
+    pub(crate) fn global_arc() -> &'static Arc<LogContext> {
+        static DEFAULT_CONTEXT: OnceLock<Arc<LogContext>> = OnceLock::new();
+        DEFAULT_CONTEXT.get_or_init(|| Arc::new(LogContext::new()))
     }
 
     /// Returns the channel for the specified topic, if there is one.
@@ -36,6 +56,9 @@ impl LogContext {
     }
 
     /// Adds a channel to the log context.
+    ///
+    /// Returns [`FoxgloveError::DuplicateChannel`] if a channel with the same topic is already
+    /// registered with this context.
     pub fn add_channel(&self, channel: Arc<Channel>) -> Result<(), FoxgloveError> {
         {
             // Wrapped in a block, so we release the lock immediately.
@@ -47,7 +70,7 @@ impl LogContext {
             entry.insert(channel.clone());
         }
         self.sinks.for_each(|sink| {
-            if channel.sinks.add_sink(sink.clone()) {
+            if sink.accepts_channel(&channel) && channel.sinks.add_sink(sink.clone()) {
                 sink.add_channel(&channel);
             }
             Ok(())
@@ -83,9 +106,9 @@ impl LogContext {
             return false;
         }
 
-        // Add the sink to all existing channels.
+        // Add the sink to all existing channels that it accepts.
         for channel in self.channels.read().values() {
-            if channel.sinks.add_sink(sink.clone()) {
+            if sink.accepts_channel(channel) && channel.sinks.add_sink(sink.clone()) {
                 sink.add_channel(channel);
             }
         }
@@ -120,6 +143,13 @@ impl LogContext {
     }
 
     /// Removes all channels and sinks from the log context.
+    ///
+    /// Sinks are notified that each channel has been removed, but are otherwise left running. To
+    /// shut down a sink cleanly, use its handle, e.g. [`McapWriterHandle::close`] or
+    /// [`WebSocketServerHandle::stop`].
+    ///
+    /// [`McapWriterHandle::close`]: crate::McapWriterHandle::close
+    /// [`WebSocketServerHandle::stop`]: crate::WebSocketServerHandle::stop
     pub fn clear(&self) {
         let channels: HashMap<_, _> = std::mem::take(&mut self.channels.write());
         self.sinks.for_each(|sink| {
@@ -139,6 +169,14 @@ impl Drop for LogContext {
     }
 }
 
+impl std::fmt::Debug for LogContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LogContext")
+            .field("channels", &self.channels.read().keys())
+            .finish_non_exhaustive()
+    }
+}
+
 impl Default for LogContext {
     fn default() -> Self {
         Self::new()
@@ -176,6 +214,7 @@ mod tests {
                 }"#,
             )),
             metadata: collection! {"key".to_string() => "value".to_string()},
+            latched: 0,
         })
     }
 
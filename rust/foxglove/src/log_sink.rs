@@ -17,6 +17,13 @@ pub trait LogSink: Send + Sync {
         metadata: &Metadata,
     ) -> Result<(), FoxgloveError>;
 
+    /// accepts_channel is called before a channel is associated with this Sink.
+    /// If it returns false, the channel is never added to the sink, and the sink will not receive
+    /// any messages logged to it. The default implementation accepts all channels.
+    fn accepts_channel(&self, _channel: &Channel) -> bool {
+        true
+    }
+
     /// add_channel is called when a new channel is associated with this Sink.
     /// Sinks can track channels seen, and do new channel related things the first
     /// time they see a channel, rather than in this method. The choice is up to the implementor.
@@ -1,25 +1,48 @@
 //! MCAP writer
 
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{BufWriter, Seek};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use std::{fmt::Debug, io::Write};
 
-use crate::{FoxgloveError, LogContext, LogSink};
+use crate::{FoxgloveError, LogContext, LogSink, TopicFilter};
+use mcap::write::NoSeek;
 use mcap::WriteOptions;
 
+mod background;
+mod flight_recorder;
 mod mcap_sink;
-use mcap_sink::McapSink;
+mod recover;
+mod rotating_sink;
+pub use background::OverflowPolicy;
+pub use flight_recorder::{FlightRecorder, FlightRecorderHandle};
+use mcap_sink::{FlushPolicy, McapSink};
+pub use recover::mcap_recover;
+use rotating_sink::{RotatingMcapSink, INDEX_PLACEHOLDER, TIMESTAMP_PLACEHOLDER};
 
 /// An MCAP writer for logging events.
 #[must_use]
 #[derive(Debug, Clone)]
-pub struct McapWriter(WriteOptions);
+pub struct McapWriter {
+    options: WriteOptions,
+    context: Arc<LogContext>,
+    topic_filter: Option<TopicFilter>,
+    flush_policy: FlushPolicy,
+    background: Option<(usize, OverflowPolicy)>,
+}
 
 impl From<WriteOptions> for McapWriter {
     fn from(value: WriteOptions) -> Self {
-        Self(value.library(format!("foxglove-sdk-rs-{}", env!("CARGO_PKG_VERSION"))))
+        Self {
+            options: value.library(format!("foxglove-sdk-rs-{}", env!("CARGO_PKG_VERSION"))),
+            context: LogContext::global_arc().clone(),
+            topic_filter: None,
+            flush_policy: FlushPolicy::default(),
+            background: None,
+        }
     }
 }
 
@@ -40,6 +63,74 @@ impl McapWriter {
         options.into()
     }
 
+    /// Sets the log context to record.
+    ///
+    /// By default, the writer records channels from the [global context](LogContext::global).
+    pub fn context(mut self, ctx: &Arc<LogContext>) -> Self {
+        self.context = ctx.clone();
+        self
+    }
+
+    /// Sets a filter to select the channels to record.
+    ///
+    /// By default, the writer records all channels in its log context.
+    pub fn topic_filter(mut self, filter: TopicFilter) -> Self {
+        self.topic_filter = Some(filter);
+        self
+    }
+
+    /// Closes the current chunk and flushes it to the writer at least this often.
+    ///
+    /// If the process exits without closing the writer, the resulting file has no summary
+    /// section, and any messages in an unfinished chunk are lost. Flushing periodically bounds
+    /// this loss, and the remaining data can be salvaged with [`mcap_recover`].
+    ///
+    /// The interval is checked on a timer, so buffered data is flushed even while nothing is
+    /// being logged. Smaller chunks compress less efficiently, so this should not be set lower
+    /// than necessary.
+    pub fn flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_policy.interval = Some(interval);
+        self
+    }
+
+    /// Closes the current chunk and flushes it to the writer after this many messages.
+    ///
+    /// See [`McapWriter::flush_interval`].
+    pub fn flush_after_messages(mut self, messages: u64) -> Self {
+        self.flush_policy.messages = Some(messages);
+        self
+    }
+
+    /// Syncs file data to disk with [`File::sync_data`] after each flush.
+    ///
+    /// Flushing only hands data to the operating system, which may lose it if the machine
+    /// crashes or loses power. Syncing guards against this, at the cost of blocking on disk I/O.
+    /// The default is false.
+    ///
+    /// This option only applies to files created by [`McapWriter::create_new_buffered_file`] and
+    /// [`McapWriter::create_rotating_files`]. Writers passed to [`McapWriter::create`] must be
+    /// synced by the caller.
+    pub fn sync_after_flush(mut self, sync: bool) -> Self {
+        self.flush_policy.sync = sync;
+        self
+    }
+
+    /// Writes the recording on a dedicated background thread.
+    ///
+    /// By default, messages are compressed and written on the thread that logs them. With a
+    /// background writer, logged messages are instead copied into a queue which holds up to
+    /// `queue_size` messages, so that compression and I/O do not block the logging thread.
+    ///
+    /// The `overflow_policy` determines what happens when a message is logged while the queue is
+    /// full. The number of dropped messages is reported by
+    /// [`McapWriterHandle::dropped_messages`].
+    ///
+    /// This option does not apply to [`McapWriter::create_rotating_files`].
+    pub fn background_writer(mut self, queue_size: usize, overflow_policy: OverflowPolicy) -> Self {
+        self.background = Some((queue_size, overflow_policy));
+        self
+    }
+
     /// Begins logging events to the specified writer.
     ///
     /// Returns a handle. When the handle is dropped, the recording will be flushed to the writer
@@ -49,9 +140,76 @@ impl McapWriter {
     where
         W: Write + Seek + Send + 'static,
     {
-        let writer = McapSink::new(writer, self.0)?;
-        LogContext::global().add_sink(writer.clone());
-        Ok(McapWriterHandle(writer))
+        self.create_with_sync_file(writer, None)
+    }
+
+    /// Begins logging events to the specified writer, syncing `sync_file` to disk after each
+    /// flush.
+    fn create_with_sync_file<W>(
+        self,
+        writer: W,
+        sync_file: Option<File>,
+    ) -> Result<McapWriterHandle<W>, FoxgloveError>
+    where
+        W: Write + Seek + Send + 'static,
+    {
+        let sink = match self.background {
+            Some((queue_size, overflow_policy)) => McapSink::new_background(
+                writer,
+                self.options,
+                self.flush_policy,
+                sync_file,
+                queue_size,
+                overflow_policy,
+                self.topic_filter,
+            )?,
+            None => {
+                let sink = McapSink::new(
+                    writer,
+                    self.options,
+                    self.flush_policy,
+                    sync_file,
+                    self.topic_filter,
+                )?;
+                sink.start_flush_timer();
+                sink
+            }
+        };
+        self.context.add_sink(sink.clone());
+        Ok(McapWriterHandle {
+            sink,
+            context: self.context,
+        })
+    }
+
+    /// Begins logging events to a writer which does not support seeking, such as a pipe, socket,
+    /// or stdout.
+    ///
+    /// Chunks are buffered in memory until they are complete, and the summary section is written
+    /// without seeking back to patch earlier records, as permitted by the MCAP specification for
+    /// streamed files.
+    ///
+    /// Returns a handle. When the handle is dropped, the recording will be flushed to the writer
+    /// and closed. Alternatively, the caller may choose to call [`McapStreamWriterHandle::close`]
+    /// to manually flush the recording and recover the writer.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use foxglove::McapWriter;
+    ///
+    /// # fn func() -> Result<(), foxglove::FoxgloveError> {
+    /// let mcap = McapWriter::new().create_stream(std::io::stdout())?;
+    /// // ...
+    /// mcap.close()?;
+    /// # Ok(()) }
+    /// ```
+    pub fn create_stream<W>(mut self, writer: W) -> Result<McapStreamWriterHandle<W>, FoxgloveError>
+    where
+        W: Write + Send + 'static,
+    {
+        self.options = self.options.disable_seeking(true);
+        let inner = self.create(NoSeek::new(writer))?;
+        Ok(McapStreamWriterHandle { inner })
     }
 
     /// Creates a new write-only buffered file, and begins logging events to it.
@@ -69,8 +227,109 @@ impl McapWriter {
         P: AsRef<Path>,
     {
         let file = File::create_new(path)?;
-        let writer = BufWriter::new(file);
-        self.create(writer)
+        let sync_file = self
+            .flush_policy
+            .sync
+            .then(|| file.try_clone())
+            .transpose()?;
+        self.create_with_sync_file(BufWriter::new(file), sync_file)
+    }
+
+    /// Begins logging events to a series of new write-only buffered files.
+    ///
+    /// A new file is started whenever the current file reaches one of the limits in `rotation`.
+    /// Each file is a complete MCAP recording which includes the schemas and channels for all
+    /// channels being logged, so that it can be read on its own.
+    ///
+    /// File paths are derived from `template`, which must contain at least one of the following
+    /// placeholders:
+    ///
+    /// - `{index}`: the index of the file in the series, starting from 0.
+    /// - `{timestamp}`: the time the file was created, in nanoseconds since the unix epoch.
+    ///
+    /// If a file already exists, this call (or the rotation) will fail with
+    /// [`AlreadyExists`](`std::io::ErrorKind::AlreadyExists`).
+    ///
+    /// # Example
+    /// ```no_run
+    /// use foxglove::{McapWriter, RotationOptions};
+    /// use std::time::Duration;
+    ///
+    /// # fn func() -> Result<(), foxglove::FoxgloveError> {
+    /// let handle = McapWriter::new().create_rotating_files(
+    ///     "recording-{index}.mcap",
+    ///     RotationOptions::new()
+    ///         .max_bytes(512 * 1024 * 1024)
+    ///         .max_duration(Duration::from_secs(600)),
+    /// )?;
+    /// // ...
+    /// let paths = handle.close()?;
+    /// # Ok(()) }
+    /// ```
+    pub fn create_rotating_files(
+        self,
+        template: impl Into<String>,
+        rotation: RotationOptions,
+    ) -> Result<McapRotatingWriterHandle, FoxgloveError> {
+        let template = template.into();
+        if !template.contains(INDEX_PLACEHOLDER) && !template.contains(TIMESTAMP_PLACEHOLDER) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "path template must contain {INDEX_PLACEHOLDER} or {TIMESTAMP_PLACEHOLDER}: {template}"
+                ),
+            )
+            .into());
+        }
+        let sink = RotatingMcapSink::new(
+            template,
+            self.options,
+            self.flush_policy,
+            rotation,
+            self.topic_filter,
+        )?;
+        self.context.add_sink(sink.clone());
+        Ok(McapRotatingWriterHandle {
+            sink,
+            context: self.context,
+        })
+    }
+}
+
+/// Limits which determine when a rotating MCAP writer starts a new file.
+///
+/// See [`McapWriter::create_rotating_files`]. If no limits are set, all events are written to a
+/// single file.
+#[must_use]
+#[derive(Debug, Clone, Default)]
+pub struct RotationOptions {
+    max_bytes: Option<u64>,
+    max_duration: Option<Duration>,
+}
+
+impl RotationOptions {
+    /// Creates new rotation options with no limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a new file once the current file reaches the specified size.
+    ///
+    /// The size is checked before each message is written, so files may slightly exceed this
+    /// limit. Data buffered in the current chunk is not counted until the chunk is written. Each
+    /// file contains at least one message, even if the limit is smaller than the file's header.
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Starts a new file once the current file has been open for the specified duration.
+    ///
+    /// The duration is checked before each message is written, so files are not rotated while no
+    /// messages are being logged.
+    pub fn max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = Some(max_duration);
+        self
     }
 }
 
@@ -79,7 +338,10 @@ impl McapWriter {
 /// When this handle is dropped, the writer will stop logging events, and flush any buffered data
 /// to the writer.
 #[must_use]
-pub struct McapWriterHandle<W: Write + Seek + Send + 'static>(Arc<McapSink<W>>);
+pub struct McapWriterHandle<W: Write + Seek + Send + 'static> {
+    sink: Arc<McapSink<W>>,
+    context: Arc<LogContext>,
+}
 
 impl<W: Write + Seek + Send + 'static> Debug for McapWriterHandle<W> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -88,6 +350,42 @@ impl<W: Write + Seek + Send + 'static> Debug for McapWriterHandle<W> {
 }
 
 impl<W: Write + Seek + Send + 'static> McapWriterHandle<W> {
+    /// Writes a metadata record to the recording.
+    ///
+    /// Metadata records hold arbitrary key-value pairs, such as build information or operator
+    /// notes.
+    pub fn write_metadata(
+        &self,
+        name: impl Into<String>,
+        metadata: BTreeMap<String, String>,
+    ) -> Result<(), FoxgloveError> {
+        self.sink.write_metadata(name.into(), metadata)
+    }
+
+    /// Writes an attachment record to the recording.
+    ///
+    /// Attachments hold arbitrary files, such as calibration files or robot descriptions. The
+    /// `media_type` describes the content of the attachment (e.g. `"text/plain"`), and `log_time`
+    /// is the time at which the attachment was recorded, in nanoseconds since the unix epoch.
+    pub fn attach(
+        &self,
+        name: impl Into<String>,
+        media_type: impl Into<String>,
+        log_time: u64,
+        data: &[u8],
+    ) -> Result<(), FoxgloveError> {
+        self.sink
+            .attach(name.into(), media_type.into(), log_time, data)
+    }
+
+    /// Returns the number of messages that were dropped because the background writer's queue
+    /// was full.
+    ///
+    /// This is always zero unless [`McapWriter::background_writer`] is used.
+    pub fn dropped_messages(&self) -> u64 {
+        self.sink.dropped_messages()
+    }
+
     /// Stops logging events, flushes buffered data, and returns the writer.
     pub fn close(self) -> Result<W, FoxgloveError> {
         // It's safe to unwrap the `Option<W>` because `McapWriterHandle` doesn't implement clone,
@@ -96,9 +394,9 @@ impl<W: Write + Seek + Send + 'static> McapWriterHandle<W> {
     }
 
     fn finish(&self) -> Result<Option<W>, FoxgloveError> {
-        let sink = self.0.clone() as Arc<dyn LogSink>;
-        LogContext::global().remove_sink(&sink);
-        self.0.finish()
+        let sink = self.sink.clone() as Arc<dyn LogSink>;
+        self.context.remove_sink(&sink);
+        self.sink.finish()
     }
 }
 
@@ -109,3 +407,121 @@ impl<W: Write + Seek + Send + 'static> Drop for McapWriterHandle<W> {
         }
     }
 }
+
+/// A handle to an MCAP writer which does not support seeking.
+///
+/// When this handle is dropped, the writer will stop logging events, and flush any buffered data
+/// to the writer.
+#[must_use]
+pub struct McapStreamWriterHandle<W: Write + Send + 'static> {
+    inner: McapWriterHandle<NoSeek<W>>,
+}
+
+impl<W: Write + Send + 'static> Debug for McapStreamWriterHandle<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("McapStreamWriterHandle").finish()
+    }
+}
+
+impl<W: Write + Send + 'static> McapStreamWriterHandle<W> {
+    /// Writes a metadata record to the recording.
+    ///
+    /// See [`McapWriterHandle::write_metadata`].
+    pub fn write_metadata(
+        &self,
+        name: impl Into<String>,
+        metadata: BTreeMap<String, String>,
+    ) -> Result<(), FoxgloveError> {
+        self.inner.write_metadata(name, metadata)
+    }
+
+    /// Writes an attachment record to the recording.
+    ///
+    /// See [`McapWriterHandle::attach`].
+    pub fn attach(
+        &self,
+        name: impl Into<String>,
+        media_type: impl Into<String>,
+        log_time: u64,
+        data: &[u8],
+    ) -> Result<(), FoxgloveError> {
+        self.inner.attach(name, media_type, log_time, data)
+    }
+
+    /// Returns the number of messages that were dropped because the background writer's queue
+    /// was full.
+    ///
+    /// See [`McapWriterHandle::dropped_messages`].
+    pub fn dropped_messages(&self) -> u64 {
+        self.inner.dropped_messages()
+    }
+
+    /// Stops logging events, flushes buffered data, and returns the writer.
+    pub fn close(self) -> Result<W, FoxgloveError> {
+        self.inner.close().map(NoSeek::into_inner)
+    }
+}
+
+/// A handle to a rotating MCAP file writer.
+///
+/// When this handle is dropped, the writer will stop logging events, and flush any buffered data
+/// to the current file.
+#[must_use]
+pub struct McapRotatingWriterHandle {
+    sink: Arc<RotatingMcapSink>,
+    context: Arc<LogContext>,
+}
+
+impl Debug for McapRotatingWriterHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("McapRotatingWriterHandle").finish()
+    }
+}
+
+impl McapRotatingWriterHandle {
+    /// Writes a metadata record to the current file.
+    ///
+    /// See [`McapWriterHandle::write_metadata`].
+    pub fn write_metadata(
+        &self,
+        name: impl Into<String>,
+        metadata: BTreeMap<String, String>,
+    ) -> Result<(), FoxgloveError> {
+        self.sink.write_metadata(name.into(), metadata)
+    }
+
+    /// Writes an attachment record to the current file.
+    ///
+    /// See [`McapWriterHandle::attach`].
+    pub fn attach(
+        &self,
+        name: impl Into<String>,
+        media_type: impl Into<String>,
+        log_time: u64,
+        data: &[u8],
+    ) -> Result<(), FoxgloveError> {
+        self.sink
+            .attach(name.into(), media_type.into(), log_time, data)
+    }
+
+    /// Stops logging events, flushes buffered data, and returns the paths of all files written.
+    pub fn close(self) -> Result<Vec<PathBuf>, FoxgloveError> {
+        // It's safe to unwrap the `Option` because `McapRotatingWriterHandle` doesn't implement
+        // clone, and this method consumes self.
+        self.finish().map(|p| p.expect("not finished"))
+    }
+
+    fn finish(&self) -> Result<Option<Vec<PathBuf>>, FoxgloveError> {
+        let sink = self.sink.clone() as Arc<dyn LogSink>;
+        self.context.remove_sink(&sink);
+        self.sink.finish()
+    }
+}
+
+impl Drop for McapRotatingWriterHandle {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            tracing::warn!("{e}");
+        }
+    }
+}
@@ -0,0 +1,529 @@
+//! Background thread for writing MCAP records.
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{Seek, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+use parking_lot::{Condvar, Mutex};
+
+use super::mcap_sink::WriterState;
+use crate::{Channel, FoxgloveError, Metadata};
+
+/// Determines what happens when a message is logged while the background writer's queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// The logging thread blocks until there is room in the queue.
+    #[default]
+    Block,
+    /// The new message is dropped.
+    DropNewest,
+    /// The oldest queued message is dropped to make room for the new message.
+    DropOldest,
+}
+
+enum Command {
+    Message {
+        channel: Arc<Channel>,
+        data: Vec<u8>,
+        metadata: Metadata,
+    },
+    Metadata {
+        name: String,
+        metadata: BTreeMap<String, String>,
+    },
+    Attachment {
+        name: String,
+        media_type: String,
+        log_time: u64,
+        data: Vec<u8>,
+    },
+}
+
+/// The result of waiting for the next command.
+enum Next {
+    Command(Command),
+    /// The deadline elapsed before a command was queued.
+    Timeout,
+    /// The queue is closed and empty.
+    Closed,
+}
+
+/// Queued commands, in the order in which they were pushed.
+///
+/// Only messages count toward the capacity, and only messages are dropped on overflow, so they
+/// are queued separately from other records. Each command is tagged with a sequence number, which
+/// preserves the order between the two queues.
+#[derive(Default)]
+struct Queue {
+    messages: VecDeque<(u64, Command)>,
+    records: VecDeque<(u64, Command)>,
+    next_seq: u64,
+    closed: bool,
+}
+
+impl Queue {
+    fn push(&mut self, command: Command) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        if matches!(command, Command::Message { .. }) {
+            self.messages.push_back((seq, command));
+        } else {
+            self.records.push_back((seq, command));
+        }
+    }
+
+    /// Removes the command which was pushed first.
+    fn pop_front(&mut self) -> Option<Command> {
+        let queue = match (self.messages.front(), self.records.front()) {
+            (Some((m, _)), Some((r, _))) if r < m => &mut self.records,
+            (Some(_), _) => &mut self.messages,
+            (None, _) => &mut self.records,
+        };
+        queue.pop_front().map(|(_, command)| command)
+    }
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+    overflow_policy: OverflowPolicy,
+    dropped: AtomicU64,
+}
+
+impl Shared {
+    fn push(&self, command: Command) -> Result<(), FoxgloveError> {
+        let mut queue = self.queue.lock();
+        if queue.closed {
+            return Err(FoxgloveError::SinkClosed);
+        }
+        if matches!(command, Command::Message { .. }) {
+            while queue.messages.len() >= self.capacity {
+                match self.overflow_policy {
+                    OverflowPolicy::Block => {
+                        self.not_full.wait(&mut queue);
+                        if queue.closed {
+                            return Err(FoxgloveError::SinkClosed);
+                        }
+                    }
+                    OverflowPolicy::DropNewest => {
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                        return Ok(());
+                    }
+                    OverflowPolicy::DropOldest => {
+                        queue.messages.pop_front();
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+        queue.push(command);
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Waits for the next command, until the deadline if there is one.
+    fn pop(&self, deadline: Option<Instant>) -> Next {
+        let mut queue = self.queue.lock();
+        loop {
+            if let Some(command) = queue.pop_front() {
+                if matches!(command, Command::Message { .. }) {
+                    self.not_full.notify_one();
+                }
+                return Next::Command(command);
+            }
+            if queue.closed {
+                return Next::Closed;
+            }
+            match deadline {
+                Some(deadline) => {
+                    if self.not_empty.wait_until(&mut queue, deadline).timed_out() {
+                        return Next::Timeout;
+                    }
+                }
+                None => self.not_empty.wait(&mut queue),
+            }
+        }
+    }
+
+    fn close(&self) {
+        self.queue.lock().closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+}
+
+/// Writes MCAP records on a dedicated thread, so that compression and I/O do not block the
+/// logging thread.
+pub(super) struct BackgroundWriter<W> {
+    shared: Arc<Shared>,
+    thread: Mutex<Option<JoinHandle<Result<W, FoxgloveError>>>>,
+}
+
+impl<W: Write + Seek + Send + 'static> BackgroundWriter<W> {
+    pub(super) fn new(
+        state: WriterState<W>,
+        capacity: usize,
+        overflow_policy: OverflowPolicy,
+    ) -> Self {
+        let shared = Arc::new(Shared {
+            queue: Mutex::default(),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity: capacity.max(1),
+            overflow_policy,
+            dropped: AtomicU64::new(0),
+        });
+        let thread = std::thread::Builder::new()
+            .name("foxglove-mcap-writer".to_string())
+            .spawn({
+                let shared = shared.clone();
+                move || run(state, &shared)
+            })
+            .expect("Failed to spawn MCAP writer thread");
+        Self {
+            shared,
+            thread: Mutex::new(Some(thread)),
+        }
+    }
+}
+
+impl<W> BackgroundWriter<W> {
+    pub(super) fn log(
+        &self,
+        channel: &Arc<Channel>,
+        msg: &[u8],
+        metadata: &Metadata,
+    ) -> Result<(), FoxgloveError> {
+        self.shared.push(Command::Message {
+            channel: channel.clone(),
+            data: msg.to_vec(),
+            metadata: *metadata,
+        })
+    }
+
+    pub(super) fn write_metadata(
+        &self,
+        name: String,
+        metadata: BTreeMap<String, String>,
+    ) -> Result<(), FoxgloveError> {
+        self.shared.push(Command::Metadata { name, metadata })
+    }
+
+    pub(super) fn attach(
+        &self,
+        name: String,
+        media_type: String,
+        log_time: u64,
+        data: &[u8],
+    ) -> Result<(), FoxgloveError> {
+        self.shared.push(Command::Attachment {
+            name,
+            media_type,
+            log_time,
+            data: data.to_vec(),
+        })
+    }
+
+    /// Returns the number of messages dropped because the queue was full.
+    pub(super) fn dropped_messages(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Writes all queued records, finalizes the recording, and returns the writer.
+    pub(super) fn finish(&self) -> Result<Option<W>, FoxgloveError> {
+        let Some(thread) = self.thread.lock().take() else {
+            return Ok(None);
+        };
+        self.shared.close();
+        match thread.join() {
+            Ok(result) => result.map(Some),
+            Err(_) => Err(FoxgloveError::Unspecified(
+                "MCAP writer thread panicked".into(),
+            )),
+        }
+    }
+}
+
+impl<W> Drop for BackgroundWriter<W> {
+    fn drop(&mut self) {
+        // Let the writer thread finish the recording and exit, if it hasn't already.
+        self.shared.close();
+    }
+}
+
+fn run<W: Write + Seek>(mut state: WriterState<W>, shared: &Shared) -> Result<W, FoxgloveError> {
+    if let Err(e) = write_queued(&mut state, shared) {
+        // Stop accepting new records, since the recording can no longer be written.
+        tracing::error!("Failed to write MCAP record: {e}");
+        shared.close();
+        return Err(e);
+    }
+    state.finish()
+}
+
+/// Writes queued records until the queue is closed, and flushes the writer whenever the flush
+/// interval elapses.
+fn write_queued<W: Write + Seek>(
+    state: &mut WriterState<W>,
+    shared: &Shared,
+) -> Result<(), FoxgloveError> {
+    loop {
+        let command = match shared.pop(state.flush_deadline()) {
+            Next::Command(command) => command,
+            Next::Timeout => {
+                state.maybe_flush()?;
+                continue;
+            }
+            Next::Closed => return Ok(()),
+        };
+        match command {
+            Command::Message {
+                channel,
+                data,
+                metadata,
+            } => state.log(&channel, &data, &metadata)?,
+            Command::Metadata { name, metadata } => state.write_metadata(name, metadata)?,
+            Command::Attachment {
+                name,
+                media_type,
+                log_time,
+                data,
+            } => state.attach(name, media_type, log_time, &data)?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcap_writer::mcap_sink::FlushPolicy;
+    use crate::{ChannelBuilder, LogContext, McapWriter};
+    use mcap::WriteOptions;
+    use std::io::Cursor;
+    use std::sync::atomic::AtomicBool;
+    use std::time::{Duration, Instant};
+
+    /// A writer which blocks while its gate is closed.
+    struct GatedWriter {
+        inner: Cursor<Vec<u8>>,
+        gate: Arc<(Mutex<bool>, Condvar)>,
+        // Set once a write has blocked on the gate.
+        blocked: Arc<AtomicBool>,
+    }
+
+    impl Write for GatedWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let (open, cond) = &*self.gate;
+            let mut open = open.lock();
+            while !*open {
+                self.blocked.store(true, Ordering::Relaxed);
+                cond.wait(&mut open);
+            }
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    impl Seek for GatedWriter {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    fn set_gate(gate: &(Mutex<bool>, Condvar), open: bool) {
+        *gate.0.lock() = open;
+        gate.1.notify_all();
+    }
+
+    fn new_writer(
+        capacity: usize,
+        overflow_policy: OverflowPolicy,
+    ) -> (BackgroundWriter<GatedWriter>, Arc<(Mutex<bool>, Condvar)>) {
+        let gate = Arc::new((Mutex::new(true), Condvar::new()));
+        let writer = GatedWriter {
+            inner: Cursor::new(Vec::new()),
+            gate: gate.clone(),
+            blocked: Arc::default(),
+        };
+        // Write messages directly to the writer, rather than buffering them in chunks.
+        let mcap_writer = WriteOptions::new()
+            .use_chunks(false)
+            .create(writer)
+            .unwrap();
+        let state = WriterState::new(mcap_writer, FlushPolicy::default());
+        (
+            BackgroundWriter::new(state, capacity, overflow_policy),
+            gate,
+        )
+    }
+
+    /// Waits until the writer thread has dequeued all commands.
+    fn wait_for_empty_queue<W>(writer: &BackgroundWriter<W>) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let queue = writer.shared.queue.lock();
+            if queue.messages.is_empty() && queue.records.is_empty() {
+                break;
+            }
+            drop(queue);
+            assert!(Instant::now() < deadline, "timed out");
+            std::thread::yield_now();
+        }
+    }
+
+    fn read_messages(buf: &[u8]) -> Vec<String> {
+        mcap::MessageStream::new(buf)
+            .expect("failed to open stream")
+            .map(|msg| String::from_utf8(msg.unwrap().data.to_vec()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_writes_in_order() {
+        let ctx = Arc::new(LogContext::new());
+        let channel = ChannelBuilder::new("/foo")
+            .message_encoding("json")
+            .context(&ctx)
+            .build()
+            .unwrap();
+        let (writer, _gate) = new_writer(2, OverflowPolicy::Block);
+        for i in 0..10 {
+            writer
+                .log(&channel, i.to_string().as_bytes(), &Metadata::default())
+                .unwrap();
+        }
+        writer
+            .write_metadata("info".to_string(), BTreeMap::new())
+            .unwrap();
+        let buf = writer.finish().unwrap().unwrap().inner.into_inner();
+        assert_eq!(
+            read_messages(&buf),
+            (0..10).map(|i| i.to_string()).collect::<Vec<_>>()
+        );
+        let summary = mcap::Summary::read(&buf).unwrap().unwrap();
+        assert_eq!(summary.metadata_indexes.len(), 1);
+        assert_eq!(writer.dropped_messages(), 0);
+
+        // The writer is closed.
+        assert!(writer.finish().unwrap().is_none());
+        assert!(writer.log(&channel, b"", &Metadata::default()).is_err());
+    }
+
+    #[test]
+    fn test_drop_policies() {
+        let ctx = Arc::new(LogContext::new());
+        let channel = ChannelBuilder::new("/foo")
+            .message_encoding("json")
+            .context(&ctx)
+            .build()
+            .unwrap();
+        for (policy, expected) in [
+            (OverflowPolicy::DropNewest, ["0", "1", "2"]),
+            (OverflowPolicy::DropOldest, ["0", "3", "4"]),
+        ] {
+            let (writer, gate) = new_writer(2, policy);
+            set_gate(&gate, false);
+
+            // The first message is dequeued, and blocks the writer thread.
+            writer.log(&channel, b"0", &Metadata::default()).unwrap();
+            wait_for_empty_queue(&writer);
+
+            for msg in ["1", "2", "3", "4"] {
+                writer
+                    .log(&channel, msg.as_bytes(), &Metadata::default())
+                    .unwrap();
+            }
+            assert_eq!(writer.dropped_messages(), 2);
+
+            set_gate(&gate, true);
+            let buf = writer.finish().unwrap().unwrap().inner.into_inner();
+            assert_eq!(read_messages(&buf), expected, "{policy:?}");
+        }
+    }
+
+    #[test]
+    fn test_block_policy() {
+        let ctx = Arc::new(LogContext::new());
+        let channel = ChannelBuilder::new("/foo")
+            .message_encoding("json")
+            .context(&ctx)
+            .build()
+            .unwrap();
+        let (writer, gate) = new_writer(1, OverflowPolicy::Block);
+        let writer = Arc::new(writer);
+        set_gate(&gate, false);
+
+        writer.log(&channel, b"0", &Metadata::default()).unwrap();
+        wait_for_empty_queue(&writer);
+        writer.log(&channel, b"1", &Metadata::default()).unwrap();
+
+        // The queue is full, so the next message blocks until the writer catches up.
+        let thread = std::thread::spawn({
+            let writer = writer.clone();
+            let channel = channel.clone();
+            move || writer.log(&channel, b"2", &Metadata::default())
+        });
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!thread.is_finished());
+
+        set_gate(&gate, true);
+        thread.join().unwrap().unwrap();
+        let buf = writer.finish().unwrap().unwrap().inner.into_inner();
+        assert_eq!(read_messages(&buf), ["0", "1", "2"]);
+        assert_eq!(writer.dropped_messages(), 0);
+    }
+
+    #[test]
+    fn test_drop_oldest_through_writer() {
+        let ctx = Arc::new(LogContext::new());
+        let gate = Arc::new((Mutex::new(true), Condvar::new()));
+        let blocked = Arc::new(AtomicBool::new(false));
+        let writer = GatedWriter {
+            inner: Cursor::new(Vec::new()),
+            gate: gate.clone(),
+            blocked: blocked.clone(),
+        };
+        let handle = McapWriter::with_options(WriteOptions::new().use_chunks(false))
+            .context(&ctx)
+            .background_writer(2, OverflowPolicy::DropOldest)
+            .create(writer)
+            .expect("failed to create writer");
+        let channel = ChannelBuilder::new("/foo")
+            .message_encoding("json")
+            .context(&ctx)
+            .build()
+            .unwrap();
+
+        // The first message is dequeued, and blocks the writer thread.
+        set_gate(&gate, false);
+        channel.log(b"0");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !blocked.load(Ordering::Relaxed) {
+            assert!(Instant::now() < deadline, "timed out");
+            std::thread::yield_now();
+        }
+
+        // Metadata records are never dropped, and don't count toward the capacity.
+        channel.log(b"1");
+        handle
+            .write_metadata("info", BTreeMap::new())
+            .expect("failed to write metadata");
+        for msg in ["2", "3", "4"] {
+            channel.log(msg.as_bytes());
+        }
+        assert_eq!(handle.dropped_messages(), 2);
+
+        set_gate(&gate, true);
+        let buf = handle.close().expect("failed to close").inner.into_inner();
+        assert_eq!(read_messages(&buf), ["0", "3", "4"]);
+        let summary = mcap::Summary::read(&buf).unwrap().unwrap();
+        assert_eq!(summary.metadata_indexes.len(), 1);
+    }
+}
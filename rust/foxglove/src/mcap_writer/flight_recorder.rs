@@ -0,0 +1,545 @@
+//! In-memory flight recorder, which can be dumped to an MCAP file on demand.
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{BufWriter, Seek, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use mcap::WriteOptions;
+use parking_lot::Mutex;
+
+use super::mcap_sink::{FlushPolicy, McapSink};
+use crate::channel::ChannelId;
+use crate::log_sink_set::LogSinkSet;
+use crate::{Channel, FoxgloveError, LogContext, LogSink, Metadata, TopicFilter};
+
+/// A flight recorder, which keeps a bounded window of recent messages in memory.
+///
+/// The buffered window can be written to an MCAP file at any time, for example when a fault is
+/// detected, with [`FlightRecorderHandle::dump_to`] or [`FlightRecorderHandle::dump_to_writer`].
+///
+/// The window is bounded by the total size of buffered message payloads, and by the span of
+/// message log times. When a limit is exceeded, the oldest messages are discarded.
+///
+/// # Example
+/// ```no_run
+/// use foxglove::FlightRecorder;
+/// use std::time::Duration;
+///
+/// # fn func() -> Result<(), foxglove::FoxgloveError> {
+/// let recorder = FlightRecorder::new()
+///     .max_duration(Duration::from_secs(30))
+///     .max_bytes(64 * 1024 * 1024)
+///     .start();
+/// // ... when a fault occurs:
+/// recorder.dump_to("fault.mcap")?;
+/// # Ok(()) }
+/// ```
+#[must_use]
+#[derive(Debug, Clone)]
+pub struct FlightRecorder {
+    options: WriteOptions,
+    context: Arc<LogContext>,
+    topic_filter: Option<TopicFilter>,
+    max_bytes: Option<usize>,
+    max_duration: Option<Duration>,
+}
+
+impl Default for FlightRecorder {
+    fn default() -> Self {
+        Self {
+            options: WriteOptions::default()
+                .library(format!("foxglove-sdk-rs-{}", env!("CARGO_PKG_VERSION"))),
+            context: LogContext::global_arc().clone(),
+            topic_filter: None,
+            max_bytes: None,
+            max_duration: None,
+        }
+    }
+}
+
+impl FlightRecorder {
+    /// Instantiates a new flight recorder with default options.
+    ///
+    /// By default, the window is unbounded. You will usually want to set
+    /// [`max_bytes`](FlightRecorder::max_bytes),
+    /// [`max_duration`](FlightRecorder::max_duration), or both.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the MCAP write options used when dumping the window to a file.
+    pub fn write_options(mut self, options: WriteOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets the log context to record.
+    ///
+    /// By default, the recorder records channels from the [global context](LogContext::global).
+    pub fn context(mut self, ctx: &Arc<LogContext>) -> Self {
+        self.context = ctx.clone();
+        self
+    }
+
+    /// Sets a filter to select the channels to record.
+    ///
+    /// By default, the recorder records all channels in its log context.
+    pub fn topic_filter(mut self, filter: TopicFilter) -> Self {
+        self.topic_filter = Some(filter);
+        self
+    }
+
+    /// Sets the maximum total size of buffered message payloads.
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Sets the maximum span of log times between the oldest and newest buffered messages.
+    pub fn max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Begins buffering events.
+    ///
+    /// Returns a handle. When the handle is dropped, the recorder stops buffering events, and
+    /// the buffered window is discarded.
+    pub fn start(self) -> FlightRecorderHandle {
+        let sink = Arc::new(FlightRecorderSink {
+            buffer: Mutex::default(),
+            topic_filter: self.topic_filter,
+            max_bytes: self.max_bytes,
+            max_duration: self
+                .max_duration
+                .map(|d| u64::try_from(d.as_nanos()).unwrap_or(u64::MAX)),
+        });
+        self.context.add_sink(sink.clone());
+        FlightRecorderHandle {
+            sink,
+            options: self.options,
+            context: self.context,
+        }
+    }
+}
+
+/// A handle to a flight recorder.
+///
+/// When this handle is dropped, the recorder will stop buffering events.
+#[must_use]
+pub struct FlightRecorderHandle {
+    sink: Arc<FlightRecorderSink>,
+    options: WriteOptions,
+    context: Arc<LogContext>,
+}
+
+impl Debug for FlightRecorderHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("FlightRecorderHandle").finish()
+    }
+}
+
+impl FlightRecorderHandle {
+    /// Writes the buffered window to a new buffered file.
+    ///
+    /// If the file already exists, this call will fail with
+    /// [`AlreadyExists`](`std::io::ErrorKind::AlreadyExists`).
+    ///
+    /// The window is not cleared, so it may be dumped more than once.
+    pub fn dump_to<P>(&self, path: P) -> Result<(), FoxgloveError>
+    where
+        P: AsRef<Path>,
+    {
+        let file = File::create_new(path)?;
+        let mut writer = self.dump_to_writer(BufWriter::new(file))?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the buffered window to the specified writer, and returns the writer.
+    ///
+    /// The output is a complete MCAP file, including the schemas and channels for all buffered
+    /// messages.
+    pub fn dump_to_writer<W>(&self, writer: W) -> Result<W, FoxgloveError>
+    where
+        W: Write + Seek + Send,
+    {
+        let (channels, entries) = self.sink.snapshot();
+        let mcap = McapSink::new(
+            writer,
+            self.options.clone(),
+            FlushPolicy::default(),
+            None,
+            None,
+        )?;
+        for entry in &entries {
+            mcap.log(&channels[&entry.channel_id], &entry.data, &entry.metadata)?;
+        }
+        Ok(mcap.finish()?.expect("not finished"))
+    }
+
+    /// Discards all buffered messages.
+    pub fn clear(&self) {
+        self.sink.buffer.lock().clear();
+    }
+
+    fn stop(&self) {
+        let sink = self.sink.clone() as Arc<dyn LogSink>;
+        self.context.remove_sink(&sink);
+    }
+}
+
+impl Drop for FlightRecorderHandle {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[derive(Clone)]
+struct Entry {
+    channel_id: ChannelId,
+    data: Bytes,
+    metadata: Metadata,
+}
+
+/// A snapshot of a channel's topic, encoding, schema and metadata, for writing buffered messages.
+///
+/// The snapshot isn't registered with any log context or sinks, so buffered messages don't keep
+/// the original channel alive.
+struct ChannelSnapshot {
+    channel: Arc<Channel>,
+    // The number of buffered entries for the channel.
+    entries: usize,
+}
+
+impl ChannelSnapshot {
+    fn new(channel: &Channel) -> Self {
+        Self {
+            channel: Arc::new(Channel {
+                sinks: LogSinkSet::new(),
+                id: channel.id,
+                message_sequence: Default::default(),
+                topic: channel.topic.clone(),
+                message_encoding: channel.message_encoding.clone(),
+                schema: channel.schema.clone(),
+                metadata: channel.metadata.clone(),
+                latched: 0,
+            }),
+            entries: 0,
+        }
+    }
+}
+
+#[derive(Default)]
+struct RingBuffer {
+    entries: VecDeque<Entry>,
+    channels: HashMap<ChannelId, ChannelSnapshot>,
+    bytes: usize,
+    // The newest log time in the buffer, which may not be the log time of the last entry.
+    newest: u64,
+}
+
+impl RingBuffer {
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.channels.clear();
+        self.bytes = 0;
+        self.newest = 0;
+    }
+
+    fn push_back(&mut self, channel: &Channel, data: Bytes, metadata: Metadata) {
+        self.channels
+            .entry(channel.id)
+            .or_insert_with(|| ChannelSnapshot::new(channel))
+            .entries += 1;
+        self.bytes += data.len();
+        self.newest = self.newest.max(metadata.log_time);
+        self.entries.push_back(Entry {
+            channel_id: channel.id,
+            data,
+            metadata,
+        });
+    }
+
+    fn pop_front(&mut self) {
+        if let Some(entry) = self.entries.pop_front() {
+            self.bytes -= entry.data.len();
+            if let Some(snapshot) = self.channels.get_mut(&entry.channel_id) {
+                snapshot.entries -= 1;
+                if snapshot.entries == 0 {
+                    self.channels.remove(&entry.channel_id);
+                }
+            }
+        }
+    }
+}
+
+/// A [`LogSink`] which buffers messages in a bounded ring.
+struct FlightRecorderSink {
+    buffer: Mutex<RingBuffer>,
+    topic_filter: Option<TopicFilter>,
+    max_bytes: Option<usize>,
+    max_duration: Option<u64>,
+}
+
+impl FlightRecorderSink {
+    /// Returns the log time before which messages fall outside the window.
+    fn cutoff(&self, buffer: &RingBuffer) -> u64 {
+        self.max_duration
+            .map_or(0, |max| buffer.newest.saturating_sub(max))
+    }
+
+    /// Returns a copy of the buffered messages, ordered by log time, and their channels.
+    ///
+    /// Messages logged out of order may remain in the buffer after they fall outside the window,
+    /// so they're filtered out here.
+    fn snapshot(&self) -> (HashMap<ChannelId, Arc<Channel>>, Vec<Entry>) {
+        let buffer = self.buffer.lock();
+        let cutoff = self.cutoff(&buffer);
+        let mut entries: Vec<_> = buffer
+            .entries
+            .iter()
+            .filter(|e| e.metadata.log_time >= cutoff)
+            .cloned()
+            .collect();
+        let channels = entries
+            .iter()
+            .filter_map(|e| {
+                let snapshot = buffer.channels.get(&e.channel_id)?;
+                Some((e.channel_id, snapshot.channel.clone()))
+            })
+            .collect();
+        drop(buffer);
+        entries.sort_by_key(|e| e.metadata.log_time);
+        (channels, entries)
+    }
+}
+
+impl LogSink for FlightRecorderSink {
+    fn log(
+        &self,
+        channel: &Arc<Channel>,
+        msg: &[u8],
+        metadata: &Metadata,
+    ) -> Result<(), FoxgloveError> {
+        if self.max_bytes.is_some_and(|max| msg.len() > max) {
+            // The message can never fit in the window.
+            return Ok(());
+        }
+        let mut buffer = self.buffer.lock();
+        if metadata.log_time < self.cutoff(&buffer) {
+            // The message is already outside the window.
+            return Ok(());
+        }
+        buffer.push_back(channel, Bytes::copy_from_slice(msg), *metadata);
+        if let Some(max_bytes) = self.max_bytes {
+            while buffer.bytes > max_bytes {
+                buffer.pop_front();
+            }
+        }
+        if self.max_duration.is_some() {
+            let cutoff = self.cutoff(&buffer);
+            while buffer
+                .entries
+                .front()
+                .is_some_and(|e| e.metadata.log_time < cutoff)
+            {
+                buffer.pop_front();
+            }
+        }
+        Ok(())
+    }
+
+    fn accepts_channel(&self, channel: &Channel) -> bool {
+        self.topic_filter
+            .as_ref()
+            .is_none_or(|f| f.matches_channel(channel))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChannelBuilder;
+    use std::io::Cursor;
+
+    fn read_messages(buf: &[u8]) -> Vec<(String, Vec<u8>, u64)> {
+        mcap::MessageStream::new(buf)
+            .expect("failed to open stream")
+            .map(|msg| {
+                let msg = msg.expect("failed to read message");
+                (msg.channel.topic.clone(), msg.data.to_vec(), msg.log_time)
+            })
+            .collect()
+    }
+
+    fn log_at(channel: &Arc<Channel>, msg: &[u8], log_time: u64) {
+        channel.log_with_meta(
+            msg,
+            crate::PartialMetadata {
+                log_time: Some(log_time),
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn test_max_duration() {
+        let ctx = Arc::new(LogContext::new());
+        let recorder = FlightRecorder::new()
+            .context(&ctx)
+            .max_duration(Duration::from_nanos(10))
+            .start();
+        let ch1 = ChannelBuilder::new("/a")
+            .message_encoding("json")
+            .context(&ctx)
+            .build()
+            .unwrap();
+        let ch2 = ChannelBuilder::new("/b")
+            .message_encoding("json")
+            .context(&ctx)
+            .build()
+            .unwrap();
+
+        log_at(&ch1, b"1", 0);
+        log_at(&ch2, b"2", 5);
+        log_at(&ch1, b"3", 12);
+        log_at(&ch2, b"4", 20);
+
+        let buf = recorder
+            .dump_to_writer(Cursor::new(Vec::new()))
+            .expect("failed to dump")
+            .into_inner();
+        assert_eq!(
+            read_messages(&buf),
+            [
+                ("/a".to_string(), b"3".to_vec(), 12),
+                ("/b".to_string(), b"4".to_vec(), 20),
+            ]
+        );
+
+        // The window is retained after a dump, and can be cleared.
+        let buf = recorder
+            .dump_to_writer(Cursor::new(Vec::new()))
+            .expect("failed to dump")
+            .into_inner();
+        assert_eq!(read_messages(&buf).len(), 2);
+        recorder.clear();
+        let buf = recorder
+            .dump_to_writer(Cursor::new(Vec::new()))
+            .expect("failed to dump")
+            .into_inner();
+        assert!(read_messages(&buf).is_empty());
+    }
+
+    #[test]
+    fn test_max_duration_out_of_order() {
+        let ctx = Arc::new(LogContext::new());
+        let recorder = FlightRecorder::new()
+            .context(&ctx)
+            .max_duration(Duration::from_nanos(10))
+            .start();
+        let ch1 = ChannelBuilder::new("/a")
+            .message_encoding("json")
+            .context(&ctx)
+            .build()
+            .unwrap();
+        let ch2 = ChannelBuilder::new("/b")
+            .message_encoding("json")
+            .context(&ctx)
+            .build()
+            .unwrap();
+
+        log_at(&ch1, b"1", 20);
+        // Older than the window when it's logged.
+        log_at(&ch2, b"2", 5);
+        // Falls outside the window after the next message, behind a newer message.
+        log_at(&ch2, b"3", 12);
+        log_at(&ch1, b"4", 25);
+
+        let buf = recorder
+            .dump_to_writer(Cursor::new(Vec::new()))
+            .expect("failed to dump")
+            .into_inner();
+        assert_eq!(
+            read_messages(&buf),
+            [
+                ("/a".to_string(), b"1".to_vec(), 20),
+                ("/a".to_string(), b"4".to_vec(), 25),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_max_bytes() {
+        let ctx = Arc::new(LogContext::new());
+        let recorder = FlightRecorder::new().context(&ctx).max_bytes(10).start();
+        let ch = ChannelBuilder::new("/a")
+            .message_encoding("json")
+            .context(&ctx)
+            .build()
+            .unwrap();
+
+        for (i, msg) in [b"aaaa", b"bbbb", b"cccc"].iter().enumerate() {
+            log_at(&ch, *msg, i as u64);
+        }
+        // Larger than the whole window.
+        log_at(&ch, b"too large to buffer", 3);
+
+        let dir = tempfile::tempdir().expect("failed to create tempdir");
+        let path = dir.path().join("dump.mcap");
+        recorder.dump_to(&path).expect("failed to dump");
+        let buf = std::fs::read(&path).expect("failed to read file");
+        assert_eq!(
+            read_messages(&buf),
+            [
+                ("/a".to_string(), b"bbbb".to_vec(), 1),
+                ("/a".to_string(), b"cccc".to_vec(), 2),
+            ]
+        );
+
+        // Dumping to an existing path fails.
+        assert!(recorder.dump_to(&path).is_err());
+    }
+
+    #[test]
+    fn test_does_not_retain_channels() {
+        let ctx = Arc::new(LogContext::new());
+        let recorder = FlightRecorder::new().context(&ctx).max_bytes(4).start();
+        let ch = ChannelBuilder::new("/a")
+            .message_encoding("json")
+            .context(&ctx)
+            .build()
+            .unwrap();
+        log_at(&ch, b"aaaa", 0);
+
+        // Buffered messages are still dumped after the channel is dropped.
+        let weak = Arc::downgrade(&ch);
+        drop(ch);
+        assert!(ctx.remove_channel_for_topic("/a"));
+        assert!(weak.upgrade().is_none());
+        let buf = recorder
+            .dump_to_writer(Cursor::new(Vec::new()))
+            .expect("failed to dump")
+            .into_inner();
+        assert_eq!(
+            read_messages(&buf),
+            [("/a".to_string(), b"aaaa".to_vec(), 0)]
+        );
+
+        // Channel snapshots are discarded with the last buffered message.
+        let ch = ChannelBuilder::new("/b")
+            .message_encoding("json")
+            .context(&ctx)
+            .build()
+            .unwrap();
+        log_at(&ch, b"bbbb", 1);
+        let buffer = recorder.sink.buffer.lock();
+        assert_eq!(buffer.channels.len(), 1);
+        assert!(buffer.channels.contains_key(&ch.id()));
+    }
+}
@@ -1,36 +1,66 @@
 //! [`LogSink`] implementation for an MCAP writer.
+use super::background::{BackgroundWriter, OverflowPolicy};
 use crate::channel::Channel;
 use crate::channel::ChannelId;
 use crate::log_sink::LogSink;
 use crate::metadata::Metadata;
-use crate::FoxgloveError;
+use crate::{nanoseconds_since_epoch, FoxgloveError, TopicFilter};
 use mcap::WriteOptions;
 use parking_lot::Mutex;
+use std::borrow::Cow;
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
 use std::io::{Seek, Write};
-use std::sync::Arc;
+use std::sync::mpsc::{RecvTimeoutError, Sender};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
 
-struct WriterState<W: Write + Seek> {
+/// Determines how often the current chunk is closed and flushed to the underlying writer.
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct FlushPolicy {
+    pub(super) interval: Option<Duration>,
+    pub(super) messages: Option<u64>,
+    /// Whether file data is synced to disk after each flush.
+    pub(super) sync: bool,
+}
+
+pub(super) struct WriterState<W: Write + Seek> {
     writer: mcap::Writer<W>,
     // ChannelId -> mcap file channel id
     channel_map: HashMap<ChannelId, u16>,
+    flush_policy: FlushPolicy,
+    last_flush: Instant,
+    messages_since_flush: u64,
+    // Whether anything has been written since the last flush.
+    unflushed: bool,
+    // The file underlying the writer, which is synced to disk after each flush.
+    sync_file: Option<File>,
 }
 
 impl<W: Write + Seek> WriterState<W> {
-    fn new(writer: mcap::Writer<W>) -> Self {
+    pub(super) fn new(writer: mcap::Writer<W>, flush_policy: FlushPolicy) -> Self {
         Self {
             writer,
             channel_map: HashMap::new(),
+            flush_policy,
+            last_flush: Instant::now(),
+            messages_since_flush: 0,
+            unflushed: false,
+            sync_file: None,
         }
     }
 
-    fn log(
-        &mut self,
-        channel: &Arc<Channel>,
-        msg: &[u8],
-        metadata: &Metadata,
-    ) -> Result<(), FoxgloveError> {
+    /// Sets the file to sync to disk after each flush, which must be the file underlying the
+    /// writer.
+    pub(super) fn with_sync_file(mut self, file: Option<File>) -> Self {
+        self.sync_file = file;
+        self
+    }
+
+    /// Returns the MCAP channel ID for the channel, writing the schema and channel records to the
+    /// file the first time the channel is seen.
+    pub(super) fn mcap_channel_id(&mut self, channel: &Channel) -> Result<u16, FoxgloveError> {
         let channel_id = channel.id();
         let mcap_channel_id = match self.channel_map.entry(channel_id) {
             Entry::Occupied(entry) => *entry.get(),
@@ -57,7 +87,16 @@ impl<W: Write + Seek> WriterState<W> {
                 mcap_channel_id
             }
         };
+        Ok(mcap_channel_id)
+    }
 
+    pub(super) fn log(
+        &mut self,
+        channel: &Arc<Channel>,
+        msg: &[u8],
+        metadata: &Metadata,
+    ) -> Result<(), FoxgloveError> {
+        let mcap_channel_id = self.mcap_channel_id(channel)?;
         self.writer
             .write_to_known_channel(
                 &mcap::records::MessageHeader {
@@ -68,17 +107,146 @@ impl<W: Write + Seek> WriterState<W> {
                 },
                 msg,
             )
+            .map_err(FoxgloveError::from)?;
+        self.messages_since_flush += 1;
+        self.unflushed = true;
+        self.maybe_flush()
+    }
+
+    /// Closes the current chunk and flushes the writer, if required by the flush policy.
+    pub(super) fn maybe_flush(&mut self) -> Result<(), FoxgloveError> {
+        let due = self
+            .flush_policy
+            .messages
+            .is_some_and(|n| self.messages_since_flush >= n)
+            || self
+                .flush_deadline()
+                .is_some_and(|deadline| Instant::now() >= deadline);
+        if !due {
+            return Ok(());
+        }
+        self.last_flush = Instant::now();
+        self.messages_since_flush = 0;
+        // Don't write empty chunks while nothing is being logged.
+        if std::mem::take(&mut self.unflushed) {
+            self.writer.flush()?;
+            if let Some(file) = &self.sync_file {
+                file.sync_data()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the time at which the flush interval elapses, if there is one.
+    pub(super) fn flush_deadline(&self) -> Option<Instant> {
+        self.flush_policy.interval.map(|d| self.last_flush + d)
+    }
+
+    pub(super) fn write_metadata(
+        &mut self,
+        name: String,
+        metadata: BTreeMap<String, String>,
+    ) -> Result<(), FoxgloveError> {
+        self.unflushed = true;
+        self.writer
+            .write_metadata(&mcap::records::Metadata { name, metadata })
+            .map_err(FoxgloveError::from)
+    }
+
+    pub(super) fn attach(
+        &mut self,
+        name: String,
+        media_type: String,
+        log_time: u64,
+        data: &[u8],
+    ) -> Result<(), FoxgloveError> {
+        self.unflushed = true;
+        self.writer
+            .attach(&mcap::Attachment {
+                log_time,
+                create_time: nanoseconds_since_epoch(),
+                name,
+                media_type,
+                data: Cow::Borrowed(data),
+            })
             .map_err(FoxgloveError::from)
     }
+
+    /// Writes the summary section and returns the inner writer.
+    pub(super) fn finish(mut self) -> Result<W, FoxgloveError> {
+        self.writer.finish()?;
+        Ok(self.writer.into_inner())
+    }
+}
+
+/// Flushes a writer on a dedicated thread when its flush interval elapses, so that buffered data
+/// is written even while nothing is being logged.
+///
+/// The thread exits when the timer is dropped.
+pub(super) struct FlushTimer {
+    _stop: Sender<()>,
+}
+
+impl FlushTimer {
+    /// Starts a timer which first calls `flush` at `deadline`.
+    ///
+    /// `flush` returns the time at which it should be called next, or None to stop the timer.
+    pub(super) fn start<F>(deadline: Instant, mut flush: F) -> Self
+    where
+        F: FnMut() -> Option<Instant> + Send + 'static,
+    {
+        let (stop, stopped) = std::sync::mpsc::channel();
+        std::thread::Builder::new()
+            .name("foxglove-mcap-flush".to_string())
+            .spawn(move || {
+                let mut deadline = deadline;
+                while let Err(RecvTimeoutError::Timeout) =
+                    stopped.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                {
+                    match flush() {
+                        Some(next) => deadline = next,
+                        None => break,
+                    }
+                }
+            })
+            .expect("Failed to spawn MCAP flush thread");
+        Self { _stop: stop }
+    }
+}
+
+// The sink is always heap-allocated, so the size difference doesn't matter.
+#[allow(clippy::large_enum_variant)]
+enum SinkWriter<W: Write + Seek> {
+    /// Records are written on the logging thread.
+    Direct(Mutex<Option<WriterState<W>>>),
+    /// Records are queued and written on a background thread.
+    Background(BackgroundWriter<W>),
 }
 
-pub struct McapSink<W: Write + Seek>(Mutex<Option<WriterState<W>>>);
+pub struct McapSink<W: Write + Seek> {
+    writer: SinkWriter<W>,
+    topic_filter: Option<TopicFilter>,
+    flush_timer: Mutex<Option<FlushTimer>>,
+}
 
 impl<W: Write + Seek> McapSink<W> {
     /// Creates a new MCAP writer log sink.
-    pub fn new(writer: W, options: WriteOptions) -> Result<Arc<McapSink<W>>, FoxgloveError> {
+    ///
+    /// If `sync_file` is set, it is synced to disk after each flush.
+    pub fn new(
+        writer: W,
+        options: WriteOptions,
+        flush_policy: FlushPolicy,
+        sync_file: Option<File>,
+        topic_filter: Option<TopicFilter>,
+    ) -> Result<Arc<McapSink<W>>, FoxgloveError> {
         let mcap_writer = options.create(writer).map_err(FoxgloveError::from)?;
-        let writer = Arc::new(Self(Mutex::new(Some(WriterState::new(mcap_writer)))));
+        let state = WriterState::new(mcap_writer, flush_policy).with_sync_file(sync_file);
+        let writer = Arc::new(Self {
+            writer: SinkWriter::Direct(Mutex::new(Some(state))),
+            topic_filter,
+            flush_timer: Mutex::default(),
+        });
         Ok(writer)
     }
 
@@ -86,11 +254,116 @@ impl<W: Write + Seek> McapSink<W> {
     ///
     /// Returns the inner writer that was passed to [`McapWriter::new`].
     pub fn finish(&self) -> Result<Option<W>, FoxgloveError> {
-        let Some(mut writer) = self.0.lock().take() else {
-            return Ok(None);
+        self.flush_timer.lock().take();
+        match &self.writer {
+            SinkWriter::Direct(state) => {
+                let Some(writer) = state.lock().take() else {
+                    return Ok(None);
+                };
+                writer.finish().map(Some)
+            }
+            SinkWriter::Background(writer) => writer.finish(),
+        }
+    }
+
+    /// Writes a metadata record to the MCAP recording.
+    pub fn write_metadata(
+        &self,
+        name: String,
+        metadata: BTreeMap<String, String>,
+    ) -> Result<(), FoxgloveError> {
+        match &self.writer {
+            SinkWriter::Direct(state) => {
+                let mut guard = state.lock();
+                let writer = guard.as_mut().ok_or(FoxgloveError::SinkClosed)?;
+                writer.write_metadata(name, metadata)
+            }
+            SinkWriter::Background(writer) => writer.write_metadata(name, metadata),
+        }
+    }
+
+    /// Writes an attachment record to the MCAP recording.
+    pub fn attach(
+        &self,
+        name: String,
+        media_type: String,
+        log_time: u64,
+        data: &[u8],
+    ) -> Result<(), FoxgloveError> {
+        match &self.writer {
+            SinkWriter::Direct(state) => {
+                let mut guard = state.lock();
+                let writer = guard.as_mut().ok_or(FoxgloveError::SinkClosed)?;
+                writer.attach(name, media_type, log_time, data)
+            }
+            SinkWriter::Background(writer) => writer.attach(name, media_type, log_time, data),
+        }
+    }
+
+    /// Returns the number of messages dropped because the background writer's queue was full.
+    pub fn dropped_messages(&self) -> u64 {
+        match &self.writer {
+            SinkWriter::Direct(_) => 0,
+            SinkWriter::Background(writer) => writer.dropped_messages(),
+        }
+    }
+}
+
+impl<W: Write + Seek + Send + 'static> McapSink<W> {
+    /// Creates a new MCAP writer log sink, which writes records on a background thread.
+    ///
+    /// Logged messages are copied into a queue which holds up to `queue_size` messages. The
+    /// background thread also flushes the writer when the flush interval elapses.
+    pub fn new_background(
+        writer: W,
+        options: WriteOptions,
+        flush_policy: FlushPolicy,
+        sync_file: Option<File>,
+        queue_size: usize,
+        overflow_policy: OverflowPolicy,
+        topic_filter: Option<TopicFilter>,
+    ) -> Result<Arc<McapSink<W>>, FoxgloveError> {
+        let mcap_writer = options.create(writer).map_err(FoxgloveError::from)?;
+        let state = WriterState::new(mcap_writer, flush_policy).with_sync_file(sync_file);
+        let writer = Arc::new(Self {
+            writer: SinkWriter::Background(BackgroundWriter::new(
+                state,
+                queue_size,
+                overflow_policy,
+            )),
+            topic_filter,
+            flush_timer: Mutex::default(),
+        });
+        Ok(writer)
+    }
+
+    /// Starts flushing the writer on a timer when the flush interval elapses, even while nothing
+    /// is being logged. Background writers flush on their own thread, so this has no effect for
+    /// them.
+    pub fn start_flush_timer(self: &Arc<Self>) {
+        let SinkWriter::Direct(state) = &self.writer else {
+            return;
+        };
+        let Some(deadline) = state.lock().as_ref().and_then(|s| s.flush_deadline()) else {
+            return;
         };
-        writer.writer.finish()?;
-        Ok(Some(writer.writer.into_inner()))
+        let sink = Arc::downgrade(self);
+        let timer = FlushTimer::start(deadline, move || Self::flush_if_due(&sink));
+        *self.flush_timer.lock() = Some(timer);
+    }
+
+    /// Flushes the writer if the flush interval has elapsed, and returns the next deadline.
+    fn flush_if_due(sink: &Weak<Self>) -> Option<Instant> {
+        let sink = sink.upgrade()?;
+        let SinkWriter::Direct(state) = &sink.writer else {
+            return None;
+        };
+        let mut guard = state.lock();
+        let state = guard.as_mut()?;
+        if let Err(e) = state.maybe_flush() {
+            tracing::warn!("Failed to flush MCAP writer: {e}");
+        }
+        state.flush_deadline()
     }
 }
 
@@ -101,10 +374,20 @@ impl<W: Write + Seek + Send> LogSink for McapSink<W> {
         msg: &[u8],
         metadata: &Metadata,
     ) -> Result<(), FoxgloveError> {
-        _ = metadata;
-        let mut guard = self.0.lock();
-        let writer = guard.as_mut().ok_or(FoxgloveError::SinkClosed)?;
-        writer.log(channel, msg, metadata)
+        match &self.writer {
+            SinkWriter::Direct(state) => {
+                let mut guard = state.lock();
+                let writer = guard.as_mut().ok_or(FoxgloveError::SinkClosed)?;
+                writer.log(channel, msg, metadata)
+            }
+            SinkWriter::Background(writer) => writer.log(channel, msg, metadata),
+        }
+    }
+
+    fn accepts_channel(&self, channel: &Channel) -> bool {
+        self.topic_filter
+            .as_ref()
+            .is_none_or(|f| f.matches_channel(channel))
     }
 }
 
@@ -114,6 +397,7 @@ mod tests {
     use crate::log_sink_set::LogSinkSet;
     use crate::{collection, Metadata, Schema};
     use mcap::McapError;
+    use std::io::BufWriter;
     use std::path::Path;
     use std::sync::atomic::AtomicU32;
     use tempfile::NamedTempFile;
@@ -137,6 +421,7 @@ mod tests {
                 }"#,
             )),
             metadata: collection! {"key".to_string() => "value".to_string()},
+            latched: 0,
         })
     }
 
@@ -192,8 +477,14 @@ mod tests {
         let mut ch2_meta_iter = ch2_meta.iter();
 
         // Log two messages to each channel, interleaved
-        let writer =
-            McapSink::new(&temp_file, WriteOptions::default()).expect("failed to create writer");
+        let writer = McapSink::new(
+            &temp_file,
+            WriteOptions::default(),
+            FlushPolicy::default(),
+            None,
+            None,
+        )
+        .expect("failed to create writer");
         writer
             .log(&ch1, b"msg1", &ch1_meta[0])
             .expect("failed to log to channel 1");
@@ -254,4 +545,112 @@ mod tests {
         })
         .expect("failed to read MCAP messages");
     }
+
+    /// Returns true if the file contains the payload.
+    fn file_contains(path: &Path, payload: &[u8]) -> bool {
+        let contents = std::fs::read(path).expect("failed to read file");
+        contents.windows(payload.len()).any(|w| w == payload)
+    }
+
+    fn new_file_sink(path: &Path, flush_policy: FlushPolicy) -> Arc<McapSink<BufWriter<File>>> {
+        let file = File::create(path).expect("failed to create file");
+        McapSink::new(
+            BufWriter::new(file),
+            WriteOptions::new().compression(None),
+            flush_policy,
+            None,
+            None,
+        )
+        .expect("failed to create sink")
+    }
+
+    #[test]
+    fn test_flush_interval() {
+        let temp_file = NamedTempFile::new().expect("create tempfile");
+        let sink = new_file_sink(
+            temp_file.path(),
+            FlushPolicy {
+                interval: Some(Duration::from_millis(20)),
+                ..FlushPolicy::default()
+            },
+        );
+        sink.start_flush_timer();
+
+        // The message is flushed to the file once the interval elapses, without any further
+        // messages being logged.
+        let ch = new_test_channel(1, "foo".to_string(), "foo_schema".to_string());
+        sink.log(&ch, b"flushed message", &Metadata::default())
+            .expect("failed to log");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !file_contains(temp_file.path(), b"flushed message") {
+            assert!(Instant::now() < deadline, "timed out");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        sink.finish().expect("failed to finish");
+    }
+
+    #[test]
+    fn test_flush_after_messages() {
+        let temp_file = NamedTempFile::new().expect("create tempfile");
+        let sink = new_file_sink(
+            temp_file.path(),
+            FlushPolicy {
+                messages: Some(2),
+                ..FlushPolicy::default()
+            },
+        );
+
+        let ch = new_test_channel(1, "foo".to_string(), "foo_schema".to_string());
+        sink.log(&ch, b"first message", &Metadata::default())
+            .expect("failed to log");
+        assert!(!file_contains(temp_file.path(), b"first message"));
+        sink.log(&ch, b"second message", &Metadata::default())
+            .expect("failed to log");
+        assert!(file_contains(temp_file.path(), b"first message"));
+        assert!(file_contains(temp_file.path(), b"second message"));
+        sink.finish().expect("failed to finish");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_sync_after_flush() {
+        let flush_policy = FlushPolicy {
+            messages: Some(1),
+            sync: true,
+            ..FlushPolicy::default()
+        };
+        let ch = new_test_channel(1, "foo".to_string(), "foo_schema".to_string());
+
+        // The file is synced after each flush.
+        let temp_file = NamedTempFile::new().expect("create tempfile");
+        let file = File::create(temp_file.path()).expect("failed to create file");
+        let sync_file = file.try_clone().expect("failed to clone file");
+        let sink = McapSink::new(
+            BufWriter::new(file),
+            WriteOptions::new().compression(None),
+            flush_policy,
+            Some(sync_file),
+            None,
+        )
+        .expect("failed to create sink");
+        sink.log(&ch, b"synced message", &Metadata::default())
+            .expect("failed to log");
+        assert!(file_contains(temp_file.path(), b"synced message"));
+        sink.finish().expect("failed to finish");
+
+        // Syncing /dev/null fails, which shows that the sync file is synced on flush.
+        let sync_file = File::options()
+            .write(true)
+            .open("/dev/null")
+            .expect("failed to open /dev/null");
+        let sink = McapSink::new(
+            std::io::Cursor::new(Vec::new()),
+            WriteOptions::new(),
+            flush_policy,
+            Some(sync_file),
+            None,
+        )
+        .expect("failed to create sink");
+        assert!(sink.log(&ch, b"message", &Metadata::default()).is_err());
+    }
 }
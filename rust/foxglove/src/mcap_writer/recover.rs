@@ -0,0 +1,231 @@
+//! Recovery of truncated MCAP files.
+use std::collections::HashMap;
+use std::io::{Read, Seek, Write};
+
+use mcap::records::{MessageHeader, Record};
+use mcap::sans_io::read::{LinearReader, ReadAction};
+use mcap::{Attachment, McapError, WriteOptions};
+
+use crate::FoxgloveError;
+
+/// Recovers the readable contents of a truncated MCAP file.
+///
+/// MCAP files that were not closed properly, for example because the process was killed, have no
+/// summary section, and may end with a partially-written record. This function reads `reader`
+/// until the end of the last complete record, and writes its schemas, channels, messages,
+/// attachments and metadata to `writer` as a new MCAP file, with a rebuilt summary and index.
+///
+/// See [`McapWriter::flush_interval`](crate::McapWriter::flush_interval) to limit the amount of
+/// data that can be lost.
+///
+/// Returns the number of messages that were recovered.
+///
+/// # Example
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::{BufReader, BufWriter};
+///
+/// # fn func() -> Result<(), foxglove::FoxgloveError> {
+/// let input = BufReader::new(File::open("truncated.mcap")?);
+/// let output = BufWriter::new(File::create_new("recovered.mcap")?);
+/// let messages = foxglove::mcap_recover(input, output)?;
+/// println!("Recovered {messages} messages");
+/// # Ok(()) }
+/// ```
+pub fn mcap_recover<R, W>(mut reader: R, writer: W) -> Result<u64, FoxgloveError>
+where
+    R: Read,
+    W: Write + Seek,
+{
+    let mut linear_reader = LinearReader::new();
+    let mut recovery: Option<Recovery<W>> = None;
+    let mut writer = Some(writer);
+    loop {
+        let action = match linear_reader.next_action() {
+            None => break,
+            Some(Ok(action)) => action,
+            // The file must at least begin with a valid header.
+            Some(Err(e)) if recovery.is_none() => return Err(e.into()),
+            Some(Err(e)) => {
+                tracing::debug!("Stopped reading MCAP at invalid or truncated record: {e}");
+                break;
+            }
+        };
+        match action {
+            ReadAction::NeedMore(count) => {
+                let count = reader.read(linear_reader.insert(count))?;
+                linear_reader.set_written(count);
+            }
+            ReadAction::GetRecord { data, opcode } => {
+                let record = match mcap::parse_record(opcode, data) {
+                    Ok(record) => record,
+                    Err(e) if recovery.is_none() => return Err(e.into()),
+                    Err(e) => {
+                        tracing::debug!("Stopped reading MCAP at invalid record: {e}");
+                        break;
+                    }
+                };
+                match (&mut recovery, record) {
+                    (None, Record::Header(header)) => {
+                        let mcap_writer = WriteOptions::new()
+                            .profile(header.profile)
+                            .library(header.library)
+                            .create(writer.take().expect("writer already created"))?;
+                        recovery = Some(Recovery::new(mcap_writer));
+                    }
+                    (None, _) => return Err(McapError::BadMagic.into()),
+                    (Some(recovery), record) => recovery.handle_record(record)?,
+                }
+            }
+        }
+    }
+    let Some(mut recovery) = recovery else {
+        return Err(McapError::UnexpectedEof.into());
+    };
+    recovery.writer.finish()?;
+    Ok(recovery.messages)
+}
+
+struct Recovery<W: Write + Seek> {
+    writer: mcap::Writer<W>,
+    // Schema and channel IDs in the input file -> IDs in the output file.
+    schemas: HashMap<u16, u16>,
+    channels: HashMap<u16, u16>,
+    messages: u64,
+}
+
+impl<W: Write + Seek> Recovery<W> {
+    fn new(writer: mcap::Writer<W>) -> Self {
+        Self {
+            writer,
+            schemas: HashMap::new(),
+            channels: HashMap::new(),
+            messages: 0,
+        }
+    }
+
+    fn handle_record(&mut self, record: Record<'_>) -> Result<(), McapError> {
+        match record {
+            Record::Schema { header, data } if !self.schemas.contains_key(&header.id) => {
+                let id = self
+                    .writer
+                    .add_schema(&header.name, &header.encoding, &data)?;
+                self.schemas.insert(header.id, id);
+            }
+            Record::Channel(channel) if !self.channels.contains_key(&channel.id) => {
+                // 0 indicates a channel without a schema.
+                let schema_id = match channel.schema_id {
+                    0 => 0,
+                    id => *self.schemas.get(&id).ok_or(McapError::UnknownSchema(
+                        channel.topic.clone(),
+                        channel.schema_id,
+                    ))?,
+                };
+                let id = self.writer.add_channel(
+                    schema_id,
+                    &channel.topic,
+                    &channel.message_encoding,
+                    &channel.metadata,
+                )?;
+                self.channels.insert(channel.id, id);
+            }
+            Record::Message { header, data } => {
+                let channel_id =
+                    *self
+                        .channels
+                        .get(&header.channel_id)
+                        .ok_or(McapError::UnknownChannel(
+                            header.sequence,
+                            header.channel_id,
+                        ))?;
+                self.writer.write_to_known_channel(
+                    &MessageHeader {
+                        channel_id,
+                        ..header
+                    },
+                    &data,
+                )?;
+                self.messages += 1;
+            }
+            Record::Attachment { header, data } => {
+                self.writer.attach(&Attachment {
+                    log_time: header.log_time,
+                    create_time: header.create_time,
+                    name: header.name,
+                    media_type: header.media_type,
+                    data,
+                })?;
+            }
+            Record::Metadata(metadata) => self.writer.write_metadata(&metadata)?,
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChannelBuilder, LogContext, McapWriter, Schema};
+    use std::collections::BTreeMap;
+    use std::io::Cursor;
+    use std::sync::Arc;
+
+    fn read_messages(buf: &[u8]) -> Vec<Vec<u8>> {
+        mcap::MessageStream::new(buf)
+            .expect("failed to open stream")
+            .map(|msg| msg.expect("failed to read message").data.to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn test_recover_truncated_file() {
+        let ctx = Arc::new(LogContext::new());
+        let writer = McapWriter::new()
+            .context(&ctx)
+            .flush_after_messages(2)
+            .create(Cursor::new(Vec::new()))
+            .expect("failed to create writer");
+        let channel = ChannelBuilder::new("/foo")
+            .message_encoding("json")
+            .schema(Schema::new("foo", "jsonschema", br#"{"type": "object"}"#))
+            .context(&ctx)
+            .build()
+            .unwrap();
+        writer
+            .write_metadata(
+                "info",
+                BTreeMap::from([("key".to_string(), "value".to_string())]),
+            )
+            .unwrap();
+        for i in 0..5 {
+            channel.log(format!("{i}").as_bytes());
+        }
+        let buf = writer.close().unwrap().into_inner();
+
+        // Simulate a crash by truncating the file before the summary section. The last chunk,
+        // which contains only one message, is incomplete.
+        let summary = mcap::Summary::read(&buf).unwrap().unwrap();
+        let last_chunk = summary.chunk_indexes.last().unwrap();
+        let truncated =
+            &buf[..(last_chunk.chunk_start_offset + last_chunk.chunk_length / 2) as usize];
+        assert!(!matches!(mcap::Summary::read(truncated), Ok(Some(_))));
+
+        let mut output = Cursor::new(Vec::new());
+        let count = mcap_recover(truncated, &mut output).expect("failed to recover");
+        assert_eq!(count, 4);
+
+        let recovered = output.into_inner();
+        assert_eq!(read_messages(&recovered), [b"0", b"1", b"2", b"3"]);
+        let summary = mcap::Summary::read(&recovered).unwrap().unwrap();
+        assert_eq!(summary.stats.unwrap().message_count, 4);
+        assert_eq!(summary.channels.len(), 1);
+        assert_eq!(summary.metadata_indexes.len(), 1);
+    }
+
+    #[test]
+    fn test_recover_invalid_file() {
+        let mut output = Cursor::new(Vec::new());
+        assert!(mcap_recover(&b"not an mcap file"[..], &mut output).is_err());
+    }
+}
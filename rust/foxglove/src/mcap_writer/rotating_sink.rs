@@ -0,0 +1,511 @@
+//! [`LogSink`] implementation which rotates between MCAP files.
+use super::mcap_sink::{FlushPolicy, FlushTimer, WriterState};
+use super::RotationOptions;
+use crate::channel::{Channel, ChannelId};
+use crate::log_sink::LogSink;
+use crate::metadata::Metadata;
+use crate::{nanoseconds_since_epoch, FoxgloveError, TopicFilter};
+use mcap::WriteOptions;
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+/// Placeholder in the path template which is replaced by the file's index.
+pub(super) const INDEX_PLACEHOLDER: &str = "{index}";
+/// Placeholder in the path template which is replaced by the file's creation time.
+pub(super) const TIMESTAMP_PLACEHOLDER: &str = "{timestamp}";
+/// The maximum number of existing files to skip over when creating a new file.
+const MAX_EXISTING_FILES: usize = 1000;
+
+/// A writer that keeps track of how many bytes have been written to the underlying file.
+struct CountingWriter<W> {
+    inner: W,
+    position: u64,
+    len: Arc<AtomicU64>,
+}
+
+impl<W> CountingWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            position: 0,
+            len: Arc::default(),
+        }
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.position += n as u64;
+        self.len.fetch_max(self.position, Ordering::Relaxed);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Seek> Seek for CountingWriter<W> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.position = self.inner.seek(pos)?;
+        Ok(self.position)
+    }
+}
+
+/// The file currently being written.
+struct CurrentFile {
+    writer: WriterState<CountingWriter<BufWriter<File>>>,
+    len: Arc<AtomicU64>,
+    created: Instant,
+    // Whether a message has been written to the file.
+    has_messages: bool,
+}
+
+impl CurrentFile {
+    fn create(
+        path: &PathBuf,
+        options: WriteOptions,
+        flush_policy: FlushPolicy,
+    ) -> Result<Self, FoxgloveError> {
+        let file = File::create_new(path)?;
+        let sync_file = flush_policy.sync.then(|| file.try_clone()).transpose()?;
+        let writer = CountingWriter::new(BufWriter::new(file));
+        let len = writer.len.clone();
+        let writer = options.create(writer).map_err(FoxgloveError::from)?;
+        Ok(Self {
+            writer: WriterState::new(writer, flush_policy).with_sync_file(sync_file),
+            len,
+            created: Instant::now(),
+            has_messages: false,
+        })
+    }
+
+    fn finish(self) -> Result<(), FoxgloveError> {
+        let mut writer = self.writer.finish()?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+struct RotatingState {
+    current: Option<CurrentFile>,
+    finished: bool,
+    // Index of the next file, which advances even if the file can't be created.
+    next_index: usize,
+    paths: Vec<PathBuf>,
+    // Channels that have been added to the sink, which are written to each new file.
+    channels: HashMap<ChannelId, Weak<Channel>>,
+}
+
+pub(super) struct RotatingMcapSink {
+    state: Mutex<RotatingState>,
+    template: String,
+    options: WriteOptions,
+    flush_policy: FlushPolicy,
+    rotation: RotationOptions,
+    topic_filter: Option<TopicFilter>,
+    flush_timer: Mutex<Option<FlushTimer>>,
+}
+
+impl RotatingMcapSink {
+    /// Creates a new rotating MCAP log sink, and opens the first file.
+    pub(super) fn new(
+        template: String,
+        options: WriteOptions,
+        flush_policy: FlushPolicy,
+        rotation: RotationOptions,
+        topic_filter: Option<TopicFilter>,
+    ) -> Result<Arc<Self>, FoxgloveError> {
+        let sink = Self {
+            state: Mutex::new(RotatingState {
+                current: None,
+                finished: false,
+                next_index: 0,
+                paths: Vec::new(),
+                channels: HashMap::new(),
+            }),
+            template,
+            options,
+            flush_policy,
+            rotation,
+            topic_filter,
+            flush_timer: Mutex::default(),
+        };
+        sink.rotate(&mut sink.state.lock())?;
+        let sink = Arc::new(sink);
+        if let Some(interval) = flush_policy.interval {
+            let weak = Arc::downgrade(&sink);
+            let timer = FlushTimer::start(Instant::now() + interval, move || {
+                weak.upgrade()?.flush_if_due(interval)
+            });
+            *sink.flush_timer.lock() = Some(timer);
+        }
+        Ok(sink)
+    }
+
+    /// Flushes the current file if the flush interval has elapsed, and returns the next deadline.
+    fn flush_if_due(&self, interval: Duration) -> Option<Instant> {
+        let mut state = self.state.lock();
+        if state.finished {
+            return None;
+        }
+        let Some(current) = state.current.as_mut() else {
+            return Some(Instant::now() + interval);
+        };
+        if let Err(e) = current.writer.maybe_flush() {
+            tracing::warn!("Failed to flush MCAP writer: {e}");
+        }
+        current.writer.flush_deadline()
+    }
+
+    /// Returns the path for the file with the given index.
+    fn path(&self, index: usize) -> PathBuf {
+        self.template
+            .replace(INDEX_PLACEHOLDER, &index.to_string())
+            .replace(
+                TIMESTAMP_PLACEHOLDER,
+                &nanoseconds_since_epoch().to_string(),
+            )
+            .into()
+    }
+
+    /// Returns true if the current file has reached one of the rotation limits.
+    ///
+    /// A file is never rotated before it has any messages, even if the header, schemas, and
+    /// channels alone exceed the size limit.
+    fn should_rotate(&self, current: &CurrentFile) -> bool {
+        current.has_messages
+            && (self
+                .rotation
+                .max_bytes
+                .is_some_and(|max| current.len.load(Ordering::Relaxed) >= max)
+                || self
+                    .rotation
+                    .max_duration
+                    .is_some_and(|max| current.created.elapsed() >= max))
+    }
+
+    /// Creates the next file, skipping over paths which already exist.
+    fn create_next(&self, state: &mut RotatingState) -> Result<CurrentFile, FoxgloveError> {
+        let mut skipped = 0;
+        loop {
+            let path = self.path(state.next_index);
+            state.next_index += 1;
+            match CurrentFile::create(&path, self.options.clone(), self.flush_policy) {
+                Ok(file) => {
+                    state.paths.push(path);
+                    return Ok(file);
+                }
+                Err(FoxgloveError::IoError(err))
+                    if err.kind() == std::io::ErrorKind::AlreadyExists
+                        && skipped < MAX_EXISTING_FILES =>
+                {
+                    tracing::warn!("Skipping existing MCAP file {}", path.display());
+                    skipped += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Finishes the current file (if any), and begins writing to a new one.
+    ///
+    /// Schemas and channels which have been added to the sink are written to the new file, so that
+    /// each file can be read independently. If the new file can't be created, the sink tries again
+    /// the next time a message is logged.
+    fn rotate(&self, state: &mut RotatingState) -> Result<(), FoxgloveError> {
+        if let Some(prev) = state.current.take() {
+            prev.finish()?;
+        }
+        let next = self.create_next(state)?;
+        let next = state.current.insert(next);
+        state
+            .channels
+            .retain(|_, channel| channel.strong_count() > 0);
+        for channel in state.channels.values().filter_map(Weak::upgrade) {
+            next.writer.mcap_channel_id(&channel)?;
+        }
+        Ok(())
+    }
+
+    /// Writes a metadata record to the current MCAP file.
+    pub(super) fn write_metadata(
+        &self,
+        name: String,
+        metadata: BTreeMap<String, String>,
+    ) -> Result<(), FoxgloveError> {
+        let mut state = self.state.lock();
+        let current = state.current.as_mut().ok_or(FoxgloveError::SinkClosed)?;
+        current.writer.write_metadata(name, metadata)
+    }
+
+    /// Writes an attachment record to the current MCAP file.
+    pub(super) fn attach(
+        &self,
+        name: String,
+        media_type: String,
+        log_time: u64,
+        data: &[u8],
+    ) -> Result<(), FoxgloveError> {
+        let mut state = self.state.lock();
+        let current = state.current.as_mut().ok_or(FoxgloveError::SinkClosed)?;
+        current.writer.attach(name, media_type, log_time, data)
+    }
+
+    /// Finalizes the current MCAP file and flushes it to disk.
+    ///
+    /// Returns the paths of all files written by the sink, or None if the sink was already
+    /// finished.
+    pub(super) fn finish(&self) -> Result<Option<Vec<PathBuf>>, FoxgloveError> {
+        self.flush_timer.lock().take();
+        let mut state = self.state.lock();
+        if std::mem::replace(&mut state.finished, true) {
+            return Ok(None);
+        }
+        if let Some(current) = state.current.take() {
+            current.finish()?;
+        }
+        Ok(Some(std::mem::take(&mut state.paths)))
+    }
+}
+
+impl LogSink for RotatingMcapSink {
+    fn log(
+        &self,
+        channel: &Arc<Channel>,
+        msg: &[u8],
+        metadata: &Metadata,
+    ) -> Result<(), FoxgloveError> {
+        let mut guard = self.state.lock();
+        let state = &mut *guard;
+        if state.finished {
+            return Err(FoxgloveError::SinkClosed);
+        }
+        state
+            .channels
+            .entry(channel.id())
+            .or_insert_with(|| Arc::downgrade(channel));
+        if state
+            .current
+            .as_ref()
+            .is_none_or(|current| self.should_rotate(current))
+        {
+            self.rotate(state)?;
+        }
+        let current = state.current.as_mut().ok_or(FoxgloveError::SinkClosed)?;
+        current.writer.log(channel, msg, metadata)?;
+        current.has_messages = true;
+        Ok(())
+    }
+
+    fn accepts_channel(&self, channel: &Channel) -> bool {
+        self.topic_filter
+            .as_ref()
+            .is_none_or(|f| f.matches_channel(channel))
+    }
+
+    fn add_channel(&self, channel: &Arc<Channel>) {
+        self.state
+            .lock()
+            .channels
+            .insert(channel.id(), Arc::downgrade(channel));
+    }
+
+    fn remove_channel(&self, channel: &Channel) {
+        self.state.lock().channels.remove(&channel.id());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_sink_set::LogSinkSet;
+    use crate::Schema;
+    use std::sync::atomic::AtomicU32;
+
+    fn new_test_channel(id: u64, topic: &str) -> Arc<Channel> {
+        Arc::new(Channel {
+            sinks: LogSinkSet::new(),
+            id: ChannelId::new(id),
+            message_sequence: AtomicU32::new(1),
+            topic: topic.to_string(),
+            message_encoding: "json".to_string(),
+            schema: Some(Schema::new(
+                "schema",
+                "jsonschema",
+                br#"{"type": "object"}"#,
+            )),
+            metadata: Default::default(),
+            latched: 0,
+        })
+    }
+
+    fn read_messages(path: &PathBuf) -> Vec<(String, Vec<u8>)> {
+        let contents = std::fs::read(path).expect("failed to read file");
+        mcap::MessageStream::new(&contents)
+            .expect("failed to open stream")
+            .map(|msg| {
+                let msg = msg.expect("failed to read message");
+                assert_eq!(msg.channel.schema.as_ref().unwrap().name, "schema");
+                (msg.channel.topic.clone(), msg.data.into_owned())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rotate_by_size() {
+        let dir = tempfile::tempdir().expect("failed to create tempdir");
+        let template = dir.path().join("test-{index}.mcap");
+        let options = WriteOptions::new().chunk_size(Some(256)).compression(None);
+        let sink = RotatingMcapSink::new(
+            template.to_string_lossy().into_owned(),
+            options,
+            FlushPolicy::default(),
+            RotationOptions::new().max_bytes(1024),
+            None,
+        )
+        .expect("failed to create sink");
+
+        let ch1 = new_test_channel(1, "/foo");
+        let ch2 = new_test_channel(2, "/bar");
+        sink.add_channel(&ch1);
+        sink.add_channel(&ch2);
+        for i in 0..20 {
+            let msg = [i; 100];
+            sink.log(&ch1, &msg, &Metadata::default())
+                .expect("failed to log");
+        }
+        let paths = sink.finish().expect("failed to finish").unwrap();
+        assert!(paths.len() > 1, "expected multiple files, got {paths:?}");
+        assert_eq!(paths[0], dir.path().join("test-0.mcap"));
+        assert_eq!(paths[1], dir.path().join("test-1.mcap"));
+
+        // Each file is complete, and messages are not lost or duplicated across files.
+        let mut count = 0;
+        for (i, path) in paths.iter().enumerate() {
+            let contents = std::fs::read(path).expect("failed to read file");
+            let summary = mcap::Summary::read(&contents)
+                .expect("failed to read summary")
+                .expect("missing summary");
+            // Known channels are written to each new file, even if they have no messages.
+            if i > 0 {
+                assert_eq!(summary.channels.len(), 2);
+            }
+            for (topic, data) in read_messages(path) {
+                assert_eq!(topic, "/foo");
+                assert_eq!(data, [count; 100]);
+                count += 1;
+            }
+        }
+        assert_eq!(count, 20);
+
+        // Subsequent calls to finish are no-ops.
+        assert!(sink.finish().expect("failed to finish").is_none());
+    }
+
+    #[test]
+    fn test_rotate_with_tiny_max_bytes() {
+        let dir = tempfile::tempdir().expect("failed to create tempdir");
+        let template = dir.path().join("test-{index}.mcap");
+        let sink = RotatingMcapSink::new(
+            template.to_string_lossy().into_owned(),
+            WriteOptions::new(),
+            FlushPolicy::default(),
+            RotationOptions::new().max_bytes(1),
+            None,
+        )
+        .expect("failed to create sink");
+
+        // The limit is smaller than the header, schema and channel records of each file.
+        let ch = new_test_channel(1, "/foo");
+        sink.add_channel(&ch);
+        for i in 0..5 {
+            sink.log(&ch, &[i], &Metadata::default())
+                .expect("failed to log");
+        }
+        let paths = sink.finish().expect("failed to finish").unwrap();
+        assert_eq!(paths.len(), 5);
+        for (i, path) in paths.iter().enumerate() {
+            assert_eq!(read_messages(path), [("/foo".to_string(), vec![i as u8])]);
+        }
+    }
+
+    #[test]
+    fn test_rotate_by_duration() {
+        let dir = tempfile::tempdir().expect("failed to create tempdir");
+        let template = dir.path().join("test-{timestamp}.mcap");
+        let sink = RotatingMcapSink::new(
+            template.to_string_lossy().into_owned(),
+            WriteOptions::new(),
+            FlushPolicy::default(),
+            RotationOptions::new().max_duration(Duration::from_millis(10)),
+            None,
+        )
+        .expect("failed to create sink");
+
+        let ch = new_test_channel(1, "/foo");
+        sink.log(&ch, b"1", &Metadata::default())
+            .expect("failed to log");
+        std::thread::sleep(Duration::from_millis(20));
+        sink.log(&ch, b"2", &Metadata::default())
+            .expect("failed to log");
+        let paths = sink.finish().expect("failed to finish").unwrap();
+        assert_eq!(paths.len(), 2);
+        assert_ne!(paths[0], paths[1]);
+        assert_eq!(
+            read_messages(&paths[0]),
+            [("/foo".to_string(), b"1".to_vec())]
+        );
+        assert_eq!(
+            read_messages(&paths[1]),
+            [("/foo".to_string(), b"2".to_vec())]
+        );
+    }
+
+    #[test]
+    fn test_rotate_skips_existing_files() {
+        let dir = tempfile::tempdir().expect("failed to create tempdir");
+        std::fs::write(dir.path().join("test-1.mcap"), b"existing").expect("failed to write");
+        let template = dir.path().join("test-{index}.mcap");
+        let sink = RotatingMcapSink::new(
+            template.to_string_lossy().into_owned(),
+            WriteOptions::new(),
+            FlushPolicy::default(),
+            RotationOptions::new().max_bytes(1),
+            None,
+        )
+        .expect("failed to create sink");
+
+        let ch = new_test_channel(1, "/foo");
+        for msg in [b"1", b"2", b"3"] {
+            sink.log(&ch, msg, &Metadata::default())
+                .expect("failed to log");
+        }
+        let paths = sink.finish().expect("failed to finish").unwrap();
+        let names: Vec<_> = paths
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, ["test-0.mcap", "test-2.mcap", "test-3.mcap"]);
+        let messages: Vec<_> = paths.iter().flat_map(read_messages).collect();
+        assert_eq!(
+            messages,
+            [
+                ("/foo".to_string(), b"1".to_vec()),
+                ("/foo".to_string(), b"2".to_vec()),
+                ("/foo".to_string(), b"3".to_vec()),
+            ]
+        );
+        assert_eq!(
+            std::fs::read(dir.path().join("test-1.mcap")).unwrap(),
+            b"existing"
+        );
+    }
+}
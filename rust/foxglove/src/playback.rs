@@ -0,0 +1,723 @@
+//! Playback of recorded MCAP files.
+//!
+//! An [`McapPlayer`] replays the messages in an MCAP file into a [`LogContext`]. Channels are
+//! re-created from the recording, with their original schemas and metadata, and messages are
+//! logged with their original log time, publish time, and sequence number. This makes it possible
+//! to feed recorded data through the same sinks that are used for live data.
+//!
+//! # Example
+//! ```no_run
+//! use foxglove::playback::{McapPlayer, PlaybackSpeed};
+//! use foxglove::WebSocketServer;
+//!
+//! # async fn func() -> Result<(), foxglove::FoxgloveError> {
+//! let server = WebSocketServer::new().start().await?;
+//! let playback = McapPlayer::open("recording.mcap")?
+//!     .speed(PlaybackSpeed::Scaled(2.0))?
+//!     .looping(true)
+//!     .start();
+//! // ...
+//! playback.pause();
+//! playback.seek(1_700_000_000_000_000_000);
+//! playback.resume();
+//! // ...
+//! playback.stop()?;
+//! # Ok(()) }
+//! ```
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use mcap::records::{MessageHeader, Record};
+use mcap::sans_io::read::{LinearReader, ReadAction};
+use parking_lot::{Condvar, Mutex};
+
+use crate::{Channel, ChannelBuilder, FoxgloveError, LogContext, PartialMetadata, Schema};
+
+/// The speed at which messages are replayed.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PlaybackSpeed {
+    /// Messages are replayed with the same timing as they were recorded.
+    #[default]
+    RealTime,
+    /// Messages are replayed with their original timing, scaled by the given factor. For example,
+    /// a factor of `2.0` replays messages twice as fast as they were recorded.
+    ///
+    /// The factor must be positive and finite.
+    Scaled(f64),
+    /// Messages are replayed as fast as possible.
+    AsFastAsPossible,
+}
+
+impl PlaybackSpeed {
+    /// Returns an error if the speed is [`PlaybackSpeed::Scaled`] with a factor that is not
+    /// positive and finite.
+    fn validate(self) -> Result<Self, FoxgloveError> {
+        match self {
+            Self::Scaled(factor) if !(factor.is_finite() && factor > 0.0) => {
+                Err(FoxgloveError::InvalidPlaybackSpeed(factor))
+            }
+            _ => Ok(self),
+        }
+    }
+
+    /// Returns the scale factor, or None if messages should be replayed as fast as possible.
+    fn factor(self) -> Option<f64> {
+        match self {
+            Self::RealTime => Some(1.0),
+            Self::Scaled(factor) => Some(factor),
+            Self::AsFastAsPossible => None,
+        }
+    }
+}
+
+/// A player which replays an MCAP recording into a [`LogContext`].
+///
+/// Messages are replayed in the order in which they appear in the file.
+#[must_use]
+#[derive(Debug)]
+pub struct McapPlayer<R> {
+    reader: R,
+    context: Arc<LogContext>,
+    speed: PlaybackSpeed,
+    looping: bool,
+}
+
+impl McapPlayer<BufReader<File>> {
+    /// Opens the MCAP file at the specified path for playback.
+    pub fn open<P>(path: P) -> Result<Self, FoxgloveError>
+    where
+        P: AsRef<Path>,
+    {
+        let file = File::open(path)?;
+        Ok(Self::new(BufReader::new(file)))
+    }
+}
+
+impl<R: Read + Seek + Send + 'static> McapPlayer<R> {
+    /// Creates a new player which reads an MCAP recording from the specified reader.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            context: LogContext::global_arc().clone(),
+            speed: PlaybackSpeed::default(),
+            looping: false,
+        }
+    }
+
+    /// Sets the log context into which messages are replayed.
+    ///
+    /// By default, messages are replayed into the [global context](LogContext::global).
+    pub fn context(mut self, ctx: &Arc<LogContext>) -> Self {
+        self.context = ctx.clone();
+        self
+    }
+
+    /// Sets the playback speed. The default is [`PlaybackSpeed::RealTime`].
+    ///
+    /// Returns an error if the speed is [`PlaybackSpeed::Scaled`] with a factor that is not
+    /// positive and finite.
+    pub fn speed(mut self, speed: PlaybackSpeed) -> Result<Self, FoxgloveError> {
+        self.speed = speed.validate()?;
+        Ok(self)
+    }
+
+    /// Sets whether playback restarts from the beginning when the end of the recording is
+    /// reached. The default is false.
+    pub fn looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    /// Replays the recording on the current thread, and returns when the end of the recording is
+    /// reached.
+    ///
+    /// If looping is enabled, this function only returns on error.
+    ///
+    /// Channels which were created for the recording are removed from the log context when
+    /// playback ends.
+    pub fn run(self) -> Result<(), FoxgloveError> {
+        let control = Arc::new(Control::new(self.speed, self.looping));
+        Worker::new(self.reader, self.context, control).run()
+    }
+
+    /// Starts replaying the recording on a background thread.
+    ///
+    /// Returns a handle, which can be used to control playback. When the handle is dropped,
+    /// playback is stopped.
+    pub fn start(self) -> PlaybackHandle {
+        let control = Arc::new(Control::new(self.speed, self.looping));
+        let worker = Worker::new(self.reader, self.context, control.clone());
+        let thread = std::thread::Builder::new()
+            .name("foxglove-playback".to_string())
+            .spawn(move || worker.run())
+            .expect("Failed to spawn playback thread");
+        PlaybackHandle {
+            control,
+            thread: Some(thread),
+        }
+    }
+}
+
+/// A handle to control a running [`McapPlayer`].
+///
+/// When this handle is dropped, playback is stopped.
+#[must_use]
+#[derive(Debug)]
+pub struct PlaybackHandle {
+    control: Arc<Control>,
+    thread: Option<JoinHandle<Result<(), FoxgloveError>>>,
+}
+
+impl PlaybackHandle {
+    /// Pauses playback.
+    pub fn pause(&self) {
+        self.control.update(|state| state.paused = true);
+    }
+
+    /// Resumes playback after a call to [`PlaybackHandle::pause`].
+    pub fn resume(&self) {
+        self.control.update(|state| {
+            state.paused = false;
+            state.reanchor = true;
+        });
+    }
+
+    /// Returns true if playback is paused.
+    pub fn is_paused(&self) -> bool {
+        self.control.state.lock().paused
+    }
+
+    /// Continues playback from the first message with a log time at or after the specified
+    /// timestamp, in nanoseconds since the unix epoch.
+    pub fn seek(&self, log_time: u64) {
+        self.control.update(|state| {
+            state.seek = Some(log_time);
+            state.reanchor = true;
+        });
+    }
+
+    /// Sets the playback speed.
+    ///
+    /// Returns an error if the speed is [`PlaybackSpeed::Scaled`] with a factor that is not
+    /// positive and finite.
+    pub fn set_speed(&self, speed: PlaybackSpeed) -> Result<(), FoxgloveError> {
+        let speed = speed.validate()?;
+        self.control.update(|state| {
+            state.speed = speed;
+            state.reanchor = true;
+        });
+        Ok(())
+    }
+
+    /// Returns the log time of the most recently replayed message, in nanoseconds since the unix
+    /// epoch, or None if no message has been replayed yet.
+    pub fn log_time(&self) -> Option<u64> {
+        self.control.state.lock().log_time
+    }
+
+    /// Sets whether playback restarts from the beginning when the end of the recording is
+    /// reached.
+    pub fn set_looping(&self, looping: bool) {
+        self.control.update(|state| state.looping = looping);
+    }
+
+    /// Returns true if playback has ended, either because the end of the recording was reached,
+    /// or because an error occurred.
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().is_none_or(|t| t.is_finished())
+    }
+
+    /// Waits for playback to reach the end of the recording.
+    ///
+    /// If looping is enabled, this blocks until playback is stopped by an error, or until looping
+    /// is disabled and the end of the recording is reached.
+    pub fn wait(mut self) -> Result<(), FoxgloveError> {
+        self.join()
+    }
+
+    /// Stops playback and waits for the playback thread to exit.
+    pub fn stop(mut self) -> Result<(), FoxgloveError> {
+        self.control.update(|state| state.stopped = true);
+        self.join()
+    }
+
+    fn join(&mut self) -> Result<(), FoxgloveError> {
+        let Some(thread) = self.thread.take() else {
+            return Ok(());
+        };
+        thread.join().unwrap_or_else(|_| {
+            Err(FoxgloveError::Unspecified(
+                "playback thread panicked".into(),
+            ))
+        })
+    }
+}
+
+impl Drop for PlaybackHandle {
+    fn drop(&mut self) {
+        self.control.update(|state| state.stopped = true);
+        if let Err(e) = self.join() {
+            tracing::warn!("{e}");
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ControlState {
+    paused: bool,
+    stopped: bool,
+    looping: bool,
+    speed: PlaybackSpeed,
+    seek: Option<u64>,
+    log_time: Option<u64>,
+    // Set when the relationship between log time and wall time must be re-established.
+    reanchor: bool,
+}
+
+/// Playback state shared between the handle and the worker.
+#[derive(Debug)]
+struct Control {
+    state: Mutex<ControlState>,
+    cond: Condvar,
+}
+
+impl Control {
+    fn new(speed: PlaybackSpeed, looping: bool) -> Self {
+        Self {
+            state: Mutex::new(ControlState {
+                paused: false,
+                stopped: false,
+                looping,
+                speed,
+                seek: None,
+                log_time: None,
+                reanchor: false,
+            }),
+            cond: Condvar::new(),
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut ControlState)) {
+        f(&mut self.state.lock());
+        self.cond.notify_all();
+    }
+}
+
+/// The worker's next step for a message.
+enum Step {
+    Log,
+    Skip,
+    Rewind,
+    Stop,
+}
+
+/// Maps log time to wall time.
+struct Anchor {
+    instant: Instant,
+    log_time: u64,
+}
+
+struct Worker<R> {
+    reader: R,
+    context: Arc<LogContext>,
+    control: Arc<Control>,
+    schemas: HashMap<u16, Schema>,
+    // MCAP channel ID -> channel
+    channels: HashMap<u16, Arc<Channel>>,
+    // Channels created by this player, by topic.
+    topics: HashMap<String, Arc<Channel>>,
+    anchor: Option<Anchor>,
+    skip_until: Option<u64>,
+    last_log_time: Option<u64>,
+}
+
+impl<R: Read + Seek> Worker<R> {
+    fn new(reader: R, context: Arc<LogContext>, control: Arc<Control>) -> Self {
+        Self {
+            reader,
+            context,
+            control,
+            schemas: HashMap::new(),
+            channels: HashMap::new(),
+            topics: HashMap::new(),
+            anchor: None,
+            skip_until: None,
+            last_log_time: None,
+        }
+    }
+
+    fn run(mut self) -> Result<(), FoxgloveError> {
+        loop {
+            self.reader.seek(SeekFrom::Start(0))?;
+            self.last_log_time = None;
+            let mut linear_reader = LinearReader::new();
+            let mut rewind = false;
+            while let Some(action) = linear_reader.next_action() {
+                match action? {
+                    ReadAction::NeedMore(count) => {
+                        let count = self.reader.read(linear_reader.insert(count))?;
+                        linear_reader.set_written(count);
+                    }
+                    ReadAction::GetRecord { data, opcode } => {
+                        match self.handle_record(mcap::parse_record(opcode, data)?)? {
+                            Step::Log | Step::Skip => (),
+                            Step::Rewind => {
+                                rewind = true;
+                                break;
+                            }
+                            Step::Stop => return Ok(()),
+                        }
+                    }
+                }
+            }
+            if rewind {
+                continue;
+            }
+            if !self.control.state.lock().looping {
+                return Ok(());
+            }
+            self.anchor = None;
+        }
+    }
+
+    fn handle_record(&mut self, record: Record<'_>) -> Result<Step, FoxgloveError> {
+        match record {
+            // Schema ID 0 indicates a channel without a schema.
+            Record::Schema { header, data } if header.id != 0 => {
+                self.schemas.entry(header.id).or_insert_with(|| {
+                    Schema::new(header.name, header.encoding, data.into_owned())
+                });
+            }
+            Record::Channel(record) if !self.channels.contains_key(&record.id) => {
+                // Multiple MCAP channels on the same topic share a single channel.
+                if let Some(channel) = self.topics.get(&record.topic) {
+                    self.channels.insert(record.id, channel.clone());
+                    return Ok(Step::Log);
+                }
+                let result = ChannelBuilder::new(&record.topic)
+                    .message_encoding(&record.message_encoding)
+                    .schema(self.schemas.get(&record.schema_id).cloned())
+                    .metadata(record.metadata)
+                    .context(&self.context)
+                    .build();
+                match result {
+                    Ok(channel) => {
+                        self.topics.insert(record.topic, channel.clone());
+                        self.channels.insert(record.id, channel);
+                    }
+                    // The topic is already in use by a channel which was not created by this
+                    // player. Log to the existing channel, and leave it in place when playback
+                    // ends. If the channel was removed in the meantime, skip its messages.
+                    Err(FoxgloveError::DuplicateChannel(topic)) => {
+                        match self.context.get_channel_by_topic(&topic) {
+                            Some(channel) => {
+                                tracing::warn!("Replaying {topic} on an existing channel");
+                                self.channels.insert(record.id, channel);
+                            }
+                            None => tracing::warn!("Skipping messages on {topic}"),
+                        }
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            Record::Message { header, data } => {
+                let step = self.wait_for(header.log_time);
+                if matches!(step, Step::Log) {
+                    self.log(&header, &data);
+                }
+                return Ok(step);
+            }
+            _ => (),
+        }
+        Ok(Step::Log)
+    }
+
+    fn log(&mut self, header: &MessageHeader, data: &[u8]) {
+        self.last_log_time = Some(header.log_time);
+        if let Some(channel) = self.channels.get(&header.channel_id) {
+            channel.log_with_meta(
+                data,
+                PartialMetadata {
+                    sequence: Some(header.sequence),
+                    log_time: Some(header.log_time),
+                    publish_time: Some(header.publish_time),
+                },
+            );
+        }
+    }
+
+    /// Waits until the message with the specified log time is due to be logged.
+    fn wait_for(&mut self, log_time: u64) -> Step {
+        let mut state = self.control.state.lock();
+        loop {
+            if state.stopped {
+                return Step::Stop;
+            }
+            if let Some(seek) = state.seek.take() {
+                self.skip_until = Some(seek);
+                if self.last_log_time.is_some_and(|t| seek < t) {
+                    return Step::Rewind;
+                }
+            }
+            if let Some(skip_until) = self.skip_until {
+                if log_time < skip_until {
+                    return Step::Skip;
+                }
+                self.skip_until = None;
+            }
+            if state.paused {
+                self.control.cond.wait(&mut state);
+                continue;
+            }
+            if std::mem::take(&mut state.reanchor) {
+                self.anchor = None;
+            }
+            let Some(factor) = state.speed.factor() else {
+                state.log_time = Some(log_time);
+                return Step::Log;
+            };
+            let anchor = self.anchor.get_or_insert_with(|| Anchor {
+                instant: Instant::now(),
+                log_time,
+            });
+            let offset = log_time.saturating_sub(anchor.log_time) as f64 / factor;
+            let deadline = anchor.instant + Duration::from_secs_f64(offset / 1e9);
+            if Instant::now() >= deadline {
+                state.log_time = Some(log_time);
+                return Step::Log;
+            }
+            self.control.cond.wait_until(&mut state, deadline);
+        }
+    }
+}
+
+impl<R> Drop for Worker<R> {
+    fn drop(&mut self) {
+        // Remove the channels created for playback, unless they have since been replaced.
+        for (topic, channel) in self.topics.drain() {
+            if self
+                .context
+                .get_channel_by_topic(&topic)
+                .is_some_and(|c| Arc::ptr_eq(&c, &channel))
+            {
+                self.context.remove_channel_for_topic(&topic);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::RecordingSink;
+    use crate::McapWriter;
+    use std::collections::BTreeMap;
+    use std::io::Cursor;
+
+    const MS: u64 = 1_000_000;
+
+    /// Writes a recording with messages on two topics, at the specified log times.
+    fn make_recording(log_times: &[u64]) -> Cursor<Vec<u8>> {
+        let ctx = Arc::new(LogContext::new());
+        let writer = McapWriter::new()
+            .context(&ctx)
+            .create(Cursor::new(Vec::new()))
+            .expect("failed to create writer");
+        let foo = ChannelBuilder::new("/foo")
+            .message_encoding("json")
+            .schema(Schema::new("foo", "jsonschema", br#"{"type": "object"}"#))
+            .metadata(BTreeMap::from([("key".to_string(), "value".to_string())]))
+            .context(&ctx)
+            .build()
+            .unwrap();
+        let bar = ChannelBuilder::new("/bar")
+            .message_encoding("raw")
+            .context(&ctx)
+            .build()
+            .unwrap();
+        for (i, &log_time) in log_times.iter().enumerate() {
+            let channel = if i % 2 == 0 { &foo } else { &bar };
+            channel.log_with_meta(
+                i.to_string().as_bytes(),
+                PartialMetadata {
+                    sequence: Some(i as u32),
+                    log_time: Some(log_time),
+                    publish_time: Some(log_time + 1),
+                },
+            );
+        }
+        let mut cursor = writer.close().expect("failed to close writer");
+        cursor.set_position(0);
+        cursor
+    }
+
+    fn new_context() -> (Arc<LogContext>, Arc<RecordingSink>) {
+        let ctx = Arc::new(LogContext::new());
+        let sink = Arc::new(RecordingSink::new());
+        ctx.add_sink(sink.clone());
+        (ctx, sink)
+    }
+
+    fn recorded(sink: &RecordingSink) -> Vec<String> {
+        sink.recorded
+            .lock()
+            .iter()
+            .map(|c| String::from_utf8(c.msg.clone()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_run_as_fast_as_possible() {
+        let (ctx, sink) = new_context();
+        McapPlayer::new(make_recording(&[10, 20, 30, 40]))
+            .context(&ctx)
+            .speed(PlaybackSpeed::AsFastAsPossible)
+            .unwrap()
+            .run()
+            .expect("playback failed");
+
+        let calls = sink.recorded.lock();
+        assert_eq!(calls.len(), 4);
+        for (i, call) in calls.iter().enumerate() {
+            assert_eq!(call.msg, i.to_string().as_bytes());
+            assert_eq!(call.metadata.sequence, i as u32);
+            assert_eq!(call.metadata.log_time, (i as u64 + 1) * 10);
+            assert_eq!(call.metadata.publish_time, (i as u64 + 1) * 10 + 1);
+        }
+
+        let foo = &calls[0].channel;
+        assert_eq!(foo.topic(), "/foo");
+        assert_eq!(foo.message_encoding, "json");
+        assert_eq!(foo.schema().unwrap().name, "foo");
+        assert_eq!(foo.metadata.get("key").unwrap(), "value");
+        let bar = &calls[1].channel;
+        assert_eq!(bar.topic(), "/bar");
+        assert_eq!(bar.message_encoding, "raw");
+        assert!(bar.schema().is_none());
+
+        // Channels are removed from the context once playback ends.
+        assert!(ctx.get_channel_by_topic("/foo").is_none());
+        assert!(ctx.get_channel_by_topic("/bar").is_none());
+    }
+
+    #[test]
+    fn test_invalid_speed() {
+        for factor in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let result = McapPlayer::new(make_recording(&[])).speed(PlaybackSpeed::Scaled(factor));
+            assert!(matches!(
+                result,
+                Err(FoxgloveError::InvalidPlaybackSpeed(_))
+            ));
+        }
+
+        let (ctx, _sink) = new_context();
+        let handle = McapPlayer::new(make_recording(&[0, 10]))
+            .context(&ctx)
+            .start();
+        handle.pause();
+        assert!(matches!(
+            handle.set_speed(PlaybackSpeed::Scaled(0.0)),
+            Err(FoxgloveError::InvalidPlaybackSpeed(_))
+        ));
+        handle.set_speed(PlaybackSpeed::Scaled(2.0)).unwrap();
+        handle.stop().expect("playback failed");
+    }
+
+    #[test]
+    fn test_existing_channel() {
+        let (ctx, sink) = new_context();
+        let existing = ChannelBuilder::new("/foo")
+            .message_encoding("json")
+            .context(&ctx)
+            .build()
+            .unwrap();
+        McapPlayer::new(make_recording(&[10, 20, 30, 40]))
+            .context(&ctx)
+            .speed(PlaybackSpeed::AsFastAsPossible)
+            .unwrap()
+            .run()
+            .expect("playback failed");
+
+        // Messages on the existing topic are logged to the existing channel.
+        let calls = sink.recorded.lock();
+        assert_eq!(calls.len(), 4);
+        assert!(Arc::ptr_eq(&calls[0].channel, &existing));
+        assert!(Arc::ptr_eq(&calls[2].channel, &existing));
+        assert_eq!(calls[1].channel.topic(), "/bar");
+
+        // The existing channel is left in place when playback ends.
+        let channel = ctx.get_channel_by_topic("/foo").unwrap();
+        assert!(Arc::ptr_eq(&channel, &existing));
+        assert!(ctx.get_channel_by_topic("/bar").is_none());
+    }
+
+    #[test]
+    fn test_real_time() {
+        let (ctx, sink) = new_context();
+        let start = Instant::now();
+        McapPlayer::new(make_recording(&[0, 50 * MS, 100 * MS]))
+            .context(&ctx)
+            .speed(PlaybackSpeed::Scaled(2.0))
+            .unwrap()
+            .run()
+            .expect("playback failed");
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(recorded(&sink), ["0", "1", "2"]);
+    }
+
+    #[test]
+    fn test_pause_and_seek() {
+        let (ctx, sink) = new_context();
+        let log_times: Vec<_> = (0..10).map(|i| i * 100 * MS).collect();
+        let handle = McapPlayer::new(make_recording(&log_times))
+            .context(&ctx)
+            .start();
+
+        // Skip forward while paused.
+        handle.pause();
+        assert!(handle.is_paused());
+        handle.seek(800 * MS);
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!handle.is_finished());
+        handle.resume();
+
+        let start = Instant::now();
+        handle.wait().expect("playback failed");
+        assert!(start.elapsed() < Duration::from_millis(500));
+        let msgs = recorded(&sink);
+        assert_eq!(msgs[msgs.len() - 2..], ["8", "9"]);
+        assert!(msgs.len() <= 3, "unexpected messages: {msgs:?}");
+    }
+
+    #[test]
+    fn test_loop() {
+        let (ctx, sink) = new_context();
+        let handle = McapPlayer::new(make_recording(&[0, 10, 20]))
+            .context(&ctx)
+            .speed(PlaybackSpeed::AsFastAsPossible)
+            .unwrap()
+            .looping(true)
+            .start();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while sink.recorded.lock().len() < 9 {
+            assert!(Instant::now() < deadline, "timed out");
+            std::thread::yield_now();
+        }
+        assert!(handle.log_time().is_some());
+        handle.set_looping(false);
+        handle.wait().expect("playback failed");
+
+        let msgs = recorded(&sink);
+        assert!(msgs.len() >= 9);
+        assert_eq!(msgs.len() % 3, 0);
+        for chunk in msgs.chunks(3) {
+            assert_eq!(chunk, ["0", "1", "2"]);
+        }
+    }
+}
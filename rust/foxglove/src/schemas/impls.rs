@@ -1,6 +1,6 @@
 // This file is @generated by foxglove-proto-gen
 use crate::schemas::{descriptors, foxglove::*};
-use crate::{Schema, Encode};
+use crate::{Schema, Encode, Decode};
 use bytes::BufMut;
 
 impl Encode for CameraCalibration {
@@ -25,6 +25,14 @@ impl Encode for CameraCalibration {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for CameraCalibration {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for CircleAnnotation {
     type Error = ::prost::EncodeError;
 
@@ -47,6 +55,14 @@ impl Encode for CircleAnnotation {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for CircleAnnotation {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for Color {
     type Error = ::prost::EncodeError;
 
@@ -69,6 +85,14 @@ impl Encode for Color {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for Color {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for CompressedImage {
     type Error = ::prost::EncodeError;
 
@@ -91,6 +115,14 @@ impl Encode for CompressedImage {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for CompressedImage {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for CompressedVideo {
     type Error = ::prost::EncodeError;
 
@@ -113,6 +145,14 @@ impl Encode for CompressedVideo {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for CompressedVideo {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for FrameTransform {
     type Error = ::prost::EncodeError;
 
@@ -135,6 +175,14 @@ impl Encode for FrameTransform {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for FrameTransform {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for FrameTransforms {
     type Error = ::prost::EncodeError;
 
@@ -157,6 +205,14 @@ impl Encode for FrameTransforms {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for FrameTransforms {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for GeoJson {
     type Error = ::prost::EncodeError;
 
@@ -179,6 +235,14 @@ impl Encode for GeoJson {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for GeoJson {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for Grid {
     type Error = ::prost::EncodeError;
 
@@ -201,6 +265,14 @@ impl Encode for Grid {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for Grid {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for ImageAnnotations {
     type Error = ::prost::EncodeError;
 
@@ -223,6 +295,14 @@ impl Encode for ImageAnnotations {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for ImageAnnotations {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for KeyValuePair {
     type Error = ::prost::EncodeError;
 
@@ -245,6 +325,14 @@ impl Encode for KeyValuePair {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for KeyValuePair {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for LaserScan {
     type Error = ::prost::EncodeError;
 
@@ -267,6 +355,14 @@ impl Encode for LaserScan {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for LaserScan {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for LocationFix {
     type Error = ::prost::EncodeError;
 
@@ -289,6 +385,14 @@ impl Encode for LocationFix {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for LocationFix {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for Log {
     type Error = ::prost::EncodeError;
 
@@ -311,6 +415,14 @@ impl Encode for Log {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for Log {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for PackedElementField {
     type Error = ::prost::EncodeError;
 
@@ -333,6 +445,14 @@ impl Encode for PackedElementField {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for PackedElementField {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for Point2 {
     type Error = ::prost::EncodeError;
 
@@ -355,6 +475,14 @@ impl Encode for Point2 {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for Point2 {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for Point3 {
     type Error = ::prost::EncodeError;
 
@@ -377,6 +505,14 @@ impl Encode for Point3 {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for Point3 {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for PointCloud {
     type Error = ::prost::EncodeError;
 
@@ -399,6 +535,14 @@ impl Encode for PointCloud {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for PointCloud {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for PointsAnnotation {
     type Error = ::prost::EncodeError;
 
@@ -421,6 +565,14 @@ impl Encode for PointsAnnotation {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for PointsAnnotation {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for Pose {
     type Error = ::prost::EncodeError;
 
@@ -443,6 +595,14 @@ impl Encode for Pose {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for Pose {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for PoseInFrame {
     type Error = ::prost::EncodeError;
 
@@ -465,6 +625,14 @@ impl Encode for PoseInFrame {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for PoseInFrame {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for PosesInFrame {
     type Error = ::prost::EncodeError;
 
@@ -487,6 +655,14 @@ impl Encode for PosesInFrame {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for PosesInFrame {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for Quaternion {
     type Error = ::prost::EncodeError;
 
@@ -509,6 +685,14 @@ impl Encode for Quaternion {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for Quaternion {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for RawImage {
     type Error = ::prost::EncodeError;
 
@@ -531,6 +715,14 @@ impl Encode for RawImage {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for RawImage {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for SceneEntity {
     type Error = ::prost::EncodeError;
 
@@ -553,6 +745,14 @@ impl Encode for SceneEntity {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for SceneEntity {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for SceneEntityDeletion {
     type Error = ::prost::EncodeError;
 
@@ -575,6 +775,14 @@ impl Encode for SceneEntityDeletion {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for SceneEntityDeletion {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for SceneUpdate {
     type Error = ::prost::EncodeError;
 
@@ -597,6 +805,14 @@ impl Encode for SceneUpdate {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for SceneUpdate {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for TextAnnotation {
     type Error = ::prost::EncodeError;
 
@@ -619,6 +835,14 @@ impl Encode for TextAnnotation {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for TextAnnotation {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for Vector2 {
     type Error = ::prost::EncodeError;
 
@@ -641,6 +865,14 @@ impl Encode for Vector2 {
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
 
+impl Decode for Vector2 {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
+
 impl Encode for Vector3 {
     type Error = ::prost::EncodeError;
 
@@ -662,3 +894,11 @@ impl Encode for Vector3 {
 
     fn encoded_len(&self) -> Option<usize> { Some(::prost::Message::encoded_len(self)) }
 }
+
+impl Decode for Vector3 {
+    type Error = ::prost::DecodeError;
+
+    fn decode(buf: &[u8]) -> Result<Self, ::prost::DecodeError> {
+        ::prost::Message::decode(buf)
+    }
+}
@@ -1,9 +1,13 @@
 use crate::testutil::GlobalContextTest;
-use crate::{ChannelBuilder, McapWriter, Schema, WebSocketServer};
+use crate::{
+    ChannelBuilder, LogContext, McapWriter, Schema, TopicFilter, TopicPattern, WebSocketServer,
+};
 use futures_util::{FutureExt, SinkExt, StreamExt};
 use serde_json::json;
 use std::{
-    io::{BufReader, BufWriter, Read, Seek},
+    collections::BTreeMap,
+    io::{BufReader, BufWriter, Cursor, Read, Seek},
+    sync::Arc,
     time::Duration,
 };
 use tempfile::NamedTempFile;
@@ -172,6 +176,171 @@ async fn test_logging_to_file_and_live_sinks() {
     server.stop().await;
 }
 
+#[test]
+fn test_logging_to_separate_contexts() {
+    let _cleanup = GlobalContextTest::new();
+
+    let ctx1 = Arc::new(LogContext::new());
+    let ctx2 = Arc::new(LogContext::new());
+
+    let mcap1 = McapWriter::new()
+        .context(&ctx1)
+        .create(Cursor::new(Vec::new()))
+        .expect("Failed to create writer");
+    let mcap2 = McapWriter::new()
+        .context(&ctx2)
+        .create(Cursor::new(Vec::new()))
+        .expect("Failed to create writer");
+    let mcap_global = McapWriter::new()
+        .create(Cursor::new(Vec::new()))
+        .expect("Failed to create writer");
+
+    // The same topic can be registered in each context.
+    let ch1 = ChannelBuilder::new("/topic")
+        .message_encoding("json")
+        .context(&ctx1)
+        .build()
+        .expect("Failed to create channel");
+    let ch2 = ChannelBuilder::new("/topic")
+        .message_encoding("json")
+        .context(&ctx2)
+        .build()
+        .expect("Failed to create channel");
+    assert!(ChannelBuilder::new("/topic")
+        .message_encoding("json")
+        .context(&ctx1)
+        .build()
+        .is_err());
+
+    ch1.log(b"one");
+    ch2.log(b"two");
+
+    let read_payloads = |buf: Vec<u8>| -> Vec<Vec<u8>> {
+        mcap::MessageStream::new(&buf)
+            .expect("Failed to create message stream")
+            .map(|m| m.expect("Failed to read message").data.to_vec())
+            .collect()
+    };
+    let buf1 = mcap1.close().expect("Failed to close").into_inner();
+    let buf2 = mcap2.close().expect("Failed to close").into_inner();
+    let buf_global = mcap_global.close().expect("Failed to close").into_inner();
+    assert_eq!(read_payloads(buf1), vec![b"one".to_vec()]);
+    assert_eq!(read_payloads(buf2), vec![b"two".to_vec()]);
+    assert!(read_payloads(buf_global).is_empty());
+    assert!(LogContext::global()
+        .get_channel_by_topic("/topic")
+        .is_none());
+}
+
+#[test]
+fn test_logging_with_topic_filters() {
+    let ctx = Arc::new(LogContext::new());
+
+    let camera = McapWriter::new()
+        .context(&ctx)
+        .topic_filter(TopicFilter::new().include(TopicPattern::glob("/camera/**").unwrap()))
+        .create(Cursor::new(Vec::new()))
+        .expect("Failed to create writer");
+    let other = McapWriter::new()
+        .context(&ctx)
+        .topic_filter(TopicFilter::new().exclude(TopicPattern::regex("/camera/.*").unwrap()))
+        .create(Cursor::new(Vec::new()))
+        .expect("Failed to create writer");
+
+    for topic in ["/camera/front", "/camera/rear/info", "/diagnostics"] {
+        let channel = ChannelBuilder::new(topic)
+            .message_encoding("json")
+            .context(&ctx)
+            .build()
+            .expect("Failed to create channel");
+        channel.log(topic.as_bytes());
+    }
+
+    let read_topics = |buf: Vec<u8>| -> Vec<String> {
+        mcap::MessageStream::new(&buf)
+            .expect("Failed to create message stream")
+            .map(|m| m.expect("Failed to read message").channel.topic.clone())
+            .collect()
+    };
+    let camera = camera.close().expect("Failed to close").into_inner();
+    let other = other.close().expect("Failed to close").into_inner();
+    assert_eq!(read_topics(camera), ["/camera/front", "/camera/rear/info"]);
+    assert_eq!(read_topics(other), ["/diagnostics"]);
+}
+
+#[test]
+fn test_mcap_metadata_and_attachments() {
+    let ctx = Arc::new(LogContext::new());
+    let mcap = McapWriter::new()
+        .context(&ctx)
+        .create(Cursor::new(Vec::new()))
+        .expect("Failed to create writer");
+
+    mcap.write_metadata(
+        "build",
+        BTreeMap::from([("version".to_string(), "1.2.3".to_string())]),
+    )
+    .expect("Failed to write metadata");
+    mcap.attach("robot.urdf", "application/xml", 42, b"<robot/>")
+        .expect("Failed to write attachment");
+
+    let buf = mcap.close().expect("Failed to close").into_inner();
+    let summary = mcap::Summary::read(&buf)
+        .expect("Failed to read summary")
+        .expect("Missing summary");
+
+    assert_eq!(summary.metadata_indexes.len(), 1);
+    let metadata =
+        mcap::read::metadata(&buf, &summary.metadata_indexes[0]).expect("Failed to read metadata");
+    assert_eq!(metadata.name, "build");
+    assert_eq!(metadata.metadata["version"], "1.2.3");
+
+    assert_eq!(summary.attachment_indexes.len(), 1);
+    let attachment = mcap::read::attachment(&buf, &summary.attachment_indexes[0])
+        .expect("Failed to read attachment");
+    assert_eq!(attachment.name, "robot.urdf");
+    assert_eq!(attachment.media_type, "application/xml");
+    assert_eq!(attachment.log_time, 42);
+    assert_eq!(&*attachment.data, b"<robot/>");
+}
+
+#[test]
+fn test_logging_to_stream() {
+    let ctx = Arc::new(LogContext::new());
+    // `Vec<u8>` implements `Write`, but not `Seek`.
+    let mcap = McapWriter::new()
+        .context(&ctx)
+        .create_stream(Vec::new())
+        .expect("Failed to create writer");
+
+    let channel = ChannelBuilder::new("/topic")
+        .message_encoding("json")
+        .schema(Schema::new(
+            "schema",
+            "jsonschema",
+            br#"{"type": "object"}"#,
+        ))
+        .context(&ctx)
+        .build()
+        .expect("Failed to create channel");
+    channel.log(b"one");
+    channel.log(b"two");
+
+    let buf: Vec<u8> = mcap.close().expect("Failed to close");
+    let messages: Vec<_> = mcap::MessageStream::new(&buf)
+        .expect("Failed to create message stream")
+        .map(|m| m.expect("Failed to read message").data.to_vec())
+        .collect();
+    assert_eq!(messages, vec![b"one".to_vec(), b"two".to_vec()]);
+
+    // The summary section is present, and can be used for indexed reads.
+    let summary = mcap::Summary::read(&buf)
+        .expect("Failed to read summary")
+        .expect("Missing summary");
+    assert_eq!(summary.stats.expect("Missing statistics").message_count, 2);
+    assert_eq!(summary.chunk_indexes.len(), 1);
+}
+
 fn ws_msg_to_json(msg: Message) -> serde_json::Value {
     let data = msg
         .into_text()
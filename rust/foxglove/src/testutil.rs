@@ -10,6 +10,7 @@ use crate::websocket::{
 pub use log_context::GlobalContextTest;
 pub use log_sink::{ErrorSink, MockSink, RecordingSink};
 use parking_lot::Mutex;
+use std::net::SocketAddr;
 
 #[allow(dead_code)]
 pub(crate) struct ClientChannelInfo {
@@ -61,7 +62,27 @@ pub(crate) struct SetParameters {
     pub request_id: Option<String>,
 }
 
+pub(crate) struct ClientInfo {
+    pub(crate) id: ClientId,
+    pub(crate) addr: SocketAddr,
+    pub(crate) user_agent: Option<String>,
+    pub(crate) path: String,
+}
+
+impl From<Client<'_>> for ClientInfo {
+    fn from(client: Client) -> Self {
+        Self {
+            id: client.id(),
+            addr: client.addr(),
+            user_agent: client.user_agent().map(String::from),
+            path: client.path().to_string(),
+        }
+    }
+}
+
 pub(crate) struct RecordingServerListener {
+    client_connect: Mutex<Vec<ClientInfo>>,
+    client_disconnect: Mutex<Vec<ClientInfo>>,
     message_data: Mutex<Vec<MessageData>>,
     subscribe: Mutex<Vec<(ClientId, ChannelInfo)>>,
     unsubscribe: Mutex<Vec<(ClientId, ChannelInfo)>>,
@@ -72,11 +93,16 @@ pub(crate) struct RecordingServerListener {
     parameters_get: Mutex<Vec<GetParameters>>,
     parameters_set: Mutex<Vec<SetParameters>>,
     parameters_get_result: Mutex<Vec<Parameter>>,
+    connection_graph_subscribe: Mutex<usize>,
+    connection_graph_unsubscribe: Mutex<usize>,
+    slow_client_disconnect: Mutex<Vec<(ClientId, String)>>,
 }
 
 impl RecordingServerListener {
     pub fn new() -> Self {
         Self {
+            client_connect: Mutex::new(Vec::new()),
+            client_disconnect: Mutex::new(Vec::new()),
             message_data: Mutex::new(Vec::new()),
             subscribe: Mutex::new(Vec::new()),
             unsubscribe: Mutex::new(Vec::new()),
@@ -87,9 +113,20 @@ impl RecordingServerListener {
             parameters_get: Mutex::new(Vec::new()),
             parameters_set: Mutex::new(Vec::new()),
             parameters_get_result: Mutex::new(Vec::new()),
+            connection_graph_subscribe: Mutex::new(0),
+            connection_graph_unsubscribe: Mutex::new(0),
+            slow_client_disconnect: Mutex::new(Vec::new()),
         }
     }
 
+    pub fn take_client_connect(&self) -> Vec<ClientInfo> {
+        std::mem::take(&mut self.client_connect.lock())
+    }
+
+    pub fn take_client_disconnect(&self) -> Vec<ClientInfo> {
+        std::mem::take(&mut self.client_disconnect.lock())
+    }
+
     pub fn take_message_data(&self) -> Vec<MessageData> {
         std::mem::take(&mut self.message_data.lock())
     }
@@ -129,9 +166,29 @@ impl RecordingServerListener {
     pub fn take_parameters_set(&self) -> Vec<SetParameters> {
         std::mem::take(&mut self.parameters_set.lock())
     }
+
+    pub fn take_connection_graph_subscribe(&self) -> usize {
+        std::mem::take(&mut self.connection_graph_subscribe.lock())
+    }
+
+    pub fn take_slow_client_disconnect(&self) -> Vec<(ClientId, String)> {
+        std::mem::take(&mut self.slow_client_disconnect.lock())
+    }
+
+    pub fn take_connection_graph_unsubscribe(&self) -> usize {
+        std::mem::take(&mut self.connection_graph_unsubscribe.lock())
+    }
 }
 
 impl ServerListener for RecordingServerListener {
+    fn on_client_connect(&self, client: Client) {
+        self.client_connect.lock().push(client.into());
+    }
+
+    fn on_client_disconnect(&self, client: Client) {
+        self.client_disconnect.lock().push(client.into());
+    }
+
     fn on_message_data(&self, client: Client, channel: ClientChannelView, payload: &[u8]) {
         let mut data = self.message_data.lock();
         data.push(MessageData {
@@ -200,4 +257,18 @@ impl ServerListener for RecordingServerListener {
         let mut unsubs = self.parameters_unsubscribe.lock();
         unsubs.push(param_names.clone());
     }
+
+    fn on_slow_client_disconnect(&self, client: Client, reason: &str) {
+        self.slow_client_disconnect
+            .lock()
+            .push((client.id(), reason.to_string()));
+    }
+
+    fn on_connection_graph_subscribe(&self) {
+        *self.connection_graph_subscribe.lock() += 1;
+    }
+
+    fn on_connection_graph_unsubscribe(&self) {
+        *self.connection_graph_unsubscribe.lock() += 1;
+    }
 }
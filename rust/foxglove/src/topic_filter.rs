@@ -0,0 +1,175 @@
+//! Topic filtering for sinks.
+
+use crate::{Channel, FoxgloveError};
+use regex::Regex;
+
+/// A pattern that matches channel topics.
+///
+/// Patterns are either globs or regular expressions, and are always matched against the whole
+/// topic.
+#[derive(Debug, Clone)]
+pub struct TopicPattern(Regex);
+
+impl TopicPattern {
+    /// Creates a pattern from a glob.
+    ///
+    /// `*` matches any sequence of characters within a single topic segment (i.e. excluding `/`),
+    /// `**` matches any sequence of characters including `/`, and `?` matches any single
+    /// character other than `/`. All other characters match literally.
+    ///
+    /// For example, `/camera/*` matches `/camera/front` but not `/camera/front/info`, whereas
+    /// `/camera/**` matches both.
+    pub fn glob(pattern: &str) -> Result<Self, FoxgloveError> {
+        let mut re = String::with_capacity(pattern.len() * 2 + 2);
+        re.push('^');
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    re.push_str(".*");
+                }
+                '*' => re.push_str("[^/]*"),
+                '?' => re.push_str("[^/]"),
+                c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+            }
+        }
+        re.push('$');
+        Self::compile(&re, pattern)
+    }
+
+    /// Creates a pattern from a regular expression.
+    ///
+    /// The expression must match the entire topic. See the [regex crate documentation] for the
+    /// supported syntax.
+    ///
+    /// [regex crate documentation]: https://docs.rs/regex/latest/regex/#syntax
+    pub fn regex(pattern: &str) -> Result<Self, FoxgloveError> {
+        Self::compile(&format!("^(?:{pattern})$"), pattern)
+    }
+
+    fn compile(re: &str, pattern: &str) -> Result<Self, FoxgloveError> {
+        Regex::new(re)
+            .map(Self)
+            .map_err(|e| FoxgloveError::InvalidTopicPattern(format!("{pattern}: {e}")))
+    }
+
+    /// Returns true if the pattern matches the topic.
+    pub fn is_match(&self, topic: &str) -> bool {
+        self.0.is_match(topic)
+    }
+}
+
+/// A set of include and exclude patterns which select the channels delivered to a sink.
+///
+/// A channel is accepted if its topic matches at least one include pattern (or there are no
+/// include patterns), and it does not match any exclude pattern.
+///
+/// # Example
+/// ```
+/// use foxglove::{TopicFilter, TopicPattern};
+///
+/// # fn func() -> Result<(), foxglove::FoxgloveError> {
+/// let filter = TopicFilter::new()
+///     .include(TopicPattern::glob("/camera/**")?)
+///     .exclude(TopicPattern::regex(r".*/compressed")?);
+/// assert!(filter.matches("/camera/front/image"));
+/// assert!(!filter.matches("/camera/front/compressed"));
+/// assert!(!filter.matches("/diagnostics"));
+/// # Ok(()) }
+/// ```
+#[must_use]
+#[derive(Debug, Clone, Default)]
+pub struct TopicFilter {
+    include: Vec<TopicPattern>,
+    exclude: Vec<TopicPattern>,
+}
+
+impl TopicFilter {
+    /// Creates a new filter which accepts all topics.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an include pattern.
+    pub fn include(mut self, pattern: TopicPattern) -> Self {
+        self.include.push(pattern);
+        self
+    }
+
+    /// Adds an exclude pattern.
+    pub fn exclude(mut self, pattern: TopicPattern) -> Self {
+        self.exclude.push(pattern);
+        self
+    }
+
+    /// Returns true if the topic is accepted by the filter.
+    pub fn matches(&self, topic: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| p.is_match(topic)))
+            && !self.exclude.iter().any(|p| p.is_match(topic))
+    }
+
+    /// Returns true if the channel is accepted by the filter.
+    pub(crate) fn matches_channel(&self, channel: &Channel) -> bool {
+        self.matches(&channel.topic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+
+    #[test]
+    fn test_glob() {
+        let p = TopicPattern::glob("/camera/*").unwrap();
+        assert!(p.is_match("/camera/front"));
+        assert!(!p.is_match("/camera/front/info"));
+        assert!(!p.is_match("/lidar"));
+
+        let p = TopicPattern::glob("/camera/**").unwrap();
+        assert!(p.is_match("/camera/front"));
+        assert!(p.is_match("/camera/front/info"));
+
+        let p = TopicPattern::glob("/tf?").unwrap();
+        assert!(p.is_match("/tf2"));
+        assert!(!p.is_match("/tf"));
+
+        // Regex metacharacters are matched literally.
+        let p = TopicPattern::glob("/a.b+").unwrap();
+        assert!(p.is_match("/a.b+"));
+        assert!(!p.is_match("/axbb"));
+    }
+
+    #[test]
+    fn test_regex() {
+        let p = TopicPattern::regex("/camera/(front|rear)").unwrap();
+        assert!(p.is_match("/camera/front"));
+        assert!(p.is_match("/camera/rear"));
+        assert!(!p.is_match("/camera/front/info"));
+
+        assert_matches!(
+            TopicPattern::regex("(unclosed"),
+            Err(FoxgloveError::InvalidTopicPattern(_))
+        );
+    }
+
+    #[test]
+    fn test_filter() {
+        let filter = TopicFilter::new();
+        assert!(filter.matches("/anything"));
+
+        let filter = TopicFilter::new()
+            .include(TopicPattern::glob("/camera/**").unwrap())
+            .include(TopicPattern::glob("/tf").unwrap())
+            .exclude(TopicPattern::glob("/camera/*/debug").unwrap());
+        assert!(filter.matches("/tf"));
+        assert!(filter.matches("/camera/front/image"));
+        assert!(!filter.matches("/camera/front/debug"));
+        assert!(!filter.matches("/diagnostics"));
+
+        let filter = TopicFilter::new().exclude(TopicPattern::glob("/camera/**").unwrap());
+        assert!(filter.matches("/diagnostics"));
+        assert!(!filter.matches("/camera/front"));
+    }
+}
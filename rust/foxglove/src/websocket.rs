@@ -8,17 +8,20 @@ pub(crate) use crate::websocket::protocol::client::{
 pub use crate::websocket::protocol::server::{
     Capability, Parameter, ParameterType, ParameterValue, Status, StatusLevel,
 };
-use crate::{get_runtime_handle, Channel, FoxgloveError, LogSink, Metadata};
+use crate::{
+    get_runtime_handle, Channel, FoxgloveError, LogSink, Metadata, TopicFilter, TopicPattern,
+};
 use bimap::BiHashMap;
-use bytes::{BufMut, BytesMut};
+use bytes::Bytes;
 use flume::TrySendError;
 use futures_util::{stream::SplitSink, SinkExt, StreamExt};
 use std::collections::hash_map::Entry;
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
+use std::panic::AssertUnwindSafe;
 use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed};
-use std::sync::atomic::{AtomicBool, AtomicU32};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64};
 use std::sync::Weak;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{collections::HashMap, net::SocketAddr, sync::Arc};
 use thiserror::Error;
 use tokio::runtime::Handle;
@@ -27,18 +30,45 @@ use tokio::{
     sync::Mutex,
 };
 use tokio_tungstenite::{
-    tungstenite::{self, handshake::server, http::HeaderValue, Message},
+    tungstenite::{
+        self,
+        handshake::server,
+        http::HeaderValue,
+        protocol::{frame::coding::CloseCode, CloseFrame},
+        Message,
+    },
     WebSocketStream,
 };
 use tokio_util::sync::CancellationToken;
 
+pub mod assets;
+mod auth;
+mod channel_queue;
+mod connection_graph;
+mod parameter_store;
 mod protocol;
 pub mod service;
+mod slow_client;
+mod stream;
 #[cfg(test)]
 mod tests;
+#[cfg(feature = "tls")]
+mod tls;
 #[cfg(all(test, feature = "unstable"))]
 mod unstable_tests;
 
+use assets::{AssetHandler, AssetResponder};
+pub use auth::{AuthRejection, AuthRequest, Authenticator, ClientIdentity};
+use channel_queue::ChannelQueues;
+pub use channel_queue::QueuePolicy;
+pub use connection_graph::ConnectionGraph;
+pub use parameter_store::{ParameterStore, TypedParameter, TypedParameterValue};
+use slow_client::OverflowTracker;
+pub use slow_client::SlowClientPolicy;
+use stream::{ClientStream, TlsAcceptor};
+#[cfg(feature = "tls")]
+pub(crate) use tls::{PemSource, TlsIdentity};
+
 use service::{CallId, Service, ServiceId};
 
 /// Identifies a client connection. Unique for the duration of the server's lifetime.
@@ -60,6 +90,109 @@ impl Client<'_> {
     pub fn id(&self) -> ClientId {
         self.0.id
     }
+
+    /// Returns the identity of the client, if the server was configured with an
+    /// [`Authenticator`].
+    pub fn identity(&self) -> Option<&ClientIdentity> {
+        self.0.identity.as_ref()
+    }
+
+    /// Returns the remote address of the client.
+    pub fn addr(&self) -> SocketAddr {
+        self.0.addr
+    }
+
+    /// Returns the `User-Agent` header from the client's handshake request, if present.
+    pub fn user_agent(&self) -> Option<&str> {
+        self.0.handshake.user_agent.as_deref()
+    }
+
+    /// Returns the path of the client's handshake request.
+    pub fn path(&self) -> &str {
+        &self.0.handshake.path
+    }
+
+    /// Returns the time at which the client connected.
+    pub fn connected_at(&self) -> SystemTime {
+        self.0.handshake.connected_at
+    }
+
+    /// Returns an owned snapshot of the client's state.
+    ///
+    /// Unlike [`Client`], the snapshot can be moved into a task that outlives the callback.
+    pub fn snapshot(&self) -> ClientSnapshot {
+        self.0.snapshot(self.0.server.upgrade().as_deref())
+    }
+}
+
+/// A snapshot of the state of a connected client.
+///
+/// See [`WebSocketServerHandle::clients`](crate::WebSocketServerHandle::clients).
+#[derive(Debug, Clone)]
+pub struct ClientSnapshot {
+    id: ClientId,
+    addr: SocketAddr,
+    identity: Option<ClientIdentity>,
+    user_agent: Option<String>,
+    path: String,
+    connected_at: SystemTime,
+    subscribed_topics: Vec<String>,
+    client_channels: Vec<String>,
+    queue_depth: usize,
+    dropped_messages: u64,
+}
+
+impl ClientSnapshot {
+    /// Returns the client ID.
+    pub fn id(&self) -> ClientId {
+        self.id
+    }
+
+    /// Returns the remote address of the client.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns the identity of the client, if the server was configured with an
+    /// [`Authenticator`].
+    pub fn identity(&self) -> Option<&ClientIdentity> {
+        self.identity.as_ref()
+    }
+
+    /// Returns the `User-Agent` header from the client's handshake request, if present.
+    pub fn user_agent(&self) -> Option<&str> {
+        self.user_agent.as_deref()
+    }
+
+    /// Returns the path of the client's handshake request.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the time at which the client connected.
+    pub fn connected_at(&self) -> SystemTime {
+        self.connected_at
+    }
+
+    /// Returns the topics of the channels the client is subscribed to.
+    pub fn subscribed_topics(&self) -> &[String] {
+        &self.subscribed_topics
+    }
+
+    /// Returns the topics of the channels advertised by the client.
+    pub fn client_channels(&self) -> &[String] {
+        &self.client_channels
+    }
+
+    /// Returns the number of messages waiting to be sent to the client.
+    pub fn queue_depth(&self) -> usize {
+        self.queue_depth
+    }
+
+    /// Returns the number of messages dropped because the client's queues were full.
+    pub fn dropped_messages(&self) -> u64 {
+        self.dropped_messages
+    }
 }
 
 /// Information about a client channel.
@@ -103,12 +236,14 @@ impl ChannelView<'_> {
 pub(crate) const SUBPROTOCOL: &str = "foxglove.sdk.v1";
 const MAX_SEND_RETRIES: usize = 10;
 
-type WebsocketSender = SplitSink<WebSocketStream<TcpStream>, Message>;
+type WebsocketSender = SplitSink<WebSocketStream<ClientStream>, Message>;
 
 // Queue up to 1024 messages per connected client before dropping messages
 const DEFAULT_MESSAGE_BACKLOG_SIZE: usize = 1024;
 const DEFAULT_CONTROL_PLANE_BACKLOG_SIZE: usize = 64;
 const DEFAULT_SERVICE_CALLS_PER_CLIENT: usize = 32;
+// Time allowed for sending a close frame to a client that is being disconnected
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
 
 #[derive(Error, Debug)]
 enum WSError {
@@ -126,15 +261,29 @@ pub(crate) struct ServerOptions {
     pub services: HashMap<String, Service>,
     pub supported_encodings: Option<HashSet<String>>,
     pub runtime: Option<Handle>,
+    pub topic_filter: Option<TopicFilter>,
+    #[cfg(feature = "tls")]
+    pub tls: Option<TlsIdentity>,
+    pub authenticator: Option<Arc<dyn Authenticator>>,
+    pub asset_handler: Option<Arc<dyn AssetHandler>>,
+    pub parameter_store: Option<ParameterStore>,
+    pub slow_client_policy: SlowClientPolicy,
+    pub queue_policies: Vec<(TopicPattern, QueuePolicy)>,
 }
 
 impl std::fmt::Debug for ServerOptions {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("ServerOptions")
-            .field("session_id", &self.session_id)
+        let mut f = f.debug_struct("ServerOptions");
+        f.field("session_id", &self.session_id)
             .field("name", &self.name)
             .field("message_backlog_size", &self.message_backlog_size)
             .field("services", &self.services)
+            .field("topic_filter", &self.topic_filter);
+        #[cfg(feature = "tls")]
+        f.field("tls", &self.tls);
+        f.field("parameter_store", &self.parameter_store)
+            .field("slow_client_policy", &self.slow_client_policy)
+            .field("queue_policies", &self.queue_policies)
             .finish()
     }
 }
@@ -167,6 +316,41 @@ pub(crate) struct Server {
     cancellation_token: CancellationToken,
     /// Registered services.
     services: parking_lot::RwLock<HashMap<ServiceId, Arc<Service>>>,
+    /// Selects the channels to advertise to clients.
+    topic_filter: Option<TopicFilter>,
+    /// Certificate chain and private key for TLS connections.
+    #[cfg(feature = "tls")]
+    tls: Option<TlsIdentity>,
+    /// Authenticates clients during the handshake.
+    authenticator: Option<Arc<dyn Authenticator>>,
+    /// The connection graph, and the clients subscribed to its updates.
+    connection_graph: parking_lot::Mutex<ConnectionGraphState>,
+    /// Handles asset requests from clients.
+    asset_handler: Option<Arc<dyn AssetHandler>>,
+    /// Answers parameter requests from clients.
+    parameter_store: Option<ParameterStore>,
+    /// How to handle clients whose queues overflow.
+    slow_client_policy: SlowClientPolicy,
+    /// Queue policies for channels, matched by topic.
+    queue_policies: Vec<(TopicPattern, QueuePolicy)>,
+    /// Queue policies for advertised channels which match one of the queue policy patterns.
+    channel_queue_policies: parking_lot::RwLock<HashMap<ChannelId, QueuePolicy>>,
+    /// The most recent messages on latched channels, with their log times.
+    latched_messages: parking_lot::RwLock<HashMap<ChannelId, LatchedMessages>>,
+}
+
+/// The most recent messages on a latched channel, with their log times.
+///
+/// The lock is held while a message is sent to subscribers, and while a client subscribes, so
+/// that clients which subscribe concurrently receive each message either by replay or as it is
+/// logged, but not both. Each channel has its own lock, so that logging on one channel doesn't
+/// block logging on another.
+type LatchedMessages = Arc<parking_lot::Mutex<VecDeque<(u64, Bytes)>>>;
+
+#[derive(Default)]
+struct ConnectionGraphState {
+    graph: ConnectionGraph,
+    subscribers: HashSet<ClientId>,
 }
 
 /// Provides a mechanism for registering callbacks for handling client message events.
@@ -175,6 +359,12 @@ pub(crate) struct Server {
 /// long-running behavior is required, the implementation should use [`tokio::task::spawn`] (or
 /// [`tokio::task::spawn_blocking`]).
 pub trait ServerListener: Send + Sync {
+    /// Callback invoked when a client connects, after the server has advertised its channels and
+    /// services to the client.
+    fn on_client_connect(&self, _client: Client) {}
+    /// Callback invoked when a client disconnects, after any subscriptions it held have been
+    /// cleaned up.
+    fn on_client_disconnect(&self, _client: Client) {}
     /// Callback invoked when a client message is received.
     fn on_message_data(
         &self,
@@ -219,20 +409,39 @@ pub trait ServerListener: Send + Sync {
     fn on_parameters_subscribe(&self, _param_names: Vec<String>) {}
     /// Callback invoked when a client unsubscribes from parameters. Requires [`Capability::ParametersSubscribe`].
     fn on_parameters_unsubscribe(&self, _param_names: Vec<String>) {}
+    /// Callback invoked when a client is disconnected because its message queue overflowed,
+    /// according to the server's [`SlowClientPolicy`].
+    ///
+    /// The overflow is detected on the thread that logged the message, but this callback is
+    /// invoked from a task on the server's runtime, so it may log to the server. It must not
+    /// block.
+    fn on_slow_client_disconnect(&self, _client: Client, _reason: &str) {}
+    /// Callback invoked when the first client subscribes to connection graph updates. Requires [`Capability::ConnectionGraph`].
+    fn on_connection_graph_subscribe(&self) {}
+    /// Callback invoked when the last client unsubscribes from connection graph updates. Requires [`Capability::ConnectionGraph`].
+    fn on_connection_graph_unsubscribe(&self) {}
 }
 
 /// A connected client session with the websocket server.
 pub(crate) struct ConnectedClient {
     id: ClientId,
     addr: SocketAddr,
+    /// Identity returned by the server's authenticator.
+    identity: Option<ClientIdentity>,
+    /// Information from the client's handshake request
+    handshake: Handshake,
     weak_self: Weak<Self>,
     /// Write side of a WS stream
     sender: Mutex<WebsocketSender>,
     data_plane_tx: flume::Sender<Message>,
     data_plane_rx: flume::Receiver<Message>,
+    /// Dedicated data plane queues for channels with a queue policy
+    channel_queues: ChannelQueues,
     control_plane_tx: flume::Sender<Message>,
     control_plane_rx: flume::Receiver<Message>,
     service_call_sem: service::Semaphore,
+    /// Semaphores for services that limit concurrent calls per client
+    service_call_limits: parking_lot::Mutex<HashMap<ServiceId, service::LimitSemaphore>>,
     /// Subscriptions from this client
     subscriptions: parking_lot::Mutex<BiHashMap<ChannelId, SubscriptionId>>,
     /// Channels advertised by this client
@@ -242,6 +451,16 @@ pub(crate) struct ConnectedClient {
     /// Optional callback handler for a server implementation
     server_listener: Option<Arc<dyn ServerListener>>,
     server: Weak<Server>,
+    /// Tracks queue overflows to detect slow clients
+    overflow: OverflowTracker,
+    /// Number of messages dropped because a queue was full
+    dropped_messages: AtomicU64,
+    /// Close frame to send when the server disconnects the client
+    close_frame: parking_lot::Mutex<Option<CloseFrame>>,
+    /// Cancelled when the server disconnects the client
+    disconnect_token: CancellationToken,
+    /// Cancelled when the client disconnects for any reason, or the server stops
+    closed_token: CancellationToken,
 }
 
 impl ConnectedClient {
@@ -301,38 +520,173 @@ impl ConnectedClient {
                 self.on_parameters_unsubscribe(server, msg.parameter_names)
             }
             ClientMessage::ServiceCallRequest(msg) => self.on_service_call(msg),
-            _ => {
-                tracing::error!("Unsupported message from {}: {}", self.addr, msg.op());
-                self.send_error(format!("Unsupported message: {}", msg.op()));
+            ClientMessage::FetchAsset(msg) => self.on_fetch_asset(server, msg.uri, msg.request_id),
+            ClientMessage::SubscribeConnectionGraph => self.on_connection_graph_subscribe(server),
+            ClientMessage::UnsubscribeConnectionGraph => {
+                if !server.capabilities.contains(&Capability::ConnectionGraph) {
+                    self.send_error("Server does not support connection graph capability".into());
+                    return;
+                }
+                self.on_connection_graph_unsubscribe(&server);
             }
         }
     }
 
     /// Send the message on the data plane, dropping up to retries older messages to make room, if necessary.
     fn send_data_lossy(&self, message: Message, retries: usize) -> SendLossyResult {
-        send_lossy(
+        let result = send_lossy(
             &self.addr,
             &self.data_plane_tx,
             &self.data_plane_rx,
             message,
             retries,
-        )
+        );
+        // Wake the sender, which pops the shared queue along with the channel queues.
+        self.channel_queues.notify();
+        match result {
+            SendLossyResult::Sent => self.overflow.on_sent(),
+            SendLossyResult::SentLossy(dropped) => {
+                self.dropped_messages.fetch_add(dropped as u64, Relaxed);
+                self.on_overflow();
+            }
+            SendLossyResult::ExhaustedRetries => {
+                self.dropped_messages.fetch_add(1, Relaxed);
+                self.on_overflow();
+            }
+        }
+        result
+    }
+
+    /// Send message data for a channel on the data plane.
+    ///
+    /// If the channel has a queue policy, the message is sent on the channel's dedicated queue.
+    /// Otherwise it is sent on the shared queue.
+    fn send_channel_data(
+        &self,
+        channel_id: ChannelId,
+        policy: Option<QueuePolicy>,
+        message: Message,
+    ) {
+        let Some(policy) = policy else {
+            self.send_data_lossy(message, MAX_SEND_RETRIES);
+            return;
+        };
+        let dropped = self.channel_queues.push(channel_id, policy, message);
+        if dropped > 0 {
+            self.dropped_messages.fetch_add(dropped as u64, Relaxed);
+            tracing::trace!(
+                "queue for channel {channel_id} full for client {}, dropped {dropped} messages",
+                self.addr
+            );
+            self.on_overflow();
+        } else {
+            self.overflow.on_sent();
+        }
     }
 
-    /// Send the message on the control plane, disconnecting the client if the channel is full.
+    /// Send the message on the control plane, dropping it if the channel is full.
+    ///
+    /// Depending on the server's [`SlowClientPolicy`], a full channel may cause the client to be
+    /// disconnected.
     fn send_control_msg(&self, message: Message) -> bool {
         if let Err(TrySendError::Full(_)) = self.control_plane_tx.try_send(message) {
-            // TODO disconnect the slow client FG-10441
             tracing::error!(
                 "Client control plane is full for {}, dropping message",
                 self.addr
             );
+            self.dropped_messages.fetch_add(1, Relaxed);
+            self.on_overflow();
             return false;
         }
+        self.overflow.on_sent();
+        true
+    }
+
+    /// Records a queue overflow, and disconnects the client if required by the slow client policy.
+    fn on_overflow(&self) {
+        let Some(reason) = self.overflow.on_overflow() else {
+            return;
+        };
+        if self.disconnect(CloseCode::Policy, &reason) {
+            tracing::warn!("Disconnecting client {}: {reason}", self.addr);
+            // Don't invoke the listener on the logging thread, which may be holding locks.
+            let (Some(handler), Some(client), Some(server)) = (
+                self.server_listener.clone(),
+                self.weak_self.upgrade(),
+                self.server.upgrade(),
+            ) else {
+                return;
+            };
+            server.runtime.spawn(async move {
+                handler.on_slow_client_disconnect(Client(&client), &reason);
+            });
+        }
+    }
+
+    /// Returns a snapshot of the client's state.
+    ///
+    /// If the server has been dropped, the snapshot has no subscribed topics.
+    fn snapshot(&self, server: Option<&Server>) -> ClientSnapshot {
+        let subscribed_topics = server.map_or_else(Vec::new, |server| {
+            let subscriptions = self.subscriptions.lock();
+            let channels = server.channels.read();
+            subscriptions
+                .left_values()
+                .filter_map(|id| channels.get(id))
+                .map(|channel| channel.topic.clone())
+                .collect()
+        });
+        let client_channels = self
+            .advertised_channels
+            .lock()
+            .values()
+            .map(|channel| channel.topic.clone())
+            .collect();
+        ClientSnapshot {
+            id: self.id,
+            addr: self.addr,
+            identity: self.identity.clone(),
+            user_agent: self.handshake.user_agent.clone(),
+            path: self.handshake.path.clone(),
+            connected_at: self.handshake.connected_at,
+            subscribed_topics,
+            client_channels,
+            queue_depth: self.data_plane_rx.len()
+                + self.control_plane_rx.len()
+                + self.channel_queues.len(),
+            dropped_messages: self.dropped_messages.load(Relaxed),
+        }
+    }
+
+    /// Disconnects the client, sending a close frame with the given code and reason.
+    ///
+    /// Returns false if the client was already being disconnected.
+    fn disconnect(&self, code: CloseCode, reason: &str) -> bool {
+        {
+            let mut close_frame = self.close_frame.lock();
+            if close_frame.is_some() {
+                return false;
+            }
+            *close_frame = Some(CloseFrame {
+                code,
+                reason: reason.into(),
+            });
+        }
+        self.disconnect_token.cancel();
         true
     }
 
     fn on_disconnect(&self, server: &Arc<Server>) {
+        // Abandon queued service calls.
+        self.closed_token.cancel();
+
+        // Unsubscribe from any channels the client is still subscribed to, and notify the
+        // handler for each one.
+        let subscription_ids: Vec<_> = self.subscriptions.lock().right_values().copied().collect();
+        self.on_unsubscribe(server.clone(), subscription_ids);
+
+        self.on_connection_graph_unsubscribe(server);
+
         // If we track paramter subscriptions, unsubscribe this clients subscriptions
         // and notify the handler, if necessary
         if !server
@@ -469,6 +823,7 @@ impl ConnectedClient {
             let mut subscriptions = self.subscriptions.lock();
             for subscription_id in subscription_ids {
                 if let Some((channel_id, _)) = subscriptions.remove_by_right(&subscription_id) {
+                    self.channel_queues.remove(channel_id);
                     unsubscribed_channel_ids.push(channel_id);
                 }
             }
@@ -530,6 +885,11 @@ impl ConnectedClient {
         for (subscription, channel) in subscriptions.into_iter().zip(subscribed_channels) {
             // Using a limited scope here to avoid holding the lock on subscriptions while calling on_subscribe
             {
+                // Hold the channel's latched messages lock while subscribing, so that messages
+                // logged concurrently are either replayed or sent, but not both.
+                let latched_messages =
+                    (channel.latched > 0).then(|| server.latched_messages(channel.id));
+                let latched_messages = latched_messages.as_ref().map(|m| m.lock());
                 let mut subscriptions = self.subscriptions.lock();
                 if subscriptions
                     .insert_no_overwrite(subscription.channel_id, subscription.id)
@@ -549,6 +909,20 @@ impl ConnectedClient {
                     }
                     continue;
                 }
+                drop(subscriptions);
+
+                if let Some(messages) = latched_messages.as_ref() {
+                    let queue_policy = server
+                        .channel_queue_policies
+                        .read()
+                        .get(&channel.id)
+                        .copied();
+                    for (log_time, payload) in messages.iter() {
+                        let frame =
+                            protocol::server::message_data(subscription.id, *log_time, payload);
+                        self.send_channel_data(channel.id, queue_policy, Message::Binary(frame));
+                    }
+                }
             }
 
             tracing::debug!(
@@ -569,6 +943,47 @@ impl ConnectedClient {
         }
     }
 
+    fn on_connection_graph_subscribe(&self, server: Arc<Server>) {
+        if !server.capabilities.contains(&Capability::ConnectionGraph) {
+            self.send_error("Server does not support connection graph capability".to_string());
+            return;
+        }
+
+        let is_first_subscriber = {
+            let mut state = server.connection_graph.lock();
+            if !state.subscribers.insert(self.id) {
+                tracing::debug!(
+                    "Client {} is already subscribed to connection graph updates",
+                    self.addr
+                );
+                return;
+            }
+            // Send the full graph to the new subscriber.
+            let empty = ConnectionGraph::default();
+            let update = empty.diff(&state.graph);
+            self.send_control_msg(Message::text(serde_json::to_string(&update).unwrap()));
+            state.subscribers.len() == 1
+        };
+        // Call the handler after releasing the connection graph lock
+        if is_first_subscriber {
+            if let Some(handler) = self.server_listener.as_ref() {
+                handler.on_connection_graph_subscribe();
+            }
+        }
+    }
+
+    fn on_connection_graph_unsubscribe(&self, server: &Arc<Server>) {
+        let is_last_subscriber = {
+            let mut state = server.connection_graph.lock();
+            state.subscribers.remove(&self.id) && state.subscribers.is_empty()
+        };
+        if is_last_subscriber {
+            if let Some(handler) = self.server_listener.as_ref() {
+                handler.on_connection_graph_unsubscribe();
+            }
+        }
+    }
+
     fn on_get_parameters(
         &self,
         server: Arc<Server>,
@@ -580,7 +995,11 @@ impl ConnectedClient {
             return;
         }
 
-        if let Some(handler) = self.server_listener.as_ref() {
+        if let Some(store) = server.parameter_store.as_ref() {
+            let parameters = store.parameters(&param_names);
+            let message = protocol::server::parameters_json(&parameters, request_id.as_deref());
+            self.send_control_msg(Message::text(message));
+        } else if let Some(handler) = self.server_listener.as_ref() {
             let request_id = request_id.as_deref();
             let parameters = handler.on_get_parameters(Client(self), param_names, request_id);
             let message = protocol::server::parameters_json(&parameters, request_id);
@@ -599,6 +1018,19 @@ impl ConnectedClient {
             return;
         }
 
+        if let Some(store) = server.parameter_store.as_ref() {
+            // Changes are published to every server attached to the store.
+            let (current, errors) = store.set_from_client(parameters);
+            for error in errors {
+                self.send_error(error);
+            }
+            if request_id.is_some() {
+                let message = protocol::server::parameters_json(&current, request_id.as_deref());
+                self.send_control_msg(Message::text(message));
+            }
+            return;
+        }
+
         let updated_parameters = if let Some(handler) = self.server_listener.as_ref() {
             let request_id = request_id.as_deref();
             let updated_parameters =
@@ -758,18 +1190,74 @@ impl ConnectedClient {
             return;
         };
 
+        // Apply the service's concurrency limits, which may queue the call.
+        let limits = service.limits();
+        let per_client = limits.per_client().map(|max| {
+            self.service_call_limits
+                .lock()
+                .entry(service_id)
+                .or_insert_with(|| service::LimitSemaphore::new(max))
+                .clone()
+        });
+        match limits.admit(guard, per_client) {
+            service::Admission::Ready(permit) => self.call_service(&service, req, permit),
+            service::Admission::Queued(permit) => {
+                let client = self.arc();
+                tokio::spawn(async move {
+                    tokio::select! {
+                        permit = permit => client.call_service(&service, req, permit),
+                        () = client.closed_token.cancelled() => (),
+                    }
+                });
+            }
+            service::Admission::Rejected => {
+                self.send_service_call_failure(service_id, call_id, "Too many requests");
+            }
+        }
+    }
+
+    /// Invokes a service call handler, after the call has been admitted.
+    fn call_service(
+        &self,
+        service: &Arc<Service>,
+        req: protocol::client::ServiceCallRequest,
+        permit: service::CallPermit,
+    ) {
+        let call_id = req.call_id;
+
         // Prepare the responder and the request.
         let responder = service::Responder::new(
             self.arc(),
             service.id(),
             call_id,
             service.response_encoding().unwrap_or(&req.encoding),
-            guard,
+            permit,
+            service.timeout(),
         );
         let request = service::Request::new(service.clone(), call_id, req.encoding, req.payload);
 
-        // Invoke the handler.
-        service.call(Client(self), request, responder);
+        // Invoke the handler. If the handler panics, the responder is dropped, which sends a
+        // failure response to the client.
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            service.call(Client(self), request, responder)
+        }));
+        if result.is_err() {
+            tracing::error!(
+                "Handler for service {} panicked on call {call_id} from client {}",
+                service.name(),
+                self.addr
+            );
+        }
+    }
+
+    fn on_fetch_asset(&self, server: Arc<Server>, uri: String, request_id: u32) {
+        let responder = AssetResponder::new(self.arc(), request_id);
+        match server.asset_handler.as_ref() {
+            Some(handler) if server.capabilities.contains(&Capability::Assets) => {
+                handler.fetch(Client(self), uri, responder);
+            }
+            _ => responder.respond(Err("Server does not support assets capability".to_string())),
+        }
     }
 
     /// Sends a service call failure message to the client with the provided message.
@@ -810,6 +1298,8 @@ impl std::fmt::Debug for ConnectedClient {
         f.debug_struct("Client")
             .field("id", &self.id)
             .field("address", &self.addr)
+            .field("identity", &self.identity)
+            .field("handshake", &self.handshake)
             .finish()
     }
 }
@@ -840,6 +1330,20 @@ impl Server {
             );
         }
 
+        // If the server was declared with an asset handler, automatically add the "assets"
+        // capability.
+        if opts.asset_handler.is_some() {
+            capabilities.insert(Capability::Assets);
+        }
+
+        // If the server was declared with a parameter store, automatically add the parameter
+        // capabilities, and notify the server when parameters change.
+        if let Some(store) = opts.parameter_store.as_ref() {
+            capabilities.insert(Capability::Parameters);
+            capabilities.insert(Capability::ParametersSubscribe);
+            store.attach(weak_self.clone());
+        }
+
         Server {
             weak_self,
             started: AtomicBool::new(false),
@@ -864,7 +1368,30 @@ impl Server {
                     .map(|s| (s.id(), Arc::new(s)))
                     .collect(),
             ),
+            topic_filter: opts.topic_filter,
+            #[cfg(feature = "tls")]
+            tls: opts.tls,
+            authenticator: opts.authenticator,
+            connection_graph: parking_lot::Mutex::default(),
+            asset_handler: opts.asset_handler,
+            parameter_store: opts.parameter_store,
+            slow_client_policy: opts.slow_client_policy,
+            queue_policies: opts.queue_policies,
+            channel_queue_policies: parking_lot::RwLock::new(HashMap::new()),
+            latched_messages: parking_lot::RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the latched messages for a channel.
+    fn latched_messages(&self, channel_id: ChannelId) -> LatchedMessages {
+        if let Some(messages) = self.latched_messages.read().get(&channel_id) {
+            return messages.clone();
         }
+        self.latched_messages
+            .write()
+            .entry(channel_id)
+            .or_default()
+            .clone()
     }
 
     pub fn arc(&self) -> Arc<Self> {
@@ -883,6 +1410,10 @@ impl Server {
         if self.started.load(Acquire) {
             return Err(FoxgloveError::ServerAlreadyStarted);
         }
+        #[cfg(feature = "tls")]
+        let tls_acceptor = self.tls.as_ref().map(TlsIdentity::acceptor).transpose()?;
+        #[cfg(not(feature = "tls"))]
+        let tls_acceptor: Option<TlsAcceptor> = None;
         let already_started = self.started.swap(true, AcqRel);
         assert!(!already_started);
 
@@ -899,7 +1430,7 @@ impl Server {
         let server = self.arc().clone();
         self.runtime.spawn(async move {
             tokio::select! {
-                () = handle_connections(server, listener) => (),
+                () = handle_connections(server, listener, tls_acceptor) => (),
                 () = cancellation_token.cancelled() => {
                     tracing::debug!("Closed connection handler");
                 }
@@ -930,24 +1461,25 @@ impl Server {
     }
 
     async fn advertise_channel(&self, channel: Arc<Channel>) {
-        if channel.schema.is_none() {
-            tracing::error!(
-                "Ignoring advertise channel for {} because a schema is required",
-                channel.topic
-            );
-            return;
-        }
-
-        self.channels.write().insert(channel.id, channel.clone());
-
         let message = match protocol::server::advertisement(&channel) {
             Ok(message) => message,
             Err(err) => {
-                tracing::error!("Error creating advertise channel message to client: {err}");
+                tracing::error!("Ignoring advertise channel for {}: {err}", channel.topic);
                 return;
             }
         };
 
+        self.channels.write().insert(channel.id, channel.clone());
+        if let Some((_, policy)) = self
+            .queue_policies
+            .iter()
+            .find(|(pattern, _)| pattern.is_match(&channel.topic))
+        {
+            self.channel_queue_policies
+                .write()
+                .insert(channel.id, *policy);
+        }
+
         let clients = self.clients.get();
         for client in clients.iter() {
             if client.send_control_msg(Message::text(message.clone())) {
@@ -962,7 +1494,13 @@ impl Server {
     }
 
     async fn unadvertise_channel(&self, channel_id: ChannelId) {
-        self.channels.write().remove(&channel_id);
+        let removed = self.channels.write().remove(&channel_id);
+        self.channel_queue_policies.write().remove(&channel_id);
+        self.latched_messages.write().remove(&channel_id);
+        if removed.is_none() {
+            // The channel was never advertised.
+            return;
+        }
 
         let message = protocol::server::unadvertise(channel_id);
         let clients = self.clients.get();
@@ -997,11 +1535,7 @@ impl Server {
             return;
         }
 
-        // https://github.com/foxglove/ws-protocol/blob/main/docs/spec.md#time
-        let mut buf = BytesMut::with_capacity(9);
-        buf.put_u8(protocol::server::BinaryOpcode::TimeData as u8);
-        buf.put_u64_le(timestamp_nanos);
-        let message = Message::binary(buf);
+        let message = Message::binary(protocol::server::time(timestamp_nanos));
 
         let clients = self.clients.get();
         for client in clients.iter() {
@@ -1040,6 +1574,55 @@ impl Server {
         }
     }
 
+    /// Replaces the connection graph, and sends the differences to subscribed clients.
+    pub fn replace_connection_graph(&self, graph: ConnectionGraph) -> Result<(), FoxgloveError> {
+        if !self.capabilities.contains(&Capability::ConnectionGraph) {
+            return Err(FoxgloveError::ConnectionGraphNotSupported);
+        }
+        let mut state = self.connection_graph.lock();
+        if !state.subscribers.is_empty() {
+            let update = state.graph.diff(&graph);
+            if !update.is_empty() {
+                let message = Message::text(serde_json::to_string(&update).unwrap());
+                for client in self.clients.get().iter() {
+                    if state.subscribers.contains(&client.id) {
+                        client.send_control_msg(message.clone());
+                    }
+                }
+            }
+        }
+        state.graph = graph;
+        Ok(())
+    }
+
+    /// Returns a snapshot of each connected client.
+    pub fn clients(&self) -> Vec<ClientSnapshot> {
+        self.clients
+            .get()
+            .iter()
+            .map(|client| client.snapshot(Some(self)))
+            .collect()
+    }
+
+    /// Disconnects a client, sending a close frame with the given reason.
+    ///
+    /// Returns false if there is no connected client with the given ID.
+    pub fn disconnect_client(&self, client_id: ClientId, reason: &str) -> bool {
+        let Some(client) = self
+            .clients
+            .get()
+            .iter()
+            .find(|c| c.id == client_id)
+            .cloned()
+        else {
+            return false;
+        };
+        if client.disconnect(CloseCode::Normal, reason) {
+            tracing::info!("Disconnecting client {}: {reason}", client.addr);
+        }
+        true
+    }
+
     /// Sets a new session ID and notifies all clients, causing them to reset their state.
     /// If no session ID is provided, generates a new one based on the current timestamp.
     pub fn clear_session(&self, new_session_id: Option<String>) {
@@ -1065,14 +1648,36 @@ impl Server {
     /// - Advertise existing channels
     /// - Advertise existing services
     /// - Listen for client meesages
-    async fn handle_connection(self: Arc<Self>, stream: TcpStream, addr: SocketAddr) {
-        let ws_stream = match do_handshake(stream).await {
-            Ok(ws_stream) => ws_stream,
-            Err(_) => {
-                tracing::error!("Dropping client {addr}: {}", WSError::HandshakeError);
+    async fn handle_connection(
+        self: Arc<Self>,
+        stream: TcpStream,
+        addr: SocketAddr,
+        tls_acceptor: Option<TlsAcceptor>,
+    ) {
+        let stream = match ClientStream::accept(stream, tls_acceptor.as_ref()).await {
+            Ok(stream) => stream,
+            Err(err) => {
+                // Failed handshakes are caused by the client, and are common on public ports.
+                tracing::debug!("Dropping client {addr}: TLS handshake failed: {err}");
                 return;
             }
         };
+        let (ws_stream, identity, handshake) =
+            match do_handshake(stream, self.authenticator.as_deref()).await {
+                Ok(result) => result,
+                // The request was rejected by the authenticator, which has already been logged.
+                Err(tungstenite::Error::Http(response)) => {
+                    tracing::debug!(
+                        "Dropping client {addr}: rejected with status {}",
+                        response.status()
+                    );
+                    return;
+                }
+                Err(_) => {
+                    tracing::error!("Dropping client {addr}: {}", WSError::HandshakeError);
+                    return;
+                }
+            };
 
         let (mut ws_sender, mut ws_receiver) = ws_stream.split();
 
@@ -1097,21 +1702,33 @@ impl Server {
         let new_client = Arc::new_cyclic(|weak_self| ConnectedClient {
             id,
             addr,
+            identity,
+            handshake,
             weak_self: weak_self.clone(),
             sender: Mutex::new(ws_sender),
             data_plane_tx: data_tx,
             data_plane_rx: data_rx,
+            channel_queues: ChannelQueues::default(),
             control_plane_tx: ctrl_tx,
             control_plane_rx: ctrl_rx,
             service_call_sem: service::Semaphore::new(DEFAULT_SERVICE_CALLS_PER_CLIENT),
+            service_call_limits: parking_lot::Mutex::default(),
             subscriptions: parking_lot::Mutex::new(BiHashMap::new()),
             advertised_channels: parking_lot::Mutex::new(HashMap::new()),
             parameter_subscriptions: parking_lot::Mutex::new(HashSet::new()),
             server_listener: self.listener.clone(),
             server: self.weak_self.clone(),
+            overflow: OverflowTracker::new(self.slow_client_policy),
+            dropped_messages: AtomicU64::new(0),
+            close_frame: parking_lot::Mutex::new(None),
+            disconnect_token: CancellationToken::new(),
+            closed_token: self.cancellation_token.child_token(),
         });
 
         self.register_client_and_advertise(new_client.clone()).await;
+        if let Some(listener) = self.listener.as_ref() {
+            listener.on_client_connect(Client(&new_client));
+        }
 
         let receive_messages = async {
             while let Some(msg) = ws_receiver.next().await {
@@ -1140,14 +1757,24 @@ impl Server {
                     } else {
                         new_client.control_plane_rx.drain();
                         new_client.data_plane_rx.drain();
+                        new_client.channel_queues.clear();
                     }
                 }
             }
         };
 
-        // send_messages forwards messages from the rx size of the data plane to the sender
+        // send_messages forwards messages from the data plane queues to the sender
         let send_messages = async {
-            while let Ok(msg) = new_client.data_plane_rx.recv_async().await {
+            loop {
+                // Messages are always taken via `pop`, so that the shared queue takes its turn
+                // with the channel queues.
+                let Some(msg) = new_client.channel_queues.pop(&new_client.data_plane_rx) else {
+                    if new_client.data_plane_rx.is_disconnected() {
+                        break;
+                    }
+                    new_client.channel_queues.notified().await;
+                    continue;
+                };
                 let mut sender = new_client.sender.lock().await;
                 if let Err(err) = sender.send(msg).await {
                     if self.started.load(Acquire) {
@@ -1155,6 +1782,7 @@ impl Server {
                     } else {
                         new_client.control_plane_rx.drain();
                         new_client.data_plane_rx.drain();
+                        new_client.channel_queues.clear();
                     }
                 }
             }
@@ -1171,10 +1799,21 @@ impl Server {
             _ = send_messages => {
                 tracing::error!("Send messages task completed");
             }
+            () = new_client.disconnect_token.cancelled() => {
+                let close_frame = new_client.close_frame.lock().take();
+                let mut sender = new_client.sender.lock().await;
+                let close = sender.send(Message::Close(close_frame));
+                if tokio::time::timeout(CLOSE_TIMEOUT, close).await.is_err() {
+                    tracing::warn!("Timed out sending close frame to client {addr}");
+                }
+            }
         }
 
         self.clients.retain(|c| !Arc::ptr_eq(c, &new_client));
         new_client.on_disconnect(&self);
+        if let Some(listener) = self.listener.as_ref() {
+            listener.on_client_disconnect(Client(&new_client));
+        }
     }
 
     async fn register_client_and_advertise(&self, client: Arc<ConnectedClient>) {
@@ -1200,8 +1839,11 @@ impl Server {
             let message = match protocol::server::advertisement(&channel) {
                 Ok(message) => message,
                 Err(err) => {
-                    tracing::error!("Error creating advertise channel message to client: {err}");
-                    return;
+                    tracing::error!(
+                        "Error creating advertise channel message for {}: {err}",
+                        channel.topic
+                    );
+                    continue;
                 }
             };
 
@@ -1402,6 +2044,21 @@ impl LogSink for Server {
         msg: &[u8],
         metadata: &Metadata,
     ) -> Result<(), FoxgloveError> {
+        let queue_policy = self.channel_queue_policies.read().get(&channel.id).copied();
+        // Hold the channel's latched messages lock while sending, so that clients which subscribe
+        // concurrently receive the message either by replay or from this loop, but not both.
+        let latched_messages = (channel.latched > 0).then(|| self.latched_messages(channel.id));
+        let _latched_messages = latched_messages.as_ref().map(|messages| {
+            let mut messages = messages.lock();
+            if messages.len() >= channel.latched {
+                messages.pop_front();
+            }
+            messages.push_back((metadata.log_time, Bytes::copy_from_slice(msg)));
+            messages
+        });
+        // The frame only depends on the subscription ID, so we encode it once for each distinct
+        // subscription ID, and share it between clients.
+        let mut frames: Vec<(SubscriptionId, Bytes)> = Vec::new();
         let clients = self.clients.get();
         for client in clients.iter() {
             let subscriptions = client.subscriptions.lock();
@@ -1409,21 +2066,27 @@ impl LogSink for Server {
                 continue;
             };
 
-            // https://github.com/foxglove/ws-protocol/blob/main/docs/spec.md#message-data
-            let header_size: usize = 1 + 4 + 8;
-            let mut buf = BytesMut::with_capacity(header_size + msg.len());
-            buf.put_u8(protocol::server::BinaryOpcode::MessageData as u8);
-            buf.put_u32_le(subscription_id.into());
-            buf.put_u64_le(metadata.log_time);
-            buf.put_slice(msg);
-
-            let message = Message::binary(buf);
+            let frame = match frames.iter().find(|(id, _)| *id == subscription_id) {
+                Some((_, frame)) => frame.clone(),
+                None => {
+                    let frame =
+                        protocol::server::message_data(subscription_id, metadata.log_time, msg);
+                    frames.push((subscription_id, frame.clone()));
+                    frame
+                }
+            };
 
-            client.send_data_lossy(message, MAX_SEND_RETRIES);
+            client.send_channel_data(channel.id, queue_policy, Message::Binary(frame));
         }
         Ok(())
     }
 
+    fn accepts_channel(&self, channel: &Channel) -> bool {
+        self.topic_filter
+            .as_ref()
+            .is_none_or(|f| f.matches_channel(channel))
+    }
+
     /// Server has an available channel. Advertise to all clients.
     fn add_channel(&self, channel: &Arc<Channel>) {
         let server = self.arc();
@@ -1446,33 +2109,84 @@ pub(crate) fn create_server(opts: ServerOptions) -> Arc<Server> {
 }
 
 // Spawn a new task for each incoming connection
-async fn handle_connections(server: Arc<Server>, listener: TcpListener) {
+async fn handle_connections(
+    server: Arc<Server>,
+    listener: TcpListener,
+    tls_acceptor: Option<TlsAcceptor>,
+) {
     while let Ok((stream, addr)) = listener.accept().await {
-        tokio::spawn(server.clone().handle_connection(stream, addr));
+        tokio::spawn(
+            server
+                .clone()
+                .handle_connection(stream, addr, tls_acceptor.clone()),
+        );
     }
 }
 
 /// Add the subprotocol header to the response if the client requested one we support.
 /// If the client doesn't support our protocol, do not include the protocol header in the response;
 /// the client must fail the connection. [WebSocket RFC](https://www.rfc-editor.org/rfc/rfc6455#section-4)
-async fn do_handshake(stream: TcpStream) -> Result<WebSocketStream<TcpStream>, tungstenite::Error> {
-    tokio_tungstenite::accept_hdr_async(
-        stream,
-        |req: &server::Request, mut res: server::Response| {
-            let all_headers = req.headers().get_all("sec-websocket-protocol");
-            if all_headers.iter().any(|h| {
-                (*h).to_str()
-                    .unwrap_or_default()
-                    .split(',')
-                    .any(|s| s.trim() == SUBPROTOCOL)
-            }) {
-                res.headers_mut().insert(
-                    "sec-websocket-protocol",
-                    HeaderValue::from_static(SUBPROTOCOL),
-                );
-            };
-            Ok(res)
-        },
-    )
-    .await
+///
+/// If the server has an authenticator, the request is rejected unless it authenticates the client,
+/// and the returned identity is attached to the client.
+async fn do_handshake(
+    stream: ClientStream,
+    authenticator: Option<&dyn Authenticator>,
+) -> Result<
+    (
+        WebSocketStream<ClientStream>,
+        Option<ClientIdentity>,
+        Handshake,
+    ),
+    tungstenite::Error,
+> {
+    let mut identity = None;
+    let mut handshake = Handshake {
+        user_agent: None,
+        path: String::new(),
+        connected_at: SystemTime::now(),
+    };
+    // The error type is tungstenite's `ErrorResponse`, which is required by its callback API, so
+    // it can't be boxed to satisfy clippy.
+    #[allow(clippy::result_large_err)]
+    let callback = |req: &server::Request, mut res: server::Response| {
+        handshake.path = req.uri().path().to_string();
+        handshake.user_agent = req
+            .headers()
+            .get("user-agent")
+            .and_then(|v| v.to_str().ok())
+            .map(String::from);
+        if let Some(authenticator) = authenticator {
+            match authenticator.authenticate(&AuthRequest::new(req)) {
+                Ok(id) => identity = Some(id),
+                Err(rejection) => {
+                    tracing::debug!("Rejecting client request for {}: {rejection}", req.uri());
+                    return Err(rejection.into_response());
+                }
+            }
+        }
+        let all_headers = req.headers().get_all("sec-websocket-protocol");
+        if all_headers.iter().any(|h| {
+            (*h).to_str()
+                .unwrap_or_default()
+                .split(',')
+                .any(|s| s.trim() == SUBPROTOCOL)
+        }) {
+            res.headers_mut().insert(
+                "sec-websocket-protocol",
+                HeaderValue::from_static(SUBPROTOCOL),
+            );
+        };
+        Ok(res)
+    };
+    let ws_stream = tokio_tungstenite::accept_hdr_async(stream, callback).await?;
+    Ok((ws_stream, identity, handshake))
+}
+
+/// Information captured from a client's handshake request.
+#[derive(Debug)]
+struct Handshake {
+    user_agent: Option<String>,
+    path: String,
+    connected_at: SystemTime,
 }
@@ -0,0 +1,259 @@
+//! Websocket asset fetching.
+//!
+//! Clients such as the Foxglove 3D panel fetch assets, like meshes referenced by URDF files or
+//! [`ModelPrimitive`](crate::schemas::ModelPrimitive) URLs, from the server with the
+//! [fetch asset][spec] message. The server resolves these requests with an [`AssetHandler`].
+//!
+//! [spec]: https://github.com/foxglove/ws-protocol/blob/main/docs/spec.md#fetch-asset
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::websocket::{protocol, Client, ConnectedClient};
+
+/// A handler for asset requests from clients.
+pub trait AssetHandler: Send + Sync {
+    /// Fetches the asset identified by `uri`.
+    ///
+    /// This method is invoked from the client's main poll loop and must not block. If blocking or
+    /// long-running behavior is required, the implementation should use [`tokio::task::spawn`] (or
+    /// [`tokio::task::spawn_blocking`]) to handle the request asynchronously.
+    ///
+    /// The implementation is responsible for completing the request with
+    /// [`AssetResponder::respond`]. If the responder is dropped without responding, the client
+    /// receives an error response.
+    fn fetch(&self, client: Client, uri: String, responder: AssetResponder);
+}
+
+impl<F> AssetHandler for F
+where
+    F: Fn(Client, String, AssetResponder) + Send + Sync,
+{
+    fn fetch(&self, client: Client, uri: String, responder: AssetResponder) {
+        self(client, uri, responder);
+    }
+}
+
+/// The error message sent when a responder is dropped without responding.
+const NO_RESPONSE: &str = "internal error: no response";
+
+/// A handle for completing an asset request.
+///
+/// If you drop the responder without responding, the client receives an error response.
+#[must_use]
+pub struct AssetResponder {
+    client: Arc<ConnectedClient>,
+    request_id: u32,
+    responded: bool,
+}
+
+impl AssetResponder {
+    pub(crate) fn new(client: Arc<ConnectedClient>, request_id: u32) -> Self {
+        Self {
+            client,
+            request_id,
+            responded: false,
+        }
+    }
+
+    /// Completes the request by sending the asset data, or an error message, to the client.
+    pub fn respond(mut self, result: Result<Bytes, String>) {
+        self.send(result);
+    }
+
+    fn send(&mut self, result: Result<Bytes, String>) {
+        self.responded = true;
+        let message = Message::binary(protocol::server::fetch_asset_response(
+            self.request_id,
+            result,
+        ));
+        // Callee logs errors.
+        let _ = self.client.send_control_msg(message);
+    }
+}
+
+impl Drop for AssetResponder {
+    fn drop(&mut self) {
+        if !self.responded {
+            tracing::warn!(
+                "Responder for asset request {} dropped without responding",
+                self.request_id
+            );
+            self.send(Err(NO_RESPONSE.to_string()));
+        }
+    }
+}
+
+/// Serves assets from a directory on the local filesystem.
+///
+/// Requests must use `file://` URIs with absolute paths inside the root directory. Paths that
+/// resolve to a location outside of the root directory, for example via `..` or symbolic links,
+/// are rejected.
+#[derive(Debug, Clone)]
+pub struct FilesystemAssetHandler {
+    root: PathBuf,
+}
+
+impl FilesystemAssetHandler {
+    /// Creates a handler which serves files under `root`.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn resolve(&self, uri: &str) -> Result<PathBuf, String> {
+        let path = uri
+            .strip_prefix("file://")
+            .ok_or_else(|| format!("Unsupported asset URI: {uri}"))?;
+        Ok(PathBuf::from(path))
+    }
+}
+
+impl AssetHandler for FilesystemAssetHandler {
+    fn fetch(&self, _client: Client, uri: String, responder: AssetResponder) {
+        let root = self.root.clone();
+        let path = self.resolve(&uri);
+        tokio::task::spawn_blocking(move || {
+            responder.respond(path.and_then(|path| read_asset(&root, &path)));
+        });
+    }
+}
+
+/// Serves assets from ROS packages, using `package://<package>/<path>` URIs.
+///
+/// Packages are located either by explicit registration with
+/// [`package`](PackageAssetHandler::package), or by looking for a directory with the package name
+/// in each of the [search paths](PackageAssetHandler::search_path).
+///
+/// Paths that resolve to a location outside of the package directory are rejected.
+#[derive(Debug, Clone, Default)]
+pub struct PackageAssetHandler {
+    packages: HashMap<String, PathBuf>,
+    search_paths: Vec<PathBuf>,
+}
+
+impl PackageAssetHandler {
+    /// Creates a handler with no known packages.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a handler which searches the directories listed in the `ROS_PACKAGE_PATH`
+    /// environment variable.
+    pub fn from_env() -> Self {
+        let mut handler = Self::new();
+        if let Some(paths) = std::env::var_os("ROS_PACKAGE_PATH") {
+            handler.search_paths = std::env::split_paths(&paths).collect();
+        }
+        handler
+    }
+
+    /// Registers the directory for a package.
+    pub fn package(mut self, name: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        self.packages.insert(name.into(), path.into());
+        self
+    }
+
+    /// Adds a directory which contains packages.
+    pub fn search_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.search_paths.push(path.into());
+        self
+    }
+
+    /// Returns the package directory and the path of the asset within it.
+    fn resolve(&self, uri: &str) -> Result<(PathBuf, PathBuf), String> {
+        let (package, path) = uri
+            .strip_prefix("package://")
+            .and_then(|rest| rest.split_once('/'))
+            .ok_or_else(|| format!("Unsupported asset URI: {uri}"))?;
+        let root = self
+            .packages
+            .get(package)
+            .cloned()
+            .or_else(|| {
+                self.search_paths
+                    .iter()
+                    .map(|dir| dir.join(package))
+                    .find(|dir| dir.is_dir())
+            })
+            .ok_or_else(|| format!("Unknown package: {package}"))?;
+        let path = root.join(path);
+        Ok((root, path))
+    }
+}
+
+impl AssetHandler for PackageAssetHandler {
+    fn fetch(&self, _client: Client, uri: String, responder: AssetResponder) {
+        let handler = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let result = handler
+                .resolve(&uri)
+                .and_then(|(root, path)| read_asset(&root, &path));
+            responder.respond(result);
+        });
+    }
+}
+
+/// Reads the file at `path`, provided that it is contained by `root`.
+fn read_asset(root: &Path, path: &Path) -> Result<Bytes, String> {
+    let not_found = || format!("Asset not found: {}", path.display());
+    if !path.is_absolute() || path.components().any(|c| c == Component::ParentDir) {
+        return Err(not_found());
+    }
+    // Resolve symbolic links before checking that the file is inside the root.
+    let root = root.canonicalize().map_err(|_| not_found())?;
+    let path = path.canonicalize().map_err(|_| not_found())?;
+    if !path.starts_with(&root) || !path.is_file() {
+        return Err(not_found());
+    }
+    std::fs::read(&path)
+        .map(Bytes::from)
+        .map_err(|err| format!("Failed to read asset {}: {err}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_asset() {
+        let dir = tempfile::tempdir().expect("failed to create tempdir");
+        let root = dir.path().join("root");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("mesh.stl"), b"mesh").unwrap();
+        std::fs::write(dir.path().join("secret"), b"secret").unwrap();
+
+        assert_eq!(read_asset(&root, &root.join("mesh.stl")).unwrap(), "mesh");
+        assert!(read_asset(&root, &root.join("missing.stl")).is_err());
+        assert!(read_asset(&root, &root.join("../secret")).is_err());
+        assert!(read_asset(&root, &dir.path().join("secret")).is_err());
+        assert!(read_asset(&root, Path::new("mesh.stl")).is_err());
+        // Directories are not assets.
+        assert!(read_asset(dir.path(), &root).is_err());
+    }
+
+    #[test]
+    fn test_resolve_package() {
+        let dir = tempfile::tempdir().expect("failed to create tempdir");
+        std::fs::create_dir(dir.path().join("found")).unwrap();
+        let handler = PackageAssetHandler::new()
+            .package("explicit", "/opt/explicit")
+            .search_path(dir.path());
+
+        assert_eq!(
+            handler.resolve("package://explicit/meshes/a.stl").unwrap(),
+            (
+                PathBuf::from("/opt/explicit"),
+                PathBuf::from("/opt/explicit/meshes/a.stl")
+            )
+        );
+        assert_eq!(
+            handler.resolve("package://found/a.stl").unwrap(),
+            (dir.path().join("found"), dir.path().join("found/a.stl"))
+        );
+        assert!(handler.resolve("package://missing/a.stl").is_err());
+        assert!(handler.resolve("file:///a.stl").is_err());
+    }
+}
@@ -0,0 +1,202 @@
+//! Authentication of websocket clients.
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request};
+use tokio_tungstenite::tungstenite::http::{Response, StatusCode};
+
+/// Authenticates clients during the websocket handshake.
+///
+/// The authenticator is invoked with the HTTP upgrade request for each new connection. If it
+/// returns an identity, the connection is accepted, and the identity is available to
+/// [`ServerListener`](super::ServerListener) callbacks and service handlers via
+/// [`Client::identity`](super::Client::identity). Otherwise, the connection is rejected with the
+/// status code of the [`AuthRejection`].
+///
+/// This method is invoked from the server's connection handler and must not block.
+pub trait Authenticator: Send + Sync {
+    /// Authenticates a client's upgrade request.
+    fn authenticate(&self, request: &AuthRequest) -> Result<ClientIdentity, AuthRejection>;
+}
+
+impl<F> Authenticator for F
+where
+    F: Fn(&AuthRequest) -> Result<ClientIdentity, AuthRejection> + Send + Sync,
+{
+    fn authenticate(&self, request: &AuthRequest) -> Result<ClientIdentity, AuthRejection> {
+        self(request)
+    }
+}
+
+/// A client's HTTP upgrade request.
+#[derive(Debug)]
+pub struct AuthRequest<'a>(&'a Request);
+
+impl<'a> AuthRequest<'a> {
+    pub(crate) fn new(request: &'a Request) -> Self {
+        Self(request)
+    }
+
+    /// Returns the request path.
+    pub fn path(&self) -> &str {
+        self.0.uri().path()
+    }
+
+    /// Returns the query string, if any.
+    pub fn query(&self) -> Option<&str> {
+        self.0.uri().query()
+    }
+
+    /// Returns the value of the named query parameter, if present.
+    ///
+    /// The value is returned as it appears in the query string, without percent-decoding.
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query()?.split('&').find_map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (key == name).then_some(value)
+        })
+    }
+
+    /// Returns the value of the named header, if present and valid UTF-8.
+    ///
+    /// Header names are case-insensitive. If the header is repeated, the first value is returned.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.0.headers().get(name)?.to_str().ok()
+    }
+
+    /// Returns an iterator over all header names and values.
+    pub fn headers(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.0
+            .headers()
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_bytes()))
+    }
+}
+
+/// The identity of an authenticated client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientIdentity {
+    name: String,
+    attributes: BTreeMap<String, String>,
+}
+
+impl ClientIdentity {
+    /// Creates a new identity with the given name, such as a user name.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            attributes: BTreeMap::new(),
+        }
+    }
+
+    /// Adds an attribute, such as a role, to the identity.
+    #[must_use]
+    pub fn with_attribute(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.attributes.insert(key.into(), value.into());
+        self
+    }
+
+    /// Returns the name of the identity.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the value of the named attribute.
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes.get(key).map(String::as_str)
+    }
+
+    /// Returns all attributes of the identity.
+    pub fn attributes(&self) -> &BTreeMap<String, String> {
+        &self.attributes
+    }
+}
+
+/// The reason that a client's upgrade request was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthRejection {
+    status: u16,
+    message: String,
+}
+
+impl AuthRejection {
+    /// Creates a rejection with an HTTP status code and a message for the client.
+    ///
+    /// If the status code is not a valid HTTP error status (400-599), `401 Unauthorized` is used
+    /// instead.
+    pub fn new(status: u16, message: impl Into<String>) -> Self {
+        let status = if (400..600).contains(&status) {
+            status
+        } else {
+            StatusCode::UNAUTHORIZED.as_u16()
+        };
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    /// Creates a `401 Unauthorized` rejection.
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED.as_u16(), message)
+    }
+
+    /// Creates a `403 Forbidden` rejection.
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN.as_u16(), message)
+    }
+
+    /// Returns the HTTP status code.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Returns the message sent to the client.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub(crate) fn into_response(self) -> ErrorResponse {
+        let mut response = Response::new(Some(self.message));
+        *response.status_mut() =
+            StatusCode::from_u16(self.status).unwrap_or(StatusCode::UNAUTHORIZED);
+        response
+    }
+}
+
+impl Display for AuthRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.status, self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auth_request() {
+        let request = Request::builder()
+            .uri("ws://localhost:8765/path?token=abc&empty&x=1")
+            .header("Authorization", "Bearer abc")
+            .body(())
+            .unwrap();
+        let request = AuthRequest::new(&request);
+        assert_eq!(request.path(), "/path");
+        assert_eq!(request.query(), Some("token=abc&empty&x=1"));
+        assert_eq!(request.query_param("token"), Some("abc"));
+        assert_eq!(request.query_param("empty"), Some(""));
+        assert_eq!(request.query_param("missing"), None);
+        assert_eq!(request.header("authorization"), Some("Bearer abc"));
+        assert_eq!(request.headers().count(), 1);
+    }
+
+    #[test]
+    fn test_rejection_status() {
+        assert_eq!(AuthRejection::new(403, "no").status(), 403);
+        assert_eq!(AuthRejection::new(200, "no").status(), 401);
+        let response = AuthRejection::forbidden("go away").into_response();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(response.body().as_deref(), Some("go away"));
+    }
+}
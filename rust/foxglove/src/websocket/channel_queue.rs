@@ -0,0 +1,247 @@
+//! Per-channel queueing of outgoing data messages.
+use std::collections::VecDeque;
+
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::channel::ChannelId;
+
+/// How the server queues outgoing messages for a channel.
+///
+/// By default, messages for all channels share a single per-client queue, and when the queue is
+/// full, the oldest message is dropped regardless of which channel it belongs to. A burst of
+/// messages on a high-rate channel can therefore push out messages on low-rate channels.
+///
+/// Channels with a queue policy get a dedicated per-client queue instead. When the queue is full,
+/// the oldest message for that channel is dropped. The server sends messages from queues of the
+/// highest priority first, and takes turns between queues of equal priority, including the shared
+/// queue, which has priority 0.
+///
+/// Messages dropped from a dedicated queue count towards the server's
+/// [`SlowClientPolicy`](crate::websocket::SlowClientPolicy), in the same way as messages dropped
+/// from the shared queue. With [`QueuePolicy::latest_only`], every message which replaces one the
+/// client hasn't received yet counts as an overflow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueuePolicy {
+    depth: usize,
+    priority: u8,
+}
+
+impl QueuePolicy {
+    /// Keep only the latest message for the channel.
+    ///
+    /// This is useful for channels like transforms or status, where a client only needs the most
+    /// recent message.
+    pub fn latest_only() -> Self {
+        Self::bounded(1)
+    }
+
+    /// Keep up to `depth` messages for the channel, dropping the oldest when full.
+    ///
+    /// A depth of 0 is treated as 1.
+    pub fn bounded(depth: usize) -> Self {
+        Self {
+            depth: depth.max(1),
+            priority: 0,
+        }
+    }
+
+    /// Sets the priority of the channel's queue.
+    ///
+    /// Messages on queues with a higher priority are sent first. The default priority is 0, which
+    /// is the same as the priority of the shared queue.
+    ///
+    /// Priority is strict: no messages are sent from lower priority queues, including the shared
+    /// queue, while any higher priority queue has messages. If a high priority channel is logged
+    /// faster than the client can receive, messages on all lower priority channels are delayed,
+    /// and eventually dropped, for as long as that continues. Use priorities for low-rate
+    /// channels, or with [`QueuePolicy::latest_only`] so that a backlog can't build up.
+    #[must_use]
+    pub fn with_priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Returns the maximum number of messages queued for the channel.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the priority of the channel's queue.
+    pub fn priority(&self) -> u8 {
+        self.priority
+    }
+}
+
+#[derive(Debug)]
+struct ChannelQueue {
+    channel_id: ChannelId,
+    policy: QueuePolicy,
+    messages: VecDeque<Message>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    queues: Vec<ChannelQueue>,
+    /// Round-robin position. An index equal to `queues.len()` refers to the shared queue.
+    cursor: usize,
+}
+
+/// The dedicated per-channel queues for a client.
+#[derive(Debug, Default)]
+pub(crate) struct ChannelQueues {
+    state: Mutex<State>,
+    notify: Notify,
+}
+
+impl ChannelQueues {
+    /// Queues a message for the channel, returning the number of messages dropped to make room.
+    pub fn push(&self, channel_id: ChannelId, policy: QueuePolicy, message: Message) -> usize {
+        let mut dropped = 0;
+        {
+            let mut state = self.state.lock();
+            let index = match state.queues.iter().position(|q| q.channel_id == channel_id) {
+                Some(index) => index,
+                None => {
+                    state.queues.push(ChannelQueue {
+                        channel_id,
+                        policy,
+                        messages: VecDeque::with_capacity(policy.depth),
+                    });
+                    state.queues.len() - 1
+                }
+            };
+            let queue = &mut state.queues[index];
+            queue.policy = policy;
+            while queue.messages.len() >= queue.policy.depth {
+                queue.messages.pop_front();
+                dropped += 1;
+            }
+            queue.messages.push_back(message);
+        }
+        self.notify.notify_one();
+        dropped
+    }
+
+    /// Returns the next message to send.
+    ///
+    /// Messages are taken from the highest priority non-empty queue. Queues of the same priority
+    /// are served in turn. The shared queue has priority 0.
+    pub fn pop(&self, shared: &flume::Receiver<Message>) -> Option<Message> {
+        let mut state = self.state.lock();
+        let priority = state
+            .queues
+            .iter()
+            .filter(|q| !q.messages.is_empty())
+            .map(|q| q.policy.priority)
+            .max()
+            .unwrap_or(0);
+        // One slot per channel queue, plus the shared queue.
+        let slots = state.queues.len() + 1;
+        for offset in 0..slots {
+            let index = (state.cursor + offset) % slots;
+            let message = match state.queues.get_mut(index) {
+                Some(queue) if queue.policy.priority == priority => queue.messages.pop_front(),
+                Some(_) => None,
+                None if priority == 0 => shared.try_recv().ok(),
+                None => None,
+            };
+            if message.is_some() {
+                state.cursor = index + 1;
+                return message;
+            }
+        }
+        None
+    }
+
+    /// Returns the total number of messages in the channel queues.
+    pub fn len(&self) -> usize {
+        self.state
+            .lock()
+            .queues
+            .iter()
+            .map(|q| q.messages.len())
+            .sum()
+    }
+
+    /// Wakes the sender after a message is pushed to the shared queue.
+    pub fn notify(&self) {
+        self.notify.notify_one();
+    }
+
+    /// Waits until a message is pushed to a channel queue, or [`ChannelQueues::notify`] is
+    /// called.
+    pub async fn notified(&self) {
+        self.notify.notified().await;
+    }
+
+    /// Removes the queue for a channel, discarding any pending messages.
+    pub fn remove(&self, channel_id: ChannelId) {
+        self.state
+            .lock()
+            .queues
+            .retain(|q| q.channel_id != channel_id);
+    }
+
+    /// Discards all pending messages.
+    pub fn clear(&self) {
+        self.state.lock().queues.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(text: &str) -> Message {
+        Message::text(text.to_string())
+    }
+
+    fn drain(queues: &ChannelQueues, shared: &flume::Receiver<Message>) -> Vec<String> {
+        std::iter::from_fn(|| queues.pop(shared))
+            .map(|m| m.into_text().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_latest_only() {
+        let queues = ChannelQueues::default();
+        let (_tx, rx) = flume::bounded(1);
+        let policy = QueuePolicy::latest_only();
+        assert_eq!(queues.push(ChannelId::new(1), policy, message("a")), 0);
+        assert_eq!(queues.push(ChannelId::new(1), policy, message("b")), 1);
+        assert_eq!(queues.push(ChannelId::new(2), policy, message("c")), 0);
+        assert_eq!(drain(&queues, &rx), vec!["b", "c"]);
+    }
+
+    #[test]
+    fn test_round_robin_with_shared_queue() {
+        let queues = ChannelQueues::default();
+        let (tx, rx) = flume::bounded(10);
+        for text in ["s1", "s2", "s3"] {
+            tx.send(message(text)).unwrap();
+        }
+        let policy = QueuePolicy::bounded(2);
+        for text in ["a1", "a2", "a3"] {
+            queues.push(ChannelId::new(1), policy, message(text));
+        }
+        assert_eq!(drain(&queues, &rx), vec!["a2", "s1", "a3", "s2", "s3"]);
+    }
+
+    #[test]
+    fn test_priority() {
+        let queues = ChannelQueues::default();
+        let (tx, rx) = flume::bounded(10);
+        tx.send(message("shared")).unwrap();
+        queues.push(ChannelId::new(1), QueuePolicy::bounded(10), message("low"));
+        let high = QueuePolicy::latest_only().with_priority(1);
+        queues.push(ChannelId::new(2), high, message("high"));
+        // Queues with equal priority take turns, starting after the last queue served.
+        assert_eq!(drain(&queues, &rx), vec!["high", "shared", "low"]);
+
+        queues.push(ChannelId::new(2), high, message("high"));
+        queues.remove(ChannelId::new(2));
+        assert!(queues.pop(&rx).is_none());
+    }
+}
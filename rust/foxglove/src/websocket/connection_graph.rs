@@ -0,0 +1,212 @@
+//! Connection graph, describing the publishers, subscribers and services of a system.
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::protocol::server::{
+    AdvertisedService, ConnectionGraphUpdate, PublishedTopic, SubscribedTopic,
+};
+
+type Graph = BTreeMap<String, BTreeSet<String>>;
+
+/// A snapshot of the connection graph, for the Foxglove Topic Graph panel.
+///
+/// The graph describes which publishers and subscribers are connected to each topic, and which
+/// providers advertise each service. Publishers, subscribers and providers are identified by
+/// arbitrary strings, such as node names.
+///
+/// Publish the graph to clients with
+/// [`WebSocketServerHandle::replace_connection_graph`](crate::WebSocketServerHandle::replace_connection_graph).
+/// The server sends subscribed clients only the differences from the previous graph.
+///
+/// For more information, refer to the [Connection Graph Update][spec] message specification.
+///
+/// [spec]: https://github.com/foxglove/ws-protocol/blob/main/docs/spec.md#connection-graph-update
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnectionGraph {
+    published_topics: Graph,
+    subscribed_topics: Graph,
+    advertised_services: Graph,
+}
+
+impl ConnectionGraph {
+    /// Creates a new, empty connection graph.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the publishers of a topic.
+    ///
+    /// Replaces any publishers previously set for the topic.
+    pub fn set_published_topic(
+        &mut self,
+        topic: impl Into<String>,
+        publisher_ids: impl IntoIterator<Item = impl Into<String>>,
+    ) {
+        insert(&mut self.published_topics, topic, publisher_ids);
+    }
+
+    /// Sets the subscribers of a topic.
+    ///
+    /// Replaces any subscribers previously set for the topic.
+    pub fn set_subscribed_topic(
+        &mut self,
+        topic: impl Into<String>,
+        subscriber_ids: impl IntoIterator<Item = impl Into<String>>,
+    ) {
+        insert(&mut self.subscribed_topics, topic, subscriber_ids);
+    }
+
+    /// Sets the providers of a service.
+    ///
+    /// Replaces any providers previously set for the service.
+    pub fn set_advertised_service(
+        &mut self,
+        service: impl Into<String>,
+        provider_ids: impl IntoIterator<Item = impl Into<String>>,
+    ) {
+        insert(&mut self.advertised_services, service, provider_ids);
+    }
+
+    /// Returns true if the topic has publishers or subscribers.
+    fn has_topic(&self, topic: &str) -> bool {
+        self.published_topics.contains_key(topic) || self.subscribed_topics.contains_key(topic)
+    }
+
+    /// Returns the update which transforms this graph into `other`.
+    pub(crate) fn diff<'a>(&'a self, other: &'a Self) -> ConnectionGraphUpdate<'a> {
+        let removed_topics: Vec<&str> = self
+            .published_topics
+            .keys()
+            .chain(self.subscribed_topics.keys())
+            .map(String::as_str)
+            .filter(|topic| !other.has_topic(topic))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let removed_services = self
+            .advertised_services
+            .keys()
+            .filter(|service| !other.advertised_services.contains_key(*service))
+            .map(String::as_str)
+            .collect();
+        ConnectionGraphUpdate {
+            published_topics: changed(&self.published_topics, &other.published_topics, |t| {
+                other.has_topic(t)
+            })
+            .map(|(name, publisher_ids)| PublishedTopic {
+                name,
+                publisher_ids,
+            })
+            .collect(),
+            subscribed_topics: changed(&self.subscribed_topics, &other.subscribed_topics, |t| {
+                other.has_topic(t)
+            })
+            .map(|(name, subscriber_ids)| SubscribedTopic {
+                name,
+                subscriber_ids,
+            })
+            .collect(),
+            advertised_services: changed(
+                &self.advertised_services,
+                &other.advertised_services,
+                |_| false,
+            )
+            .map(|(name, provider_ids)| AdvertisedService { name, provider_ids })
+            .collect(),
+            removed_topics,
+            removed_services,
+        }
+    }
+}
+
+fn insert(
+    graph: &mut Graph,
+    name: impl Into<String>,
+    ids: impl IntoIterator<Item = impl Into<String>>,
+) {
+    graph.insert(name.into(), ids.into_iter().map(Into::into).collect());
+}
+
+/// Returns the entries whose ids differ between `old` and `new`.
+///
+/// Entries that are only present in `old` are reported with an empty set of ids if `retained`
+/// returns true for their name. Otherwise, the caller is expected to report them as removed.
+fn changed<'a>(
+    old: &'a Graph,
+    new: &'a Graph,
+    retained: impl Fn(&str) -> bool + 'a,
+) -> impl Iterator<Item = (&'a str, Vec<&'a str>)> {
+    let updated = new
+        .iter()
+        .filter(|(name, ids)| old.get(*name) != Some(ids))
+        .map(|(name, ids)| (name.as_str(), ids.iter().map(String::as_str).collect()));
+    let emptied = old
+        .keys()
+        .filter(move |name| !new.contains_key(*name) && retained(name))
+        .map(|name| (name.as_str(), Vec::new()));
+    updated.chain(emptied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_from_empty() {
+        let mut graph = ConnectionGraph::new();
+        graph.set_published_topic("/a", ["pub1", "pub2"]);
+        graph.set_subscribed_topic("/a", ["sub1"]);
+        graph.set_advertised_service("/svc", ["provider"]);
+        let empty = ConnectionGraph::default();
+        let update = empty.diff(&graph);
+        assert_eq!(
+            serde_json::to_value(&update).unwrap(),
+            json!({
+                "op": "connectionGraphUpdate",
+                "publishedTopics": [{"name": "/a", "publisherIds": ["pub1", "pub2"]}],
+                "subscribedTopics": [{"name": "/a", "subscriberIds": ["sub1"]}],
+                "advertisedServices": [{"name": "/svc", "providerIds": ["provider"]}],
+                "removedTopics": [],
+                "removedServices": [],
+            })
+        );
+        assert!(graph.diff(&graph).is_empty());
+    }
+
+    #[test]
+    fn test_incremental_diff() {
+        let mut old = ConnectionGraph::new();
+        old.set_published_topic("/a", ["pub1"]);
+        old.set_published_topic("/b", ["pub1"]);
+        old.set_subscribed_topic("/b", ["sub1"]);
+        old.set_published_topic("/c", ["pub2"]);
+        old.set_advertised_service("/svc1", ["provider"]);
+        old.set_advertised_service("/svc2", ["provider"]);
+
+        let mut new = ConnectionGraph::new();
+        // Unchanged.
+        new.set_published_topic("/a", ["pub1"]);
+        // No longer published, but still subscribed.
+        new.set_subscribed_topic("/b", ["sub1"]);
+        // /c is removed, and /d is added.
+        new.set_published_topic("/d", ["pub2"]);
+        // Changed providers.
+        new.set_advertised_service("/svc1", ["other"]);
+
+        let update = old.diff(&new);
+        assert_eq!(
+            serde_json::to_value(&update).unwrap(),
+            json!({
+                "op": "connectionGraphUpdate",
+                "publishedTopics": [
+                    {"name": "/d", "publisherIds": ["pub2"]},
+                    {"name": "/b", "publisherIds": []},
+                ],
+                "subscribedTopics": [],
+                "advertisedServices": [{"name": "/svc1", "providerIds": ["other"]}],
+                "removedTopics": ["/c"],
+                "removedServices": ["/svc2"],
+            })
+        );
+    }
+}
@@ -0,0 +1,537 @@
+//! Server-side parameter store.
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Weak};
+
+use parking_lot::{Mutex, RwLock};
+
+use super::{Parameter, ParameterType, ParameterValue, Server};
+use crate::FoxgloveError;
+
+/// A Rust type which can be stored in a [`ParameterStore`].
+pub trait TypedParameterValue: Sized + Send + Sync + 'static {
+    /// The parameter type advertised to clients, if any.
+    fn parameter_type() -> Option<ParameterType> {
+        None
+    }
+
+    /// Converts the value into a parameter value.
+    fn into_value(self) -> ParameterValue;
+
+    /// Converts a parameter value into this type, or returns `None` if the value has a different
+    /// type.
+    fn from_value(value: &ParameterValue) -> Option<Self>;
+}
+
+impl TypedParameterValue for f64 {
+    fn parameter_type() -> Option<ParameterType> {
+        Some(ParameterType::Float64)
+    }
+
+    fn into_value(self) -> ParameterValue {
+        ParameterValue::Number(self)
+    }
+
+    fn from_value(value: &ParameterValue) -> Option<Self> {
+        match value {
+            ParameterValue::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+impl TypedParameterValue for i64 {
+    fn into_value(self) -> ParameterValue {
+        ParameterValue::Number(self as f64)
+    }
+
+    fn from_value(value: &ParameterValue) -> Option<Self> {
+        match value {
+            ParameterValue::Number(n) if n.fract() == 0.0 => Some(*n as i64),
+            _ => None,
+        }
+    }
+}
+
+impl TypedParameterValue for bool {
+    fn into_value(self) -> ParameterValue {
+        ParameterValue::Bool(self)
+    }
+
+    fn from_value(value: &ParameterValue) -> Option<Self> {
+        match value {
+            ParameterValue::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+impl TypedParameterValue for String {
+    fn into_value(self) -> ParameterValue {
+        ParameterValue::String(self.into_bytes())
+    }
+
+    fn from_value(value: &ParameterValue) -> Option<Self> {
+        match value {
+            ParameterValue::String(s) => String::from_utf8(s.clone()).ok(),
+            _ => None,
+        }
+    }
+}
+
+impl TypedParameterValue for Vec<u8> {
+    fn parameter_type() -> Option<ParameterType> {
+        Some(ParameterType::ByteArray)
+    }
+
+    fn into_value(self) -> ParameterValue {
+        ParameterValue::String(self)
+    }
+
+    fn from_value(value: &ParameterValue) -> Option<Self> {
+        match value {
+            ParameterValue::String(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl TypedParameterValue for Vec<f64> {
+    fn parameter_type() -> Option<ParameterType> {
+        Some(ParameterType::Float64Array)
+    }
+
+    fn into_value(self) -> ParameterValue {
+        ParameterValue::Array(self.into_iter().map(ParameterValue::Number).collect())
+    }
+
+    fn from_value(value: &ParameterValue) -> Option<Self> {
+        match value {
+            ParameterValue::Array(values) => values.iter().map(f64::from_value).collect(),
+            _ => None,
+        }
+    }
+}
+
+type Validator = Arc<dyn Fn(&ParameterValue) -> Result<(), String> + Send + Sync>;
+type ChangeCallback = Arc<dyn Fn(&ParameterValue) + Send + Sync>;
+type TypedValidator<T> = Box<dyn Fn(&T) -> Result<(), String> + Send + Sync>;
+type TypedChangeCallback<T> = Box<dyn Fn(&T) + Send + Sync>;
+
+/// The declaration of a typed parameter, for use with [`ParameterStore::declare`].
+#[must_use]
+pub struct TypedParameter<T> {
+    name: String,
+    value: T,
+    read_only: bool,
+    validator: Option<TypedValidator<T>>,
+    on_change: Option<TypedChangeCallback<T>>,
+}
+
+impl<T: TypedParameterValue> TypedParameter<T> {
+    /// Creates a new parameter declaration with an initial value.
+    pub fn new(name: impl Into<String>, value: T) -> Self {
+        Self {
+            name: name.into(),
+            value,
+            read_only: false,
+            validator: None,
+            on_change: None,
+        }
+    }
+
+    /// Prevents clients from changing the parameter.
+    ///
+    /// The parameter can still be changed with [`ParameterStore::set`].
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    /// Sets a function to validate new values.
+    ///
+    /// If the validator returns an error, the value is not changed, and the error is reported to
+    /// the client or the caller of [`ParameterStore::set`].
+    pub fn validator<F, E>(mut self, validator: F) -> Self
+    where
+        F: Fn(&T) -> Result<(), E> + Send + Sync + 'static,
+        E: ToString,
+    {
+        self.validator = Some(Box::new(move |v| validator(v).map_err(|e| e.to_string())));
+        self
+    }
+
+    /// Sets a callback to be invoked when the value changes.
+    ///
+    /// The callback is invoked from the thread that changed the value, which may be the client's
+    /// main poll loop. It must not block.
+    pub fn on_change(mut self, callback: impl Fn(&T) + Send + Sync + 'static) -> Self {
+        self.on_change = Some(Box::new(callback));
+        self
+    }
+
+    fn into_entry(self) -> (String, Entry) {
+        let validator = self.validator;
+        let on_change = self.on_change;
+        let entry = Entry {
+            value: self.value.into_value(),
+            r#type: T::parameter_type(),
+            read_only: self.read_only,
+            validator: Arc::new(move |value| {
+                let value = T::from_value(value).ok_or("Invalid parameter type")?;
+                match &validator {
+                    Some(validator) => validator(&value),
+                    None => Ok(()),
+                }
+            }),
+            on_change: on_change.map(|callback| {
+                Arc::new(move |value: &ParameterValue| {
+                    if let Some(value) = T::from_value(value) {
+                        callback(&value);
+                    }
+                }) as ChangeCallback
+            }),
+        };
+        (self.name, entry)
+    }
+}
+
+impl<T> Debug for TypedParameter<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TypedParameter")
+            .field("name", &self.name)
+            .field("read_only", &self.read_only)
+            .finish_non_exhaustive()
+    }
+}
+
+struct Entry {
+    value: ParameterValue,
+    r#type: Option<ParameterType>,
+    read_only: bool,
+    validator: Validator,
+    on_change: Option<ChangeCallback>,
+}
+
+impl Entry {
+    fn parameter(&self, name: &str) -> Parameter {
+        Parameter {
+            name: name.to_string(),
+            r#type: self.r#type.clone(),
+            value: Some(self.value.clone()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: RwLock<BTreeMap<String, Entry>>,
+    servers: Mutex<Vec<Weak<Server>>>,
+}
+
+/// A store of typed parameters, which answers parameter requests from clients.
+///
+/// When a store is attached to a server with
+/// [`WebSocketServer::parameter_store`](crate::WebSocketServer::parameter_store), the server
+/// handles `getParameters` and `setParameters` requests from the store, and notifies subscribed
+/// clients whenever a parameter changes. In that case, the
+/// [`ServerListener`](super::ServerListener) parameter get and set callbacks are not invoked.
+///
+/// The store is cheap to clone, and clones share the same parameters.
+///
+/// # Example
+/// ```
+/// use foxglove::websocket::{ParameterStore, TypedParameter};
+///
+/// # fn func() -> Result<(), foxglove::FoxgloveError> {
+/// let store = ParameterStore::new();
+/// store.declare(
+///     TypedParameter::new("gain", 1.0)
+///         .validator(|v: &f64| if *v >= 0.0 { Ok(()) } else { Err("must not be negative") })
+///         .on_change(|v| println!("gain changed to {v}")),
+/// )?;
+/// store.declare(TypedParameter::new("robot", "r2d2".to_string()).read_only())?;
+///
+/// store.set("gain", 2.0)?;
+/// assert_eq!(store.get::<f64>("gain"), Some(2.0));
+/// # Ok(()) }
+/// ```
+#[derive(Clone, Default)]
+pub struct ParameterStore {
+    inner: Arc<Inner>,
+}
+
+impl Debug for ParameterStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ParameterStore")
+            .field(
+                "names",
+                &self.inner.entries.read().keys().collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl ParameterStore {
+    /// Creates a new, empty parameter store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares a new parameter.
+    ///
+    /// Returns an error if a parameter with the same name has already been declared.
+    pub fn declare<T: TypedParameterValue>(
+        &self,
+        parameter: TypedParameter<T>,
+    ) -> Result<(), FoxgloveError> {
+        let (name, entry) = parameter.into_entry();
+        let parameter = {
+            let mut entries = self.inner.entries.write();
+            if entries.contains_key(&name) {
+                return Err(FoxgloveError::DuplicateParameter(name));
+            }
+            let parameter = entry.parameter(&name);
+            entries.insert(name, entry);
+            parameter
+        };
+        self.publish(vec![parameter]);
+        Ok(())
+    }
+
+    /// Returns the value of a parameter.
+    ///
+    /// Returns `None` if the parameter has not been declared, or has a different type.
+    pub fn get<T: TypedParameterValue>(&self, name: &str) -> Option<T> {
+        let entries = self.inner.entries.read();
+        T::from_value(&entries.get(name)?.value)
+    }
+
+    /// Sets the value of a parameter.
+    ///
+    /// The value is checked with the parameter's validator. If the value changed, the parameter's
+    /// change callback is invoked, and subscribed clients are notified.
+    ///
+    /// Unlike changes requested by clients, this method may change read-only parameters.
+    pub fn set<T: TypedParameterValue>(&self, name: &str, value: T) -> Result<(), FoxgloveError> {
+        let parameter = self.update(name, value.into_value(), false)?;
+        self.publish(parameter.into_iter().collect());
+        Ok(())
+    }
+
+    /// Returns the named parameters, or all parameters if `names` is empty.
+    ///
+    /// Parameters which have not been declared are omitted.
+    pub fn parameters(&self, names: &[String]) -> Vec<Parameter> {
+        let entries = self.inner.entries.read();
+        if names.is_empty() {
+            entries
+                .iter()
+                .map(|(name, entry)| entry.parameter(name))
+                .collect()
+        } else {
+            names
+                .iter()
+                .filter_map(|name| Some(entries.get(name)?.parameter(name)))
+                .collect()
+        }
+    }
+
+    /// Attaches the store to a server, which will be notified of changes.
+    pub(crate) fn attach(&self, server: Weak<Server>) {
+        self.inner.servers.lock().push(server);
+    }
+
+    /// Applies parameter changes requested by a client, and publishes the changes to all attached
+    /// servers.
+    ///
+    /// Returns the current values of the requested parameters, and error messages for requests
+    /// that were rejected.
+    pub(crate) fn set_from_client(
+        &self,
+        parameters: Vec<Parameter>,
+    ) -> (Vec<Parameter>, Vec<String>) {
+        let mut changed = Vec::new();
+        let mut errors = Vec::new();
+        for parameter in &parameters {
+            let Some(value) = parameter.value.clone() else {
+                errors.push(format!("Parameter {} cannot be unset", parameter.name));
+                continue;
+            };
+            match self.update(&parameter.name, value, true) {
+                Ok(Some(parameter)) => changed.push(parameter),
+                Ok(None) => (),
+                Err(err) => errors.push(err.to_string()),
+            }
+        }
+        self.publish(changed);
+        let names: Vec<_> = parameters.into_iter().map(|p| p.name).collect();
+        (self.parameters(&names), errors)
+    }
+
+    /// Validates and stores a new value, and invokes the change callback.
+    ///
+    /// Returns the updated parameter if the value changed.
+    fn update(
+        &self,
+        name: &str,
+        value: ParameterValue,
+        from_client: bool,
+    ) -> Result<Option<Parameter>, FoxgloveError> {
+        let invalid = |reason: &str| FoxgloveError::InvalidParameter(format!("{name}: {reason}"));
+        // Run the validator without holding the lock.
+        let validator = {
+            let entries = self.inner.entries.read();
+            let entry = entries
+                .get(name)
+                .ok_or_else(|| FoxgloveError::UnknownParameter(name.to_string()))?;
+            if from_client && entry.read_only {
+                return Err(invalid("parameter is read-only"));
+            }
+            entry.validator.clone()
+        };
+        validator(&value).map_err(|reason| invalid(&reason))?;
+
+        let (parameter, on_change) = {
+            let mut entries = self.inner.entries.write();
+            let entry = entries
+                .get_mut(name)
+                .ok_or_else(|| FoxgloveError::UnknownParameter(name.to_string()))?;
+            if entry.value == value {
+                return Ok(None);
+            }
+            entry.value = value;
+            (entry.parameter(name), entry.on_change.clone())
+        };
+        if let (Some(on_change), Some(value)) = (on_change, &parameter.value) {
+            on_change(value);
+        }
+        Ok(Some(parameter))
+    }
+
+    /// Notifies subscribed clients of attached servers about changed parameters.
+    fn publish(&self, parameters: Vec<Parameter>) {
+        if parameters.is_empty() {
+            return;
+        }
+        let servers: Vec<_> = {
+            let mut servers = self.inner.servers.lock();
+            servers.retain(|s| s.strong_count() > 0);
+            servers.iter().filter_map(Weak::upgrade).collect()
+        };
+        for server in servers {
+            server.publish_parameter_values(parameters.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_declare_get_set() {
+        let store = ParameterStore::new();
+        store.declare(TypedParameter::new("f", 1.5)).unwrap();
+        store.declare(TypedParameter::new("i", 3_i64)).unwrap();
+        store.declare(TypedParameter::new("b", true)).unwrap();
+        store
+            .declare(TypedParameter::new("s", "hello".to_string()))
+            .unwrap();
+        store
+            .declare(TypedParameter::new("a", vec![1.0, 2.0]))
+            .unwrap();
+        assert!(matches!(
+            store.declare(TypedParameter::new("f", 0.0)),
+            Err(FoxgloveError::DuplicateParameter(_))
+        ));
+
+        assert_eq!(store.get::<f64>("f"), Some(1.5));
+        assert_eq!(store.get::<i64>("i"), Some(3));
+        assert_eq!(store.get::<bool>("b"), Some(true));
+        assert_eq!(store.get::<String>("s"), Some("hello".to_string()));
+        assert_eq!(store.get::<Vec<f64>>("a"), Some(vec![1.0, 2.0]));
+        assert_eq!(store.get::<bool>("f"), None);
+        assert_eq!(store.get::<f64>("missing"), None);
+
+        store.set("f", 2.5).unwrap();
+        assert_eq!(store.get::<f64>("f"), Some(2.5));
+        assert!(matches!(
+            store.set("f", true),
+            Err(FoxgloveError::InvalidParameter(_))
+        ));
+        assert!(matches!(
+            store.set("missing", 1.0),
+            Err(FoxgloveError::UnknownParameter(_))
+        ));
+
+        let names: Vec<_> = store.parameters(&[]).into_iter().map(|p| p.name).collect();
+        assert_eq!(names, ["a", "b", "f", "i", "s"]);
+        let params = store.parameters(&["f".to_string(), "missing".to_string()]);
+        assert_eq!(
+            params,
+            [Parameter {
+                name: "f".to_string(),
+                r#type: Some(ParameterType::Float64),
+                value: Some(ParameterValue::Number(2.5)),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_validator_and_callbacks() {
+        let changes = Arc::new(AtomicUsize::new(0));
+        let store = ParameterStore::new();
+        store
+            .declare(
+                TypedParameter::new("gain", 1.0)
+                    .validator(|v: &f64| if *v >= 0.0 { Ok(()) } else { Err("negative") })
+                    .on_change({
+                        let changes = changes.clone();
+                        move |_| {
+                            changes.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }),
+            )
+            .unwrap();
+
+        assert!(store.set("gain", -1.0).is_err());
+        assert_eq!(store.get::<f64>("gain"), Some(1.0));
+        store.set("gain", 2.0).unwrap();
+        // Setting the same value is not a change.
+        store.set("gain", 2.0).unwrap();
+        assert_eq!(changes.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_set_from_client() {
+        let store = ParameterStore::new();
+        store.declare(TypedParameter::new("gain", 1.0)).unwrap();
+        store
+            .declare(TypedParameter::new("name", "robot".to_string()).read_only())
+            .unwrap();
+
+        let request = |name: &str, value: ParameterValue| Parameter {
+            name: name.to_string(),
+            r#type: None,
+            value: Some(value),
+        };
+        let (current, errors) = store.set_from_client(vec![
+            request("gain", ParameterValue::Number(2.0)),
+            request("name", ParameterValue::String(b"other".to_vec())),
+            request("missing", ParameterValue::Bool(true)),
+        ]);
+        assert_eq!(
+            current.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(),
+            ["gain", "name"]
+        );
+        assert_eq!(current[0].value, Some(ParameterValue::Number(2.0)));
+        assert_eq!(errors.len(), 2);
+        assert_eq!(store.get::<String>("name"), Some("robot".to_string()));
+
+        // Read-only parameters may be changed by the server.
+        store.set("name", "other".to_string()).unwrap();
+        assert_eq!(store.get::<String>("name"), Some("other".to_string()));
+    }
+}
@@ -1,5 +1,6 @@
 use crate::channel::Channel;
 use crate::channel::ChannelId;
+use crate::websocket::protocol::client::SubscriptionId;
 use crate::websocket::service::CallId;
 use crate::websocket::service::ServiceId;
 use crate::websocket::service::{self, Service};
@@ -18,7 +19,7 @@ pub enum BinaryOpcode {
     #[cfg(feature = "unstable")]
     TimeData = 2,
     ServiceCallResponse = 3,
-    // FetchAssetResponse = 4,
+    FetchAssetResponse = 4,
 }
 
 #[derive(Debug, Serialize, PartialEq)]
@@ -137,6 +138,50 @@ pub struct RemoveStatus {
     pub status_ids: Vec<String>,
 }
 
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PublishedTopic<'a> {
+    pub name: &'a str,
+    pub publisher_ids: Vec<&'a str>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscribedTopic<'a> {
+    pub name: &'a str,
+    pub subscriber_ids: Vec<&'a str>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AdvertisedService<'a> {
+    pub name: &'a str,
+    pub provider_ids: Vec<&'a str>,
+}
+
+// https://github.com/foxglove/ws-protocol/blob/main/docs/spec.md#connection-graph-update
+#[derive(Debug, Default, Serialize, PartialEq)]
+#[serde(tag = "op")]
+#[serde(rename = "connectionGraphUpdate")]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionGraphUpdate<'a> {
+    pub published_topics: Vec<PublishedTopic<'a>>,
+    pub subscribed_topics: Vec<SubscribedTopic<'a>>,
+    pub advertised_services: Vec<AdvertisedService<'a>>,
+    pub removed_topics: Vec<&'a str>,
+    pub removed_services: Vec<&'a str>,
+}
+
+impl ConnectionGraphUpdate<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.published_topics.is_empty()
+            && self.subscribed_topics.is_empty()
+            && self.advertised_services.is_empty()
+            && self.removed_topics.is_empty()
+            && self.removed_services.is_empty()
+    }
+}
+
 /// A capability that the websocket server advertises to its clients.
 #[derive(Debug, Serialize, Eq, PartialEq, Hash)]
 #[serde(rename_all = "camelCase")]
@@ -157,6 +202,10 @@ pub enum Capability {
     Time,
     /// Allow clients to call services.
     Services,
+    /// Allow clients to subscribe to updates of the connection graph.
+    ConnectionGraph,
+    /// Allow clients to fetch assets.
+    Assets,
 }
 
 // https://github.com/foxglove/ws-protocol/blob/main/docs/spec.md#server-info
@@ -177,30 +226,45 @@ pub fn server_info(
     .to_string()
 }
 
+/// Message encodings which don't require a schema to be decoded.
+const SCHEMALESS_ENCODINGS: &[&str] = &["json"];
+
 // https://github.com/foxglove/ws-protocol/blob/main/docs/spec.md#advertise
-// Caller must check that the channel has a schema, otherwise this will panic.
+// Channels with a self-describing message encoding may be advertised without a schema, in which
+// case the schema name and schema are empty. Other channels require a schema.
 pub fn advertisement(channel: &Channel) -> Result<String, FoxgloveError> {
-    let schema = &channel
-        .schema
-        .as_ref()
-        .ok_or_else(|| FoxgloveError::SchemaRequired)?;
-
-    let schema_data = match schema.encoding.as_str() {
-        "protobuf" => BASE64_STANDARD.encode(&schema.data),
-        _ => String::from_utf8(schema.data.to_vec())
-            .map_err(|e| FoxgloveError::Unspecified(e.into()))?,
+    let advertisement = match &channel.schema {
+        Some(schema) => {
+            let schema_data = match schema.encoding.as_str() {
+                "protobuf" => BASE64_STANDARD.encode(&schema.data),
+                _ => String::from_utf8(schema.data.to_vec())
+                    .map_err(|e| FoxgloveError::Unspecified(e.into()))?,
+            };
+            Advertisement {
+                id: channel.id,
+                topic: &channel.topic,
+                encoding: &channel.message_encoding,
+                schema_name: &schema.name,
+                schema: schema_data,
+                schema_encoding: Some(&schema.encoding),
+            }
+        }
+        None if SCHEMALESS_ENCODINGS.contains(&channel.message_encoding.as_str()) => {
+            Advertisement {
+                id: channel.id,
+                topic: &channel.topic,
+                encoding: &channel.message_encoding,
+                schema_name: "",
+                schema: String::new(),
+                schema_encoding: None,
+            }
+        }
+        None => return Err(FoxgloveError::SchemaRequired),
     };
 
     Ok(json!({
         "op": "advertise",
-        "channels": [Advertisement{
-            id: channel.id,
-            topic: &channel.topic,
-            encoding: &channel.message_encoding,
-            schema_name: &schema.name,
-            schema: schema_data,
-            schema_encoding: Some(&schema.encoding),
-        }],
+        "channels": [advertisement],
     })
     .to_string())
 }
@@ -327,6 +391,45 @@ impl ServiceCallResponse {
     }
 }
 
+// https://github.com/foxglove/ws-protocol/blob/main/docs/spec.md#message-data
+pub(crate) fn message_data(
+    subscription_id: SubscriptionId,
+    log_time: u64,
+    payload: &[u8],
+) -> Bytes {
+    let mut buf = BytesMut::with_capacity(13 + payload.len());
+    buf.put_u8(BinaryOpcode::MessageData as u8);
+    buf.put_u32_le(subscription_id.into());
+    buf.put_u64_le(log_time);
+    buf.put_slice(payload);
+    buf.into()
+}
+
+// https://github.com/foxglove/ws-protocol/blob/main/docs/spec.md#time
+#[cfg(feature = "unstable")]
+pub(crate) fn time(timestamp_nanos: u64) -> Bytes {
+    let mut buf = BytesMut::with_capacity(9);
+    buf.put_u8(BinaryOpcode::TimeData as u8);
+    buf.put_u64_le(timestamp_nanos);
+    buf.into()
+}
+
+// https://github.com/foxglove/ws-protocol/blob/main/docs/spec.md#fetch-asset-response
+pub(crate) fn fetch_asset_response(request_id: u32, result: Result<Bytes, String>) -> Bytes {
+    let (status, error, data) = match result {
+        Ok(data) => (0, String::new(), data),
+        Err(error) => (1, error, Bytes::new()),
+    };
+    let mut buf = BytesMut::with_capacity(10 + error.len() + data.len());
+    buf.put_u8(BinaryOpcode::FetchAssetResponse as u8);
+    buf.put_u32_le(request_id);
+    buf.put_u8(status);
+    buf.put_u32_le(error.len() as u32);
+    buf.put(error.as_bytes());
+    buf.put(data);
+    buf.into()
+}
+
 // https://github.com/foxglove/ws-protocol/blob/main/docs/spec.md#service-call-failure
 pub(crate) fn service_call_failure(
     service_id: ServiceId,
@@ -344,12 +447,50 @@ pub(crate) fn service_call_failure(
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use service::ServiceSchema;
 
-    use crate::Schema;
+    use crate::{ChannelBuilder, LogContext, Schema};
 
     use super::*;
 
+    #[test]
+    fn test_schemaless_advertisement() {
+        let ctx = Arc::new(LogContext::new());
+        let channel = ChannelBuilder::new("/json")
+            .message_encoding("json")
+            .context(&ctx)
+            .build()
+            .unwrap();
+        let message = advertisement(&channel).unwrap();
+        let expected = json!({
+            "op": "advertise",
+            "channels": [{
+                "id": channel.id(),
+                "topic": "/json",
+                "encoding": "json",
+                "schemaName": "",
+                "schema": "",
+            }],
+        });
+        assert_eq!(message, expected.to_string());
+    }
+
+    #[test]
+    fn test_schemaless_advertisement_requires_self_describing_encoding() {
+        let ctx = Arc::new(LogContext::new());
+        let channel = ChannelBuilder::new("/protobuf")
+            .message_encoding("protobuf")
+            .context(&ctx)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            advertisement(&channel),
+            Err(FoxgloveError::SchemaRequired)
+        ));
+    }
+
     #[test]
     fn test_server_info() {
         let default = server_info("id:123", "name:test", &HashSet::new(), &HashSet::new());
@@ -380,6 +521,38 @@ mod tests {
         assert_eq!(with_publish, expected.to_string());
     }
 
+    #[test]
+    fn test_message_data() {
+        let data = message_data(SubscriptionId::new(3), 123, b"payload");
+        let mut expected = BytesMut::new();
+        expected.put_u8(1);
+        expected.put_u32_le(3);
+        expected.put_u64_le(123);
+        expected.put(b"payload".as_slice());
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_fetch_asset_response() {
+        let ok = fetch_asset_response(7, Ok(Bytes::from_static(b"data")));
+        let mut expected = BytesMut::new();
+        expected.put_u8(4);
+        expected.put_u32_le(7);
+        expected.put_u8(0);
+        expected.put_u32_le(0);
+        expected.put(b"data".as_slice());
+        assert_eq!(ok, expected);
+
+        let err = fetch_asset_response(8, Err("not found".into()));
+        let mut expected = BytesMut::new();
+        expected.put_u8(4);
+        expected.put_u32_le(8);
+        expected.put_u8(1);
+        expected.put_u32_le(9);
+        expected.put(b"not found".as_slice());
+        assert_eq!(err, expected);
+    }
+
     #[test]
     fn test_status() {
         fn json(level: StatusLevel) -> serde_json::Value {
@@ -1,21 +1,27 @@
 //! Websocket services.
 
 use std::fmt::Display;
+use std::future::Future;
+use std::marker::PhantomData;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 
-use crate::websocket::Client;
+use crate::websocket::{Client, ClientSnapshot};
+use crate::{Decode, Encode};
 
 mod handler;
+mod limits;
 mod request;
 mod response;
 mod schema;
 mod semaphore;
-pub use handler::{Handler, SyncHandler};
-use handler::{HandlerFn, SyncHandlerFn};
+pub use handler::{AsyncHandler, BoxError, Handler, SyncHandler};
+use handler::{AsyncHandlerAdapter, AsyncHandlerFn, HandlerFn, SyncHandlerFn, TypedHandlerFn};
+pub(crate) use limits::{Admission, CallLimits, CallPermit, LimitSemaphore};
 pub use request::Request;
 pub use response::Responder;
 pub(crate) use schema::MessageSchema;
@@ -75,6 +81,8 @@ pub struct ServiceBuilder {
     id: ServiceId,
     name: String,
     schema: ServiceSchema,
+    timeout: Option<Duration>,
+    limits: CallLimits,
 }
 impl ServiceBuilder {
     /// Creates a new builder for a websocket service.
@@ -85,6 +93,8 @@ impl ServiceBuilder {
             id: ServiceId::new(id),
             name: name.into(),
             schema,
+            timeout: None,
+            limits: CallLimits::default(),
         }
     }
 
@@ -95,12 +105,68 @@ impl ServiceBuilder {
         self
     }
 
+    /// Sets a timeout for service calls.
+    ///
+    /// If the handler doesn't respond within the timeout, the client receives a service call
+    /// failure, and the call no longer counts towards the service's concurrency limits. A later
+    /// response from the handler is discarded. The timeout applies to every kind of handler, but
+    /// is mostly useful for handlers which respond asynchronously.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum number of concurrent calls to the service, across all clients.
+    ///
+    /// A call is in progress until its response is sent. By default, the number of concurrent
+    /// calls to a service is unlimited. A limit of 0 is treated as 1, which serializes calls to
+    /// the service.
+    ///
+    /// Calls which exceed the limit fail with "Too many requests", unless they can be queued.
+    /// Refer to [`ServiceBuilder::max_queued_calls`].
+    pub fn max_concurrent_calls(mut self, max: usize) -> Self {
+        self.limits.set_global(max);
+        self
+    }
+
+    /// Sets the maximum number of concurrent calls to the service from each client.
+    ///
+    /// By default, the number of concurrent calls to a service from a client is only limited by
+    /// the limit on the total number of calls in progress for the client. A limit of 0 is treated
+    /// as 1.
+    ///
+    /// Calls which exceed the limit fail with "Too many requests", unless they can be queued.
+    /// Refer to [`ServiceBuilder::max_queued_calls`].
+    pub fn max_concurrent_calls_per_client(mut self, max: usize) -> Self {
+        self.limits.set_per_client(max);
+        self
+    }
+
+    /// Sets the maximum number of calls which can wait for the service's concurrency limits.
+    ///
+    /// Calls which exceed the limits set by [`ServiceBuilder::max_concurrent_calls`] or
+    /// [`ServiceBuilder::max_concurrent_calls_per_client`] wait in a queue of up to `max` calls,
+    /// and are handled when other calls complete. Calls which don't fit in the queue fail with
+    /// "Too many requests". The default is 0, which rejects calls immediately.
+    ///
+    /// Calls waiting for the same limit are handled in the order they arrived. A call waiting for
+    /// its client's limit does not hold up calls from other clients.
+    ///
+    /// Queued calls are abandoned if the client disconnects. Time spent in the queue does not
+    /// count towards the [`ServiceBuilder::timeout`].
+    pub fn max_queued_calls(mut self, max: usize) -> Self {
+        self.limits.set_max_queued(max);
+        self
+    }
+
     /// Configures a handler and returns the constructed [`Service`].
     pub fn handler<H: Handler + 'static>(self, handler: H) -> Service {
         Service {
             id: self.id,
             name: self.name,
             schema: self.schema,
+            timeout: self.timeout,
+            limits: self.limits,
             handler: Arc::new(handler),
         }
     }
@@ -125,6 +191,25 @@ impl ServiceBuilder {
     {
         self.handler(SyncHandlerFn(call))
     }
+
+    /// Configures an asynchronous handler and returns the constructed [`Service`].
+    ///
+    /// Refer to [`AsyncHandler::call`] for a description of the `call` function.
+    pub fn async_handler<H: AsyncHandler + 'static>(self, handler: H) -> Service {
+        self.handler(AsyncHandlerAdapter(handler))
+    }
+
+    /// Configures an asynchronous handler function and returns the constructed [`Service`].
+    ///
+    /// Refer to [`AsyncHandler::call`] for a description of the `call` function.
+    pub fn async_handler_fn<F, Fut, E>(self, call: F) -> Service
+    where
+        F: Fn(ClientSnapshot, Request) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Bytes, E>> + Send + 'static,
+        E: Display + 'static,
+    {
+        self.async_handler(AsyncHandlerFn(call))
+    }
 }
 
 /// A websocket service.
@@ -133,6 +218,8 @@ pub struct Service {
     id: ServiceId,
     name: String,
     schema: ServiceSchema,
+    timeout: Option<Duration>,
+    limits: CallLimits,
     handler: Arc<dyn Handler>,
 }
 
@@ -152,6 +239,36 @@ impl Service {
         ServiceBuilder::new(name, schema)
     }
 
+    /// Creates a service with a handler for typed requests and responses.
+    ///
+    /// The service schema is named after the service, and the request and response schemas and
+    /// encodings are derived from the [`Encode`] implementations of `Req` and `Resp`. This works
+    /// with the protobuf types in [`foxglove::schemas`](crate::schemas), as well as types that
+    /// implement [`Serialize`], [`Deserialize`] and
+    /// [`JsonSchema`](https://docs.rs/schemars/latest/schemars/trait.JsonSchema.html).
+    ///
+    /// The request payload is decoded before calling the handler, and the handler's result is
+    /// encoded as the response. If the request can't be decoded, or the handler returns an error,
+    /// the client receives a service call failure with the error message.
+    ///
+    /// The handler is invoked from the client's main poll loop and must not block. If blocking or
+    /// long-running behavior is required, use [`Service::builder`] with a [`Handler`] instead.
+    pub fn typed<Req, Resp>(
+        name: impl Into<String>,
+        call: impl Fn(Client, Req) -> Result<Resp, BoxError> + Send + Sync + 'static,
+    ) -> Service
+    where
+        Req: Decode + Encode + 'static,
+        Resp: Encode + 'static,
+    {
+        let name = name.into();
+        let schema = ServiceSchema::typed::<Req, Resp>(name.clone());
+        ServiceBuilder::new(name, schema).handler(TypedHandlerFn {
+            call,
+            phantom: PhantomData,
+        })
+    }
+
     /// Returns the service's ID.
     pub fn id(&self) -> ServiceId {
         self.id
@@ -177,6 +294,16 @@ impl Service {
         self.schema().response().map(|rs| rs.encoding.as_str())
     }
 
+    /// The timeout for calls to the service.
+    pub(crate) fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// The concurrency limits for calls to the service.
+    pub(crate) fn limits(&self) -> &CallLimits {
+        &self.limits
+    }
+
     /// Invokes the service call implementation.
     pub(crate) fn call(&self, client: Client<'_>, request: Request, responder: Responder) {
         self.handler.call(client, request, responder);
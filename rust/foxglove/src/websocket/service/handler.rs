@@ -1,9 +1,12 @@
 use std::fmt::Display;
+use std::future::Future;
+use std::marker::PhantomData;
 
 use bytes::Bytes;
 
 use crate::websocket::service::{Request, Responder};
-use crate::websocket::Client;
+use crate::websocket::{Client, ClientSnapshot};
+use crate::{Decode, Encode};
 
 /// A websocket service call handler.
 pub trait Handler: Send + Sync {
@@ -15,8 +18,9 @@ pub trait Handler: Send + Sync {
     /// long-running behavior is required, the implementation should use [`tokio::task::spawn`] (or
     /// [`tokio::task::spawn_blocking`]) to handle the request asynchronously.
     ///
-    /// The implementation is responsible for completing the request with [`Responder::respond`],
-    /// otherwise no response will be sent to the client.
+    /// The implementation is responsible for completing the request with [`Responder::respond`].
+    /// If the responder is dropped without responding, or if this method panics, the client
+    /// receives a service call failure.
     fn call(&self, client: Client, request: Request, responder: Responder);
 }
 
@@ -34,6 +38,12 @@ where
     }
 }
 
+/// The error type returned by typed service call handlers.
+///
+/// Any error type can be converted into this with the `?` operator, as can strings with
+/// [`Into::into`].
+pub type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
 /// A synchronous service call handler.
 ///
 /// This is a convenience wrapper around [`Handler`] that takes care of moving the result into the
@@ -74,3 +84,88 @@ where
         self.0(client, request)
     }
 }
+
+/// An asynchronous service call handler.
+///
+/// This is a convenience wrapper around [`Handler`] for handlers that need to await other work.
+/// The returned future is spawned on the server's runtime, and its result is sent to the client
+/// when it completes.
+pub trait AsyncHandler: Send + Sync {
+    /// The error type returned for service calls.
+    type Error: Display;
+
+    /// Handles a service call request from a client, returning a future for the result.
+    ///
+    /// The client is passed as an owned [`ClientSnapshot`], so it can be moved into the future.
+    fn call(
+        &self,
+        client: ClientSnapshot,
+        request: Request,
+    ) -> impl Future<Output = Result<Bytes, Self::Error>> + Send + 'static;
+}
+
+/// A [`Handler`] which spawns the futures returned by an [`AsyncHandler`].
+pub(crate) struct AsyncHandlerAdapter<H>(pub H);
+
+impl<H: AsyncHandler> Handler for AsyncHandlerAdapter<H> {
+    fn call(&self, client: Client, request: Request, responder: Responder) {
+        let future = self.0.call(client.snapshot(), request);
+        tokio::spawn(async move {
+            let result = future.await.map_err(|e| e.to_string());
+            responder.respond(result);
+        });
+    }
+}
+
+/// A wrapper around a function that serves as an asynchronous service call handler.
+pub(crate) struct AsyncHandlerFn<F, Fut, E>(pub F)
+where
+    F: Fn(ClientSnapshot, Request) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<Bytes, E>> + Send + 'static,
+    E: Display + 'static;
+
+impl<F, Fut, E> AsyncHandler for AsyncHandlerFn<F, Fut, E>
+where
+    F: Fn(ClientSnapshot, Request) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<Bytes, E>> + Send + 'static,
+    E: Display + 'static,
+{
+    type Error = E;
+
+    fn call(
+        &self,
+        client: ClientSnapshot,
+        request: Request,
+    ) -> impl Future<Output = Result<Bytes, Self::Error>> + Send + 'static {
+        self.0(client, request)
+    }
+}
+
+/// A wrapper around a function that serves as a typed service call handler.
+///
+/// The request payload is decoded into `Req`, and the returned `Resp` is encoded into the
+/// response payload.
+pub(crate) struct TypedHandlerFn<F, Req, Resp> {
+    pub call: F,
+    pub phantom: PhantomData<fn(Req) -> Resp>,
+}
+
+impl<F, Req, Resp> SyncHandler for TypedHandlerFn<F, Req, Resp>
+where
+    F: Fn(Client, Req) -> Result<Resp, BoxError> + Send + Sync,
+    Req: Decode,
+    Resp: Encode,
+{
+    type Error = String;
+
+    fn call(&self, client: Client, request: Request) -> Result<Bytes, Self::Error> {
+        let request =
+            Req::decode(request.payload()).map_err(|e| format!("Failed to decode request: {e}"))?;
+        let response = (self.call)(client, request).map_err(|e| e.to_string())?;
+        let mut buf = Vec::with_capacity(response.encoded_len().unwrap_or(0));
+        response
+            .encode(&mut buf)
+            .map_err(|e| format!("Failed to encode response: {e}"))?;
+        Ok(Bytes::from(buf))
+    }
+}
@@ -0,0 +1,406 @@
+//! Per-service concurrency limits.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use super::semaphore::{Semaphore, SemaphoreGuard};
+
+/// Concurrency limits for calls to a service.
+#[derive(Debug, Default)]
+pub(crate) struct CallLimits {
+    /// Limits concurrent calls from all clients.
+    global: Option<LimitSemaphore>,
+    /// The maximum number of concurrent calls from each client.
+    per_client: Option<usize>,
+    /// The maximum number of calls waiting for a permit.
+    max_queued: usize,
+    /// The number of calls waiting for a permit.
+    queued: Arc<AtomicUsize>,
+}
+
+/// A semaphore which tracks the number of calls waiting for it.
+///
+/// [`LimitSemaphore::try_acquire`] fails while any calls are waiting, so that new calls queue
+/// behind them.
+#[derive(Debug, Clone)]
+pub(crate) struct LimitSemaphore {
+    sem: Semaphore,
+    waiting: Arc<AtomicUsize>,
+}
+
+impl LimitSemaphore {
+    /// Constructs a new semaphore.
+    pub fn new(count: usize) -> Self {
+        Self {
+            sem: Semaphore::new(count),
+            waiting: Arc::default(),
+        }
+    }
+
+    /// Attempts to acquire the semaphore, if no other calls are waiting for it.
+    fn try_acquire(&self) -> Option<SemaphoreGuard> {
+        if self.waiting.load(Ordering::Acquire) > 0 {
+            return None;
+        }
+        self.sem.try_acquire()
+    }
+
+    /// Takes a place in line for the semaphore.
+    fn waiter(&self) -> Waiter {
+        self.waiting.fetch_add(1, Ordering::AcqRel);
+        Waiter(self.clone())
+    }
+}
+
+/// A place in line for a [`LimitSemaphore`], which is released when dropped.
+struct Waiter(LimitSemaphore);
+
+impl Waiter {
+    /// Waits until the semaphore can be acquired.
+    async fn acquire(self) -> SemaphoreGuard {
+        self.0.sem.acquire().await
+    }
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        self.0.waiting.fetch_sub(1, Ordering::Release);
+    }
+}
+
+/// The state of a call's permit for one of the semaphores that limit it.
+enum Permit {
+    /// The semaphore does not apply to the call.
+    Unlimited,
+    /// The permit has been acquired.
+    Acquired(SemaphoreGuard),
+    /// The call has not yet taken a place in line for the semaphore.
+    Pending(LimitSemaphore),
+    /// The call is in line for the semaphore.
+    Waiting(Waiter),
+}
+
+impl Permit {
+    /// Attempts to acquire the semaphore without waiting.
+    fn try_acquire(sem: Option<&LimitSemaphore>) -> Self {
+        match sem {
+            Some(sem) => match sem.try_acquire() {
+                Some(guard) => Self::Acquired(guard),
+                None => Self::Pending(sem.clone()),
+            },
+            None => Self::Unlimited,
+        }
+    }
+
+    fn is_pending(&self) -> bool {
+        matches!(self, Self::Pending(_))
+    }
+
+    /// Takes a place in line, if the permit is pending.
+    fn wait(self) -> Self {
+        match self {
+            Self::Pending(sem) => Self::Waiting(sem.waiter()),
+            permit => permit,
+        }
+    }
+
+    /// Returns the acquired guard, if any.
+    fn into_guard(self) -> Option<SemaphoreGuard> {
+        match self {
+            Self::Acquired(guard) => Some(guard),
+            _ => None,
+        }
+    }
+
+    /// Waits until the permit is acquired.
+    async fn acquire(self) -> Option<SemaphoreGuard> {
+        match self {
+            Self::Unlimited => None,
+            Self::Acquired(guard) => Some(guard),
+            Self::Pending(sem) => Some(sem.waiter().acquire().await),
+            Self::Waiting(waiter) => Some(waiter.acquire().await),
+        }
+    }
+}
+
+/// The outcome of admitting a service call.
+pub(crate) enum Admission<F> {
+    /// The call may proceed immediately.
+    Ready(CallPermit),
+    /// The call is queued, and may proceed when the future completes.
+    Queued(F),
+    /// The call was rejected.
+    Rejected,
+}
+
+/// Permits held for the duration of a service call.
+#[derive(Debug)]
+pub(crate) struct CallPermit {
+    _client: SemaphoreGuard,
+    _per_client: Option<SemaphoreGuard>,
+    _global: Option<SemaphoreGuard>,
+}
+
+impl CallLimits {
+    /// Sets the maximum number of concurrent calls from all clients.
+    pub fn set_global(&mut self, max: usize) {
+        self.global = Some(LimitSemaphore::new(max.max(1)));
+    }
+
+    /// Sets the maximum number of concurrent calls from each client.
+    pub fn set_per_client(&mut self, max: usize) {
+        self.per_client = Some(max.max(1));
+    }
+
+    /// Sets the maximum number of calls waiting for a permit.
+    pub fn set_max_queued(&mut self, max: usize) {
+        self.max_queued = max;
+    }
+
+    /// Returns the maximum number of concurrent calls from each client, if limited.
+    pub fn per_client(&self) -> Option<usize> {
+        self.per_client
+    }
+
+    /// Admits a call, given the client's permit for the call, and the client's semaphore for
+    /// this service, if calls are limited per client.
+    ///
+    /// If the limits are reached, or other calls are already waiting for the same limit, the call
+    /// is queued if there's room in the queue, and rejected otherwise.
+    ///
+    /// A queued call acquires the per-client permit first, and only then takes a place in line for
+    /// the global permit. A call waiting for its own client's limit does not hold up calls from
+    /// other clients.
+    pub fn admit(
+        &self,
+        client: SemaphoreGuard,
+        per_client: Option<LimitSemaphore>,
+    ) -> Admission<impl Future<Output = CallPermit> + Send + 'static> {
+        let per_client = Permit::try_acquire(per_client.as_ref());
+        let global = if per_client.is_pending() {
+            self.global
+                .clone()
+                .map_or(Permit::Unlimited, Permit::Pending)
+        } else {
+            Permit::try_acquire(self.global.as_ref())
+        };
+        if !per_client.is_pending() && !global.is_pending() {
+            return Admission::Ready(CallPermit {
+                _client: client,
+                _per_client: per_client.into_guard(),
+                _global: global.into_guard(),
+            });
+        }
+        let Some(slot) = QueueSlot::reserve(&self.queued, self.max_queued) else {
+            return Admission::Rejected;
+        };
+        // Take a place in line for the first semaphore the call waits for, so that later calls
+        // queue behind it.
+        let global = if per_client.is_pending() {
+            global
+        } else {
+            global.wait()
+        };
+        let per_client = per_client.wait();
+        Admission::Queued(async move {
+            let per_client = per_client.acquire().await;
+            let global = global.acquire().await;
+            drop(slot);
+            CallPermit {
+                _client: client,
+                _per_client: per_client,
+                _global: global,
+            }
+        })
+    }
+}
+
+/// A reserved slot in a service's call queue, which is released when dropped.
+struct QueueSlot(Arc<AtomicUsize>);
+
+impl QueueSlot {
+    fn reserve(queued: &Arc<AtomicUsize>, max: usize) -> Option<Self> {
+        queued
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (n < max).then_some(n + 1)
+            })
+            .ok()?;
+        Some(Self(queued.clone()))
+    }
+}
+
+impl Drop for QueueSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn admit(limits: &CallLimits, per_client: Option<&LimitSemaphore>) -> Admission<()> {
+        let client = Semaphore::new(1).try_acquire().unwrap();
+        match limits.admit(client, per_client.cloned()) {
+            Admission::Ready(permit) => Admission::Ready(permit),
+            Admission::Queued(_) => Admission::Queued(()),
+            Admission::Rejected => Admission::Rejected,
+        }
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let limits = CallLimits::default();
+        let permits: Vec<_> = (0..100).map(|_| admit(&limits, None)).collect();
+        assert!(permits.iter().all(|a| matches!(a, Admission::Ready(_))));
+    }
+
+    #[test]
+    fn test_global_limit_without_queue() {
+        let mut limits = CallLimits::default();
+        limits.set_global(1);
+        let first = admit(&limits, None);
+        assert!(matches!(first, Admission::Ready(_)));
+        assert!(matches!(admit(&limits, None), Admission::Rejected));
+        drop(first);
+        assert!(matches!(admit(&limits, None), Admission::Ready(_)));
+    }
+
+    #[test]
+    fn test_per_client_limit() {
+        let mut limits = CallLimits::default();
+        limits.set_per_client(1);
+        let client_a = LimitSemaphore::new(limits.per_client().unwrap());
+        let client_b = LimitSemaphore::new(limits.per_client().unwrap());
+        let first = admit(&limits, Some(&client_a));
+        assert!(matches!(first, Admission::Ready(_)));
+        assert!(matches!(
+            admit(&limits, Some(&client_a)),
+            Admission::Rejected
+        ));
+        assert!(matches!(
+            admit(&limits, Some(&client_b)),
+            Admission::Ready(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_queue() {
+        let mut limits = CallLimits::default();
+        limits.set_global(1);
+        limits.set_max_queued(1);
+        let client = || Semaphore::new(1).try_acquire().unwrap();
+
+        let Admission::Ready(first) = limits.admit(client(), None) else {
+            panic!("expected first call to be ready");
+        };
+        let Admission::Queued(second) = limits.admit(client(), None) else {
+            panic!("expected second call to be queued");
+        };
+        assert!(matches!(limits.admit(client(), None), Admission::Rejected));
+
+        // The queued call proceeds when the first call completes, freeing its queue slot.
+        let second = tokio::spawn(second);
+        drop(first);
+        let second = second.await.unwrap();
+        assert!(matches!(limits.admit(client(), None), Admission::Queued(_)));
+        drop(second);
+    }
+
+    #[tokio::test]
+    async fn test_per_client_queue() {
+        let mut limits = CallLimits::default();
+        limits.set_global(2);
+        limits.set_per_client(1);
+        limits.set_max_queued(2);
+        let client = || Semaphore::new(1).try_acquire().unwrap();
+        let client_a = LimitSemaphore::new(limits.per_client().unwrap());
+        let client_b = LimitSemaphore::new(limits.per_client().unwrap());
+
+        let Admission::Ready(first) = limits.admit(client(), Some(client_a.clone())) else {
+            panic!("expected first call to be ready");
+        };
+        let Admission::Queued(second) = limits.admit(client(), Some(client_a.clone())) else {
+            panic!("expected second call to be queued");
+        };
+
+        // A call from client B doesn't wait behind client A's limit.
+        let Admission::Ready(third) = limits.admit(client(), Some(client_b.clone())) else {
+            panic!("expected third call to be ready");
+        };
+
+        // Client A's queued call proceeds when its first call completes.
+        let second = tokio::spawn(second);
+        drop(first);
+        drop(second.await.unwrap());
+        drop(third);
+    }
+
+    #[tokio::test]
+    async fn test_global_queue_before_new_calls() {
+        let mut limits = CallLimits::default();
+        limits.set_global(1);
+        limits.set_per_client(1);
+        limits.set_max_queued(2);
+        let client = || Semaphore::new(1).try_acquire().unwrap();
+        let client_a = LimitSemaphore::new(limits.per_client().unwrap());
+        let client_b = LimitSemaphore::new(limits.per_client().unwrap());
+        let client_c = LimitSemaphore::new(limits.per_client().unwrap());
+
+        let Admission::Ready(first) = limits.admit(client(), Some(client_a.clone())) else {
+            panic!("expected first call to be ready");
+        };
+        let Admission::Queued(second) = limits.admit(client(), Some(client_b.clone())) else {
+            panic!("expected second call to be queued");
+        };
+
+        // Although the limits allow a call from client C, it waits behind the call which is
+        // waiting for the global limit.
+        drop(first);
+        let Admission::Queued(third) = limits.admit(client(), Some(client_c.clone())) else {
+            panic!("expected third call to be queued");
+        };
+        let second = tokio::spawn(second);
+        tokio::task::yield_now().await;
+        let third = tokio::spawn(third);
+        let second = second.await.unwrap();
+        tokio::task::yield_now().await;
+        assert!(!third.is_finished());
+        drop(second);
+        drop(third.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_global_queue_order() {
+        let mut limits = CallLimits::default();
+        limits.set_global(1);
+        limits.set_per_client(1);
+        limits.set_max_queued(2);
+        let client = || Semaphore::new(1).try_acquire().unwrap();
+        let client_a = LimitSemaphore::new(limits.per_client().unwrap());
+        let client_b = LimitSemaphore::new(limits.per_client().unwrap());
+
+        let Admission::Ready(first) = limits.admit(client(), Some(client_a.clone())) else {
+            panic!("expected first call to be ready");
+        };
+        // The second call waits for client A's limit, and the third for the global limit.
+        let Admission::Queued(second) = limits.admit(client(), Some(client_a.clone())) else {
+            panic!("expected second call to be queued");
+        };
+        let Admission::Queued(third) = limits.admit(client(), Some(client_b.clone())) else {
+            panic!("expected third call to be queued");
+        };
+        let second = tokio::spawn(second);
+        let third = tokio::spawn(third);
+        tokio::task::yield_now().await;
+
+        // The third call was waiting for the global limit first, so it proceeds first.
+        drop(first);
+        let third = third.await.unwrap();
+        tokio::task::yield_now().await;
+        assert!(!second.is_finished());
+        drop(third);
+        drop(second.await.unwrap());
+    }
+}
@@ -1,42 +1,74 @@
 //! Service call response handling.
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use bytes::Bytes;
+use parking_lot::Mutex;
+use tokio::task::AbortHandle;
 use tokio_tungstenite::tungstenite::Message;
 
-use super::semaphore::SemaphoreGuard;
+use super::limits::CallPermit;
 use super::{CallId, ServiceId};
 use crate::websocket::{protocol, ConnectedClient};
 
+/// The failure message sent when a responder is dropped without responding.
+const NO_RESPONSE: &str = "internal error: no response";
+
+/// The permit for a call that hasn't completed yet.
+///
+/// Whichever of the responder and the timeout takes the permit first completes the call.
+type PendingCall = Arc<Mutex<Option<CallPermit>>>;
+
 /// A handle for completing a service call.
 ///
 /// If you're holding one of these, you're responsible for eventually calling
-/// [`Responder::respond`]. If you drop the responder without responding, the client will never
-/// receive a response for its request.
+/// [`Responder::respond`]. If you drop the responder without responding, the client receives a
+/// service call failure.
 #[must_use]
 pub struct Responder {
     client: Arc<ConnectedClient>,
     service_id: ServiceId,
     call_id: CallId,
     encoding: String,
-    _guard: SemaphoreGuard,
+    pending: PendingCall,
+    timeout: Option<AbortHandle>,
 }
 impl Responder {
     /// Creates a new responder.
+    ///
+    /// If a timeout is provided, the call fails if the responder doesn't respond in time.
     pub(crate) fn new(
         client: Arc<ConnectedClient>,
         service_id: ServiceId,
         call_id: CallId,
         encoding: impl Into<String>,
-        _guard: SemaphoreGuard,
+        permit: CallPermit,
+        timeout: Option<Duration>,
     ) -> Self {
+        let pending = Arc::new(Mutex::new(Some(permit)));
+        let timeout = timeout.map(|timeout| {
+            let client = client.clone();
+            let pending = pending.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(timeout).await;
+                let Some(_permit) = pending.lock().take() else {
+                    return;
+                };
+                let message = format!("Service call timed out after {} ms", timeout.as_millis());
+                let _ = client.send_control_msg(Message::text(
+                    protocol::server::service_call_failure(service_id, call_id, &message),
+                ));
+            })
+            .abort_handle()
+        });
         Self {
             client,
             service_id,
             call_id,
             encoding: encoding.into(),
-            _guard,
+            pending,
+            timeout,
         }
     }
 
@@ -50,13 +82,28 @@ impl Responder {
     }
 
     /// Completes the request by sending a response to the client.
-    pub fn respond(self, result: Result<Bytes, String>) {
+    pub fn respond(mut self, result: Result<Bytes, String>) {
+        self.send(result);
+    }
+
+    fn send(&mut self, result: Result<Bytes, String>) {
+        if let Some(timeout) = self.timeout.take() {
+            timeout.abort();
+        }
+        let Some(_permit) = self.pending.lock().take() else {
+            tracing::debug!(
+                "Discarding response for service {} call {} after timeout",
+                self.service_id,
+                self.call_id
+            );
+            return;
+        };
         let message = match result {
             Ok(payload) => Message::binary(
                 protocol::server::ServiceCallResponse::new(
                     self.service_id,
                     self.call_id,
-                    self.encoding,
+                    std::mem::take(&mut self.encoding),
                     payload,
                 )
                 .encode(),
@@ -72,3 +119,16 @@ impl Responder {
         let _ = self.client.send_control_msg(message);
     }
 }
+
+impl Drop for Responder {
+    fn drop(&mut self) {
+        if self.pending.lock().is_some() {
+            tracing::warn!(
+                "Responder for service {} call {} dropped without responding",
+                self.service_id,
+                self.call_id
+            );
+            self.send(Err(NO_RESPONSE.to_string()));
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::Schema;
+use crate::{Encode, Schema};
 
 /// A service request or response schema.
 #[derive(Debug, Clone)]
@@ -45,6 +45,17 @@ impl ServiceSchema {
         self
     }
 
+    /// Creates a new named service schema, with request and response schemas derived from the
+    /// [`Encode`] implementations of `Req` and `Resp`.
+    ///
+    /// If a type doesn't provide a schema, an empty schema named after the type is used.
+    #[must_use]
+    pub fn typed<Req: Encode, Resp: Encode>(name: impl Into<String>) -> Self {
+        Self::new(name)
+            .with_request(Req::get_message_encoding(), schema_for::<Req>())
+            .with_response(Resp::get_message_encoding(), schema_for::<Resp>())
+    }
+
     /// Returns the name of the schema.
     pub fn name(&self) -> &str {
         &self.name
@@ -60,3 +71,8 @@ impl ServiceSchema {
         self.response.as_ref()
     }
 }
+
+/// Returns the schema for an encodable type, or an empty schema if it doesn't have one.
+fn schema_for<T: Encode>() -> Schema {
+    T::get_schema().unwrap_or_else(|| Schema::new(std::any::type_name::<T>(), "", Vec::<u8>::new()))
+}
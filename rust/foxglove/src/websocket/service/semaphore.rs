@@ -1,49 +1,39 @@
 //! A sempahore for admission control
 
-use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
+use tokio::sync::OwnedSemaphorePermit;
+
 #[cfg(test)]
 mod tests;
 
-/// A non-blocking counting semaphore for concurrency control.
+/// A counting semaphore for concurrency control.
 ///
-/// Decrements the inner counter when acquired.
+/// Waiters acquire the semaphore in FIFO order, and [`Semaphore::try_acquire`] does not jump
+/// ahead of waiters.
 #[derive(Debug, Clone)]
-pub(crate) struct Semaphore(Arc<AtomicUsize>);
+pub(crate) struct Semaphore(Arc<tokio::sync::Semaphore>);
+
+/// A counting semaphore guard, which releases the semaphore when dropped.
+pub(crate) type SemaphoreGuard = OwnedSemaphorePermit;
 
 impl Semaphore {
     /// Constructs a new semaphore.
     pub fn new(count: usize) -> Self {
-        Self(Arc::new(AtomicUsize::new(count)))
+        Self(Arc::new(tokio::sync::Semaphore::new(count)))
     }
 
-    /// Attempts to acquire the semaphore.
-    pub fn try_acquire(&self) -> Option<SemaphoreGuard> {
-        loop {
-            let current = self.0.load(Ordering::Acquire);
-            if current == 0 {
-                return None;
-            }
-            if self
-                .0
-                .compare_exchange(current, current - 1, Ordering::AcqRel, Ordering::Acquire)
-                .is_ok()
-            {
-                return Some(SemaphoreGuard(self.0.clone()));
-            }
-        }
+    /// Waits until the semaphore can be acquired.
+    pub async fn acquire(&self) -> SemaphoreGuard {
+        self.0
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("semaphore is never closed")
     }
-}
 
-/// A counting semaphore guard.
-///
-/// Increments the inner counter when dropped.
-#[derive(Debug)]
-pub(crate) struct SemaphoreGuard(Arc<AtomicUsize>);
-
-impl Drop for SemaphoreGuard {
-    fn drop(&mut self) {
-        self.0.fetch_add(1, Ordering::Release);
+    /// Attempts to acquire the semaphore.
+    pub fn try_acquire(&self) -> Option<SemaphoreGuard> {
+        self.0.clone().try_acquire_owned().ok()
     }
 }
@@ -9,21 +9,21 @@ use super::Semaphore;
 #[test]
 fn test_semaphore() {
     let sem = Semaphore::new(3);
-    assert_eq!(sem.0.load(Ordering::Acquire), 3);
+    assert_eq!(sem.0.available_permits(), 3);
 
     let g1 = sem.try_acquire().unwrap();
     let g2 = sem.try_acquire().unwrap();
     let g3 = sem.try_acquire().unwrap();
-    assert_eq!(sem.0.load(Ordering::Acquire), 0);
+    assert_eq!(sem.0.available_permits(), 0);
     assert!(sem.try_acquire().is_none());
 
     drop(g1);
-    assert_eq!(sem.0.load(Ordering::Acquire), 1);
+    assert_eq!(sem.0.available_permits(), 1);
     assert!(sem.try_acquire().is_some());
 
     drop(g2);
     drop(g3);
-    assert_eq!(sem.0.load(Ordering::Acquire), 3);
+    assert_eq!(sem.0.available_permits(), 3);
     assert!(sem.try_acquire().is_some());
 }
 
@@ -55,3 +55,50 @@ async fn test_concurrent() {
 
     tasks.join_all().await;
 }
+
+#[tokio::test]
+async fn test_acquire() {
+    let sem = Semaphore::new(1);
+    let guard = sem.acquire().await;
+    assert!(sem.try_acquire().is_none());
+
+    let waiter = tokio::spawn({
+        let sem = sem.clone();
+        async move { sem.acquire().await }
+    });
+    tokio::task::yield_now().await;
+    assert!(!waiter.is_finished());
+
+    drop(guard);
+    let guard = waiter.await.unwrap();
+    assert_eq!(sem.0.available_permits(), 0);
+    drop(guard);
+    assert_eq!(sem.0.available_permits(), 1);
+}
+
+#[tokio::test]
+async fn test_acquire_fifo() {
+    let sem = Semaphore::new(1);
+    let guard = sem.acquire().await;
+
+    // Waiters acquire the semaphore in the order they started waiting.
+    let order = Arc::new(parking_lot::Mutex::new(Vec::new()));
+    let mut tasks = JoinSet::new();
+    for id in 0..3 {
+        let sem = sem.clone();
+        let order = order.clone();
+        tasks.spawn(async move {
+            let _guard = sem.acquire().await;
+            order.lock().push(id);
+            tokio::task::yield_now().await;
+        });
+        tokio::task::yield_now().await;
+    }
+
+    // A waiter is first in line for a released permit.
+    drop(guard);
+    assert!(sem.try_acquire().is_none());
+
+    tasks.join_all().await;
+    assert_eq!(*order.lock(), [0, 1, 2]);
+}
@@ -0,0 +1,121 @@
+//! Handling of clients that can't keep up with outgoing messages.
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+/// The policy for clients whose outgoing message queues overflow.
+///
+/// A queue overflows when the server tries to send a message to a client, and the client's queue
+/// is full. This usually means that the client, or the network, is too slow to keep up with the
+/// rate of messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SlowClientPolicy {
+    /// Drop messages, and keep the client connected.
+    ///
+    /// The oldest data messages are dropped to make room for new ones, and control messages which
+    /// don't fit in the queue are dropped. This is the default.
+    #[default]
+    Drop,
+    /// Disconnect the client after the given number of consecutive overflows.
+    ///
+    /// Any message which is queued without dropping messages resets the count.
+    DisconnectAfterOverflows(u32),
+    /// Disconnect the client when its queue has been overflowing for the given duration.
+    ///
+    /// Any message which is queued without dropping messages resets the timer.
+    DisconnectAfterFullFor(Duration),
+}
+
+/// Tracks queue overflows for a client, according to a [`SlowClientPolicy`].
+#[derive(Debug)]
+pub(crate) struct OverflowTracker {
+    policy: SlowClientPolicy,
+    state: Mutex<OverflowState>,
+}
+
+#[derive(Debug, Default)]
+struct OverflowState {
+    consecutive: u32,
+    full_since: Option<Instant>,
+}
+
+impl OverflowTracker {
+    pub fn new(policy: SlowClientPolicy) -> Self {
+        Self {
+            policy,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Records that a message was queued without overflowing.
+    pub fn on_sent(&self) {
+        if self.policy != SlowClientPolicy::Drop {
+            *self.state.lock() = OverflowState::default();
+        }
+    }
+
+    /// Records an overflow, and returns a reason if the client should be disconnected.
+    pub fn on_overflow(&self) -> Option<String> {
+        let mut state = self.state.lock();
+        match self.policy {
+            SlowClientPolicy::Drop => None,
+            SlowClientPolicy::DisconnectAfterOverflows(max) => {
+                state.consecutive = state.consecutive.saturating_add(1);
+                (state.consecutive >= max).then(|| {
+                    format!(
+                        "Slow client: {} consecutive queue overflows",
+                        state.consecutive
+                    )
+                })
+            }
+            SlowClientPolicy::DisconnectAfterFullFor(duration) => {
+                let full_since = *state.full_since.get_or_insert_with(Instant::now);
+                (full_since.elapsed() >= duration).then(|| {
+                    format!(
+                        "Slow client: queue full for {} ms",
+                        full_since.elapsed().as_millis()
+                    )
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drop() {
+        let tracker = OverflowTracker::new(SlowClientPolicy::Drop);
+        for _ in 0..100 {
+            assert_eq!(tracker.on_overflow(), None);
+        }
+    }
+
+    #[test]
+    fn test_disconnect_after_overflows() {
+        let tracker = OverflowTracker::new(SlowClientPolicy::DisconnectAfterOverflows(3));
+        assert_eq!(tracker.on_overflow(), None);
+        assert_eq!(tracker.on_overflow(), None);
+        // A successful send resets the count.
+        tracker.on_sent();
+        assert_eq!(tracker.on_overflow(), None);
+        assert_eq!(tracker.on_overflow(), None);
+        assert!(tracker.on_overflow().is_some());
+    }
+
+    #[test]
+    fn test_disconnect_after_full_for() {
+        let tracker = OverflowTracker::new(SlowClientPolicy::DisconnectAfterFullFor(
+            Duration::from_millis(20),
+        ));
+        assert_eq!(tracker.on_overflow(), None);
+        std::thread::sleep(Duration::from_millis(30));
+        // A successful send resets the timer.
+        tracker.on_sent();
+        assert_eq!(tracker.on_overflow(), None);
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(tracker.on_overflow().is_some());
+    }
+}
@@ -1,5 +1,5 @@
 use assert_matches::assert_matches;
-use bytes::{BufMut, BytesMut};
+use bytes::{BufMut, Bytes, BytesMut};
 use futures_util::{FutureExt, SinkExt, StreamExt};
 use serde::Deserialize;
 use serde_json::{json, Value};
@@ -10,14 +10,20 @@ use tokio_tungstenite::tungstenite::{self, http::HeaderValue, Message};
 use tracing_test::traced_test;
 use tungstenite::client::IntoClientRequest;
 
-use super::{create_server, send_lossy, SendLossyResult, ServerOptions, SUBPROTOCOL};
+use super::{create_server, send_lossy, QueuePolicy, SendLossyResult, ServerOptions, SUBPROTOCOL};
+#[cfg(feature = "tls")]
+use super::{PemSource, TlsIdentity};
 use crate::testutil::RecordingServerListener;
-use crate::websocket::service::{CallId, Service, ServiceId, ServiceSchema};
+use crate::websocket::assets::{AssetResponder, FilesystemAssetHandler};
+use crate::websocket::service::{CallId, Request, Responder, Service, ServiceId, ServiceSchema};
 use crate::websocket::{
-    Capability, ClientChannelId, Parameter, ParameterType, ParameterValue, Status, StatusLevel,
+    AuthRejection, AuthRequest, Capability, Client, ClientChannelId, ClientId, ClientIdentity,
+    ConnectionGraph, Parameter, ParameterStore, ParameterType, ParameterValue, SlowClientPolicy,
+    Status, StatusLevel, TypedParameter,
 };
 use crate::{
-    collection, Channel, ChannelBuilder, FoxgloveError, LogContext, LogSink, Metadata, Schema,
+    collection, Channel, ChannelBuilder, FoxgloveError, LogContext, LogSink, Metadata,
+    PartialMetadata, Schema, TopicPattern,
 };
 
 fn make_message(id: usize) -> Message {
@@ -73,7 +79,7 @@ fn test_send_lossy() {
     assert_eq!(received, ((TOTAL - BACKLOG)..TOTAL).collect::<Vec<_>>());
 }
 
-fn new_channel(topic: &str, ctx: &LogContext) -> Arc<Channel> {
+fn new_channel(topic: &str, ctx: &Arc<LogContext>) -> Arc<Channel> {
     ChannelBuilder::new(topic)
         .message_encoding("message_encoding")
         .schema(Schema::new(
@@ -82,7 +88,7 @@ fn new_channel(topic: &str, ctx: &LogContext) -> Arc<Channel> {
             b"schema_data",
         ))
         .metadata(collection! {"key".to_string() => "value".to_string()})
-        .with_context(ctx)
+        .context(ctx)
         .build()
         .expect("Failed to create channel")
 }
@@ -198,7 +204,7 @@ async fn test_advertise_to_client() {
         ..Default::default()
     });
 
-    let ctx = LogContext::new();
+    let ctx = Arc::new(LogContext::new());
     ctx.add_sink(server.clone());
 
     let addr = server
@@ -290,7 +296,7 @@ async fn test_log_only_to_subscribers() {
         ..Default::default()
     });
 
-    let ctx = LogContext::new();
+    let ctx = Arc::new(LogContext::new());
 
     ctx.add_sink(server.clone());
 
@@ -1275,27 +1281,1607 @@ async fn test_services() {
     );
 }
 
-/// Connect to a server, ensuring the protocol header is set, and return the client WS stream
-pub async fn connect_client(
-    addr: String,
-) -> tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>> {
-    let mut request = format!("ws://{addr}/")
+#[traced_test]
+#[tokio::test]
+async fn test_connection_graph() {
+    let recording_listener = Arc::new(RecordingServerListener::new());
+    let server = create_server(ServerOptions {
+        capabilities: Some(HashSet::from([Capability::ConnectionGraph])),
+        listener: Some(recording_listener.clone()),
+        ..Default::default()
+    });
+    let addr = server
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+
+    let mut graph = ConnectionGraph::new();
+    graph.set_published_topic("/a", ["pub1"]);
+    graph.set_published_topic("/b", ["pub1"]);
+    server
+        .replace_connection_graph(graph)
+        .expect("Failed to replace graph");
+
+    let mut client1 = connect_client(addr.clone()).await;
+    let mut client2 = connect_client(addr).await;
+    let _ = client1.next().await.expect("No serverInfo sent");
+    let _ = client2.next().await.expect("No serverInfo sent");
+
+    let subscribe = Message::text(r#"{"op":"subscribeConnectionGraph"}"#);
+    client1
+        .send(subscribe.clone())
+        .await
+        .expect("Failed to send");
+    client2.send(subscribe).await.expect("Failed to send");
+
+    // Subscribers first receive the full graph.
+    let full_graph = json!({
+        "op": "connectionGraphUpdate",
+        "publishedTopics": [
+            {"name": "/a", "publisherIds": ["pub1"]},
+            {"name": "/b", "publisherIds": ["pub1"]},
+        ],
+        "subscribedTopics": [],
+        "advertisedServices": [],
+        "removedTopics": [],
+        "removedServices": [],
+    });
+    for client in [&mut client1, &mut client2] {
+        let msg = client.next().await.expect("No message received").unwrap();
+        let msg: Value = serde_json::from_str(msg.to_text().unwrap()).unwrap();
+        assert_eq!(msg, full_graph);
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert_eq!(recording_listener.take_connection_graph_subscribe(), 1);
+
+    client2
+        .send(Message::text(r#"{"op":"unsubscribeConnectionGraph"}"#))
+        .await
+        .expect("Failed to send");
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert_eq!(recording_listener.take_connection_graph_unsubscribe(), 0);
+
+    // Subsequent updates only include the differences.
+    let mut graph = ConnectionGraph::new();
+    graph.set_published_topic("/a", ["pub1"]);
+    graph.set_subscribed_topic("/a", ["sub1"]);
+    graph.set_advertised_service("/svc", ["pub1"]);
+    server
+        .replace_connection_graph(graph)
+        .expect("Failed to replace graph");
+
+    let msg = client1.next().await.expect("No message received").unwrap();
+    let msg: Value = serde_json::from_str(msg.to_text().unwrap()).unwrap();
+    assert_eq!(
+        msg,
+        json!({
+            "op": "connectionGraphUpdate",
+            "publishedTopics": [],
+            "subscribedTopics": [{"name": "/a", "subscriberIds": ["sub1"]}],
+            "advertisedServices": [{"name": "/svc", "providerIds": ["pub1"]}],
+            "removedTopics": ["/b"],
+            "removedServices": [],
+        })
+    );
+
+    // Disconnecting the last subscriber notifies the listener.
+    client1.close(None).await.expect("Failed to close");
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert_eq!(recording_listener.take_connection_graph_unsubscribe(), 1);
+
+    server.stop().await;
+
+    // The unsubscribed client did not receive the update.
+    assert_matches!(client2.next().await, Some(Ok(Message::Close(_))));
+}
+
+#[tokio::test]
+async fn test_connection_graph_not_supported() {
+    let server = create_server(ServerOptions::default());
+    assert_matches!(
+        server.replace_connection_graph(ConnectionGraph::new()),
+        Err(FoxgloveError::ConnectionGraphNotSupported)
+    );
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_parameter_store() {
+    let store = ParameterStore::new();
+    store.declare(TypedParameter::new("gain", 1.0)).unwrap();
+    store
+        .declare(TypedParameter::new("name", "robot".to_string()).read_only())
+        .unwrap();
+    let server = create_server(ServerOptions {
+        parameter_store: Some(store.clone()),
+        ..Default::default()
+    });
+    let addr = server
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+
+    let mut client = connect_client(addr).await;
+    let msg = client.next().await.expect("No serverInfo sent").unwrap();
+    let server_info: Value = serde_json::from_str(msg.to_text().unwrap()).unwrap();
+    let capabilities = server_info["capabilities"].as_array().unwrap();
+    assert!(capabilities.contains(&json!("parameters")));
+    assert!(capabilities.contains(&json!("parametersSubscribe")));
+
+    client
+        .send(Message::text(
+            r#"{"op":"getParameters","parameterNames":[],"id":"get"}"#,
+        ))
+        .await
+        .expect("Failed to send");
+    assert_eq!(
+        next_json(&mut client).await,
+        json!({
+            "op": "parameterValues",
+            "id": "get",
+            "parameters": [
+                {"name": "gain", "type": "float64", "value": 1.0},
+                {"name": "name", "value": "cm9ib3Q="},
+            ],
+        })
+    );
+
+    client
+        .send(Message::text(
+            r#"{"op":"subscribeParameterUpdates","parameterNames":["gain"]}"#,
+        ))
+        .await
+        .expect("Failed to send");
+    client
+        .send(Message::text(
+            r#"{"op":"setParameters","parameters":[{"name":"gain","value":2},{"name":"name","value":"b3RoZXI="}],"id":"set"}"#,
+        ))
+        .await
+        .expect("Failed to send");
+
+    // Subscribers are notified about the change.
+    assert_eq!(
+        next_json(&mut client).await,
+        json!({
+            "op": "parameterValues",
+            "parameters": [{"name": "gain", "type": "float64", "value": 2.0}],
+        })
+    );
+    // The read-only parameter is rejected.
+    let msg = next_json(&mut client).await;
+    assert_eq!(msg["op"], "status");
+    assert_eq!(msg["level"], 2);
+    // The response contains the current values of the requested parameters.
+    assert_eq!(
+        next_json(&mut client).await,
+        json!({
+            "op": "parameterValues",
+            "id": "set",
+            "parameters": [
+                {"name": "gain", "type": "float64", "value": 2.0},
+                {"name": "name", "value": "cm9ib3Q="},
+            ],
+        })
+    );
+    assert_eq!(store.get::<f64>("gain"), Some(2.0));
+
+    // Changes made by the server are also published.
+    store.set("gain", 3.0).unwrap();
+    assert_eq!(
+        next_json(&mut client).await,
+        json!({
+            "op": "parameterValues",
+            "parameters": [{"name": "gain", "type": "float64", "value": 3.0}],
+        })
+    );
+
+    server.stop().await;
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_parameter_store_shared_by_servers() {
+    let store = ParameterStore::new();
+    store.declare(TypedParameter::new("gain", 1.0)).unwrap();
+    let server1 = create_server(ServerOptions {
+        parameter_store: Some(store.clone()),
+        ..Default::default()
+    });
+    let server2 = create_server(ServerOptions {
+        parameter_store: Some(store.clone()),
+        ..Default::default()
+    });
+    let addr1 = server1
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+    let addr2 = server2
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+
+    let mut client1 = connect_client(addr1).await;
+    let mut client2 = connect_client(addr2).await;
+    for client in [&mut client1, &mut client2] {
+        let _ = client.next().await.expect("No serverInfo sent").unwrap();
+    }
+    client2
+        .send(Message::text(
+            r#"{"op":"subscribeParameterUpdates","parameterNames":["gain"]}"#,
+        ))
+        .await
+        .expect("Failed to send");
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+    // A change made by a client of one server is published to clients of the other.
+    client1
+        .send(Message::text(
+            r#"{"op":"setParameters","parameters":[{"name":"gain","value":2}]}"#,
+        ))
+        .await
+        .expect("Failed to send");
+    assert_eq!(
+        next_json(&mut client2).await,
+        json!({
+            "op": "parameterValues",
+            "parameters": [{"name": "gain", "type": "float64", "value": 2.0}],
+        })
+    );
+
+    server1.stop().await;
+    server2.stop().await;
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_fetch_asset() {
+    let dir = tempfile::tempdir().expect("Failed to create tempdir");
+    let root = dir.path().canonicalize().unwrap();
+    std::fs::write(root.join("mesh.stl"), b"solid mesh").unwrap();
+
+    let server = create_server(ServerOptions {
+        asset_handler: Some(Arc::new(FilesystemAssetHandler::new(&root))),
+        ..Default::default()
+    });
+    let addr = server
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+
+    let mut client = connect_client(addr).await;
+    let msg = client.next().await.expect("No serverInfo sent").unwrap();
+    let server_info: Value = serde_json::from_str(msg.to_text().unwrap()).unwrap();
+    assert_eq!(server_info["capabilities"], json!(["assets"]));
+
+    let fetch = |uri: String, request_id: u32| {
+        Message::text(
+            json!({
+                "op": "fetchAsset",
+                "uri": uri,
+                "requestId": request_id,
+            })
+            .to_string(),
+        )
+    };
+    let expected_response = |request_id: u32, status: u8, error: &str, data: &[u8]| {
+        let mut buf = BytesMut::new();
+        buf.put_u8(4); // opcode
+        buf.put_u32_le(request_id);
+        buf.put_u8(status);
+        buf.put_u32_le(error.len() as u32);
+        buf.put(error.as_bytes());
+        buf.put(data);
+        buf.freeze()
+    };
+
+    let uri = format!("file://{}", root.join("mesh.stl").display());
+    client.send(fetch(uri, 1)).await.expect("Failed to send");
+    let msg = client.next().await.expect("No response").unwrap();
+    assert_eq!(msg.into_data(), expected_response(1, 0, "", b"solid mesh"));
+
+    let missing = root.join("missing.stl");
+    let uri = format!("file://{}", missing.display());
+    client.send(fetch(uri, 2)).await.expect("Failed to send");
+    let msg = client.next().await.expect("No response").unwrap();
+    let error = format!("Asset not found: {}", missing.display());
+    assert_eq!(msg.into_data(), expected_response(2, 1, &error, b""));
+
+    server.stop().await;
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_fetch_asset_dropped_responder() {
+    let handler = |_client: Client, _uri: String, responder: AssetResponder| drop(responder);
+    let server = create_server(ServerOptions {
+        asset_handler: Some(Arc::new(handler)),
+        ..Default::default()
+    });
+    let addr = server
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+
+    let mut client = connect_client(addr).await;
+    let _ = client.next().await.expect("No serverInfo sent").unwrap();
+    let fetch = json!({
+        "op": "fetchAsset",
+        "uri": "file:///mesh.stl",
+        "requestId": 1,
+    });
+    client
+        .send(Message::text(fetch.to_string()))
+        .await
+        .expect("Failed to send");
+
+    // The client receives an error response.
+    let msg = client.next().await.expect("No response").unwrap();
+    let data = msg.into_data();
+    assert_eq!(data[0], 4); // opcode
+    assert_eq!(data[5], 1); // error status
+    assert!(data.ends_with(b"internal error: no response"));
+
+    server.stop().await;
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_fetch_asset_not_supported() {
+    let server = create_server(ServerOptions::default());
+    let addr = server
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+
+    let mut client = connect_client(addr).await;
+    let _ = client.next().await.expect("No serverInfo sent");
+    client
+        .send(Message::text(
+            r#"{"op":"fetchAsset","uri":"package://foo/bar.stl","requestId":3}"#,
+        ))
+        .await
+        .expect("Failed to send");
+    let msg = client.next().await.expect("No response").unwrap();
+    let data = msg.into_data();
+    assert_eq!(data[..6], [4, 3, 0, 0, 0, 1]);
+    assert!(data.ends_with(b"Server does not support assets capability"));
+
+    server.stop().await;
+}
+
+#[cfg(feature = "tls")]
+#[traced_test]
+#[tokio::test]
+async fn test_tls_client_connect() {
+    use tokio_rustls::rustls::{self, pki_types::ServerName, ClientConfig, RootCertStore};
+
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])
+        .expect("Failed to generate certificate");
+    let server = create_server(ServerOptions {
+        name: Some("tls_server".to_string()),
+        tls: Some(TlsIdentity {
+            cert_chain: PemSource::Data(cert.cert.pem().into_bytes()),
+            private_key: PemSource::Data(cert.key_pair.serialize_pem().into_bytes()),
+        }),
+        ..Default::default()
+    });
+    let addr = server
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+
+    let mut roots = RootCertStore::empty();
+    roots
+        .add(cert.cert.der().clone())
+        .expect("Failed to add root");
+    let config =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .expect("Failed to configure protocol versions")
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+    let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
+    let tcp = tokio::net::TcpStream::connect(&addr)
+        .await
+        .expect("Failed to connect");
+    let tls = connector
+        .connect(ServerName::try_from("localhost").unwrap(), tcp)
+        .await
+        .expect("TLS handshake failed");
+
+    let mut request = format!("wss://{addr}/")
         .into_client_request()
         .expect("Failed to build request");
-
     request.headers_mut().insert(
         "sec-websocket-protocol",
         HeaderValue::from_static(SUBPROTOCOL),
     );
-
-    let (ws_stream, response) = tokio_tungstenite::connect_async(request)
+    let (mut client_stream, _) = tokio_tungstenite::client_async(request, tls)
         .await
         .expect("Failed to connect");
 
-    assert_eq!(
-        response.headers().get("sec-websocket-protocol"),
-        Some(&HeaderValue::from_static(SUBPROTOCOL))
-    );
+    let msg = client_stream
+        .next()
+        .await
+        .expect("No message received")
+        .expect("Failed to parse message");
+    let text = msg.into_text().expect("Failed to get message text");
+    let server_info: Value = serde_json::from_str(&text).expect("Failed to parse server info");
+    assert_eq!(server_info["name"], "tls_server");
 
-    ws_stream
+    // Plaintext connections are rejected.
+    let request = format!("ws://{addr}/")
+        .into_client_request()
+        .expect("Failed to build request");
+    assert!(tokio_tungstenite::connect_async(request).await.is_err());
+
+    server.stop().await;
+}
+
+#[cfg(feature = "tls")]
+#[tokio::test]
+async fn test_tls_invalid_identity() {
+    let server = create_server(ServerOptions {
+        tls: Some(TlsIdentity {
+            cert_chain: PemSource::Data(b"not a certificate".to_vec()),
+            private_key: PemSource::Data(b"not a key".to_vec()),
+        }),
+        ..Default::default()
+    });
+    let result = server.start("127.0.0.1", 0).await;
+    assert_matches!(result, Err(FoxgloveError::TlsConfig(_)));
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_authenticator() {
+    let authenticator = |req: &AuthRequest| {
+        assert_eq!(req.path(), "/live");
+        match req.header("authorization") {
+            Some("Bearer secret") => {
+                Ok(ClientIdentity::new("alice").with_attribute("role", "admin"))
+            }
+            Some(_) => Err(AuthRejection::forbidden("bad token")),
+            None => Err(AuthRejection::unauthorized("missing token")),
+        }
+    };
+    let whoami = Service::builder("/whoami", ServiceSchema::new("plain"))
+        .with_id(ServiceId::new(1))
+        .sync_handler_fn(|client, _| {
+            let identity = client.identity().ok_or("no identity")?;
+            let role = identity.attribute("role").unwrap_or_default();
+            Ok::<_, &str>(format!("{}:{role}", identity.name()).into())
+        });
+    let server = create_server(ServerOptions {
+        authenticator: Some(Arc::new(authenticator)),
+        services: HashMap::from([("/whoami".to_string(), whoami)]),
+        supported_encodings: Some(HashSet::from(["raw".to_string()])),
+        ..Default::default()
+    });
+    let addr = server
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+
+    let request = |token: Option<&'static str>| {
+        let mut request = format!("ws://{addr}/live")
+            .into_client_request()
+            .expect("Failed to build request");
+        let headers = request.headers_mut();
+        headers.insert(
+            "sec-websocket-protocol",
+            HeaderValue::from_static(SUBPROTOCOL),
+        );
+        if let Some(token) = token {
+            headers.insert("authorization", HeaderValue::from_static(token));
+        }
+        request
+    };
+
+    // Rejected requests receive the status code from the authenticator.
+    for (token, status) in [(None, 401), (Some("Bearer wrong"), 403)] {
+        let result = tokio_tungstenite::connect_async(request(token)).await;
+        assert_matches!(
+            result,
+            Err(tungstenite::Error::Http(response)) if response.status() == status
+        );
+    }
+
+    let (mut client, _) = tokio_tungstenite::connect_async(request(Some("Bearer secret")))
+        .await
+        .expect("Failed to connect");
+    let _ = client.next().await.expect("No serverInfo sent").unwrap();
+    let _ = client.next().await.expect("No advertisement sent").unwrap();
+
+    let mut buf = BytesMut::new();
+    buf.put_u8(2); // opcode
+    buf.put_u32_le(1); // service id
+    buf.put_u32_le(1); // call id
+    buf.put_u32_le(3); // encoding length
+    buf.put(b"raw".as_slice());
+    client
+        .send(Message::binary(buf.freeze()))
+        .await
+        .expect("Failed to send");
+
+    let msg = client
+        .next()
+        .await
+        .expect("No service call response")
+        .expect("Failed to parse response");
+    let data = msg.into_data();
+    assert_eq!(data[0], 3); // opcode
+    assert!(data.ends_with(b"alice:admin"));
+
+    // Rejections are not logged as errors.
+    assert!(logs_contain("rejected with status 401"));
+    assert!(!logs_contain("client handshake failed"));
+
+    server.stop().await;
+}
+
+/// Receive the next message from the server and parse it as JSON.
+async fn next_json(
+    client: &mut tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >,
+) -> Value {
+    let msg = client
+        .next()
+        .await
+        .expect("No message received")
+        .expect("Failed to parse message");
+    serde_json::from_str(msg.to_text().expect("Expected utf8")).expect("Failed to parse JSON")
+}
+
+/// Build a raw-encoded service call request message
+fn service_call_request(service_id: u32, call_id: u32, payload: &[u8]) -> Message {
+    encoded_service_call_request(service_id, call_id, "raw", payload)
+}
+
+fn encoded_service_call_request(
+    service_id: u32,
+    call_id: u32,
+    encoding: &str,
+    payload: &[u8],
+) -> Message {
+    let mut buf = BytesMut::new();
+    buf.put_u8(2); // opcode
+    buf.put_u32_le(service_id);
+    buf.put_u32_le(call_id);
+    buf.put_u32_le(encoding.len() as u32);
+    buf.put(encoding.as_bytes());
+    buf.put(payload);
+    Message::binary(buf)
+}
+
+/// Connect to a server, ensuring the protocol header is set, and return the client WS stream
+pub async fn connect_client(
+    addr: String,
+) -> tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>> {
+    let mut request = format!("ws://{addr}/")
+        .into_client_request()
+        .expect("Failed to build request");
+
+    request.headers_mut().insert(
+        "sec-websocket-protocol",
+        HeaderValue::from_static(SUBPROTOCOL),
+    );
+
+    let (ws_stream, response) = tokio_tungstenite::connect_async(request)
+        .await
+        .expect("Failed to connect");
+
+    assert_eq!(
+        response.headers().get("sec-websocket-protocol"),
+        Some(&HeaderValue::from_static(SUBPROTOCOL))
+    );
+
+    ws_stream
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_slow_client_disconnect() {
+    let recording_listener = Arc::new(RecordingServerListener::new());
+
+    let server = create_server(ServerOptions {
+        listener: Some(recording_listener.clone()),
+        message_backlog_size: Some(1),
+        slow_client_policy: SlowClientPolicy::DisconnectAfterOverflows(3),
+        ..Default::default()
+    });
+
+    let ctx = Arc::new(LogContext::new());
+    ctx.add_sink(server.clone());
+    let ch = new_channel("/foo", &ctx);
+
+    let addr = server
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+
+    let mut client = connect_client(addr).await;
+    let msg = next_json(&mut client).await;
+    assert_eq!(msg["op"], "serverInfo");
+    let msg = next_json(&mut client).await;
+    assert_eq!(msg["op"], "advertise");
+
+    let subscribe = json!({
+        "op": "subscribe",
+        "subscriptions": [{ "id": 1, "channelId": ch.id() }]
+    });
+    client
+        .send(Message::text(subscribe.to_string()))
+        .await
+        .expect("Failed to send");
+
+    // Allow the server to process the subscription
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    // The server's send task can't run while we're logging, so the queue overflows.
+    let metadata = Metadata::default();
+    for _ in 0..10 {
+        server.log(&ch, b"foo", &metadata).unwrap();
+    }
+
+    // The client receives a close frame explaining why it was disconnected.
+    let close_frame = loop {
+        match client.next().await.expect("Connection closed") {
+            Ok(Message::Close(frame)) => break frame.expect("Missing close frame"),
+            Ok(_) => continue,
+            Err(err) => panic!("Unexpected error: {err}"),
+        }
+    };
+
+    // The listener is notified from a task on the server's runtime.
+    let disconnects = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            let disconnects = recording_listener.take_slow_client_disconnect();
+            if !disconnects.is_empty() {
+                break disconnects;
+            }
+            tokio::task::yield_now().await;
+        }
+    })
+    .await
+    .expect("Listener was not notified");
+    assert_eq!(disconnects.len(), 1);
+    assert!(disconnects[0].1.contains("3 consecutive queue overflows"));
+    assert_eq!(
+        close_frame.code,
+        tungstenite::protocol::frame::coding::CloseCode::Policy
+    );
+    assert_eq!(close_frame.reason.as_str(), disconnects[0].1);
+
+    server.stop().await;
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_slow_client_disconnect_channel_queue() {
+    let server = create_server(ServerOptions {
+        slow_client_policy: SlowClientPolicy::DisconnectAfterOverflows(3),
+        queue_policies: vec![(
+            TopicPattern::glob("/tf").unwrap(),
+            QueuePolicy::latest_only().with_priority(1),
+        )],
+        ..Default::default()
+    });
+
+    let ctx = Arc::new(LogContext::new());
+    ctx.add_sink(server.clone());
+    let tf = new_channel("/tf", &ctx);
+
+    let addr = server
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+
+    let mut client = connect_client(addr).await;
+    let msg = next_json(&mut client).await;
+    assert_eq!(msg["op"], "serverInfo");
+    let msg = next_json(&mut client).await;
+    assert_eq!(msg["op"], "advertise");
+
+    let subscribe = json!({
+        "op": "subscribe",
+        "subscriptions": [{ "id": 1, "channelId": tf.id() }]
+    });
+    client
+        .send(Message::text(subscribe.to_string()))
+        .await
+        .expect("Failed to send");
+
+    // Allow the server to process the subscription
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    // The server's send task can't run while we're logging, so the channel's queue overflows.
+    for i in 0..10u8 {
+        server.log(&tf, &[i], &Metadata::default()).unwrap();
+    }
+
+    let close_frame = loop {
+        match client.next().await.expect("Connection closed") {
+            Ok(Message::Close(frame)) => break frame.expect("Missing close frame"),
+            Ok(_) => continue,
+            Err(err) => panic!("Unexpected error: {err}"),
+        }
+    };
+    assert_eq!(
+        close_frame.code,
+        tungstenite::protocol::frame::coding::CloseCode::Policy
+    );
+    assert!(close_frame
+        .reason
+        .as_str()
+        .contains("3 consecutive queue overflows"));
+
+    server.stop().await;
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_queue_policy_latest_only() {
+    let server = create_server(ServerOptions {
+        message_backlog_size: Some(2),
+        queue_policies: vec![(
+            TopicPattern::glob("/tf").unwrap(),
+            QueuePolicy::latest_only(),
+        )],
+        ..Default::default()
+    });
+
+    let ctx = Arc::new(LogContext::new());
+    ctx.add_sink(server.clone());
+    let points = new_channel("/points", &ctx);
+    let tf = new_channel("/tf", &ctx);
+
+    let addr = server
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+
+    let mut client = connect_client(addr).await;
+    let msg = next_json(&mut client).await;
+    assert_eq!(msg["op"], "serverInfo");
+    for _ in 0..2 {
+        let msg = next_json(&mut client).await;
+        assert_eq!(msg["op"], "advertise");
+    }
+
+    let subscribe = json!({
+        "op": "subscribe",
+        "subscriptions": [
+            { "id": 1, "channelId": points.id() },
+            { "id": 2, "channelId": tf.id() },
+        ]
+    });
+    client
+        .send(Message::text(subscribe.to_string()))
+        .await
+        .expect("Failed to send");
+
+    // Allow the server to process the subscription
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    // The server's send task can't run while we're logging, so the queues overflow. A burst of
+    // points must not push out the latest transform.
+    for i in 0..10u8 {
+        server.log(&points, &[i], &Metadata::default()).unwrap();
+        if i % 2 == 0 {
+            server.log(&tf, &[i], &Metadata::default()).unwrap();
+        }
+    }
+
+    let mut received = vec![];
+    for _ in 0..3 {
+        let msg = client
+            .next()
+            .await
+            .expect("No message received")
+            .expect("Failed to parse message");
+        let data = msg.into_data();
+        assert_eq!(data[0], 0x01); // message data opcode
+        let subscription_id = u32::from_le_bytes(data[1..=4].try_into().unwrap());
+        received.push((subscription_id, data[13]));
+    }
+    received.sort();
+    assert_eq!(received, vec![(1, 8), (1, 9), (2, 8)]);
+
+    server.stop().await;
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_queue_policy_priority_after_idle() {
+    let server = create_server(ServerOptions {
+        queue_policies: vec![(
+            TopicPattern::glob("/tf").unwrap(),
+            QueuePolicy::bounded(10).with_priority(1),
+        )],
+        ..Default::default()
+    });
+
+    let ctx = Arc::new(LogContext::new());
+    ctx.add_sink(server.clone());
+    let points = new_channel("/points", &ctx);
+    let tf = new_channel("/tf", &ctx);
+
+    let addr = server
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+
+    let mut client = connect_client(addr).await;
+    let msg = next_json(&mut client).await;
+    assert_eq!(msg["op"], "serverInfo");
+    for _ in 0..2 {
+        let msg = next_json(&mut client).await;
+        assert_eq!(msg["op"], "advertise");
+    }
+
+    let subscribe = json!({
+        "op": "subscribe",
+        "subscriptions": [
+            { "id": 1, "channelId": points.id() },
+            { "id": 2, "channelId": tf.id() },
+        ]
+    });
+    client
+        .send(Message::text(subscribe.to_string()))
+        .await
+        .expect("Failed to send");
+
+    // Allow the server to process the subscription, and the send task to go idle.
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    // Although the shared queue wakes the idle send task first, the higher priority message is
+    // sent first.
+    server.log(&points, &[0], &Metadata::default()).unwrap();
+    server.log(&tf, &[1], &Metadata::default()).unwrap();
+
+    let mut received = vec![];
+    for _ in 0..2 {
+        let msg = client
+            .next()
+            .await
+            .expect("No message received")
+            .expect("Failed to parse message");
+        let data = msg.into_data();
+        assert_eq!(data[0], 0x01); // message data opcode
+        let subscription_id = u32::from_le_bytes(data[1..=4].try_into().unwrap());
+        received.push((subscription_id, data[13]));
+    }
+    assert_eq!(received, vec![(2, 1), (1, 0)]);
+
+    server.stop().await;
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_client_connect_disconnect() {
+    let recording_listener = Arc::new(RecordingServerListener::new());
+    let server = create_server(ServerOptions {
+        listener: Some(recording_listener.clone()),
+        ..Default::default()
+    });
+    let ctx = Arc::new(LogContext::new());
+    ctx.add_sink(server.clone());
+    let ch = new_channel("/foo", &ctx);
+
+    let addr = server
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+
+    let mut request = format!("ws://{addr}/viz")
+        .into_client_request()
+        .expect("Failed to build request");
+    let headers = request.headers_mut();
+    headers.insert(
+        "sec-websocket-protocol",
+        HeaderValue::from_static(SUBPROTOCOL),
+    );
+    headers.insert("user-agent", HeaderValue::from_static("test-agent/1.0"));
+    let (mut client, _) = tokio_tungstenite::connect_async(request)
+        .await
+        .expect("Failed to connect");
+    let msg = next_json(&mut client).await;
+    assert_eq!(msg["op"], "serverInfo");
+    let local_addr = match client.get_ref() {
+        tokio_tungstenite::MaybeTlsStream::Plain(stream) => stream.local_addr().unwrap(),
+        _ => unreachable!(),
+    };
+
+    // Allow the server to invoke the callback
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    let connects = recording_listener.take_client_connect();
+    assert_eq!(connects.len(), 1);
+    assert_eq!(connects[0].addr, local_addr);
+    assert_eq!(connects[0].user_agent.as_deref(), Some("test-agent/1.0"));
+    assert_eq!(connects[0].path, "/viz");
+    assert!(recording_listener.take_client_disconnect().is_empty());
+
+    let subscribe = json!({
+        "op": "subscribe",
+        "subscriptions": [{ "id": 1, "channelId": ch.id() }]
+    });
+    client
+        .send(Message::text(subscribe.to_string()))
+        .await
+        .expect("Failed to send");
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert_eq!(recording_listener.take_subscribe().len(), 1);
+
+    // Disconnecting without unsubscribing still notifies the listener of the unsubscribe.
+    client.close(None).await.expect("Failed to close");
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    let unsubscribes = recording_listener.take_unsubscribe();
+    assert_eq!(unsubscribes.len(), 1);
+    assert_eq!(unsubscribes[0].0, connects[0].id);
+    assert_eq!(unsubscribes[0].1.topic, "/foo");
+    let disconnects = recording_listener.take_client_disconnect();
+    assert_eq!(disconnects.len(), 1);
+    assert_eq!(disconnects[0].id, connects[0].id);
+
+    server.stop().await;
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_clients_snapshot_and_disconnect() {
+    let server = create_server(ServerOptions {
+        capabilities: Some(HashSet::from([Capability::ClientPublish])),
+        supported_encodings: Some(HashSet::from(["json".to_string()])),
+        ..Default::default()
+    });
+    let ctx = Arc::new(LogContext::new());
+    ctx.add_sink(server.clone());
+    let ch = new_channel("/foo", &ctx);
+
+    let addr = server
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+
+    let mut client = connect_client(addr).await;
+    let msg = next_json(&mut client).await;
+    assert_eq!(msg["op"], "serverInfo");
+    let msg = next_json(&mut client).await;
+    assert_eq!(msg["op"], "advertise");
+
+    let subscribe = json!({
+        "op": "subscribe",
+        "subscriptions": [{ "id": 1, "channelId": ch.id() }]
+    });
+    let advertise = json!({
+        "op": "advertise",
+        "channels": [{ "id": 1, "topic": "/bar", "encoding": "json", "schemaName": "test" }]
+    });
+    for msg in [subscribe, advertise] {
+        client
+            .send(Message::text(msg.to_string()))
+            .await
+            .expect("Failed to send");
+    }
+
+    // Allow the server to process the messages
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let clients = server.clients();
+    assert_eq!(clients.len(), 1);
+    let snapshot = &clients[0];
+    assert_eq!(snapshot.path(), "/");
+    assert_eq!(snapshot.subscribed_topics(), ["/foo"]);
+    assert_eq!(snapshot.client_channels(), ["/bar"]);
+    assert_eq!(snapshot.queue_depth(), 0);
+    assert_eq!(snapshot.dropped_messages(), 0);
+
+    assert!(!server.disconnect_client(ClientId(u32::MAX), "unknown"));
+    assert!(server.disconnect_client(snapshot.id(), "kicked by operator"));
+
+    let close_frame = loop {
+        match client.next().await.expect("Connection closed") {
+            Ok(Message::Close(frame)) => break frame.expect("Missing close frame"),
+            Ok(_) => continue,
+            Err(err) => panic!("Unexpected error: {err}"),
+        }
+    };
+    assert_eq!(close_frame.reason.as_str(), "kicked by operator");
+
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert!(server.clients().is_empty());
+
+    server.stop().await;
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_latched_channel() {
+    let server = create_server(ServerOptions::default());
+    let ctx = Arc::new(LogContext::new());
+    ctx.add_sink(server.clone());
+    let ch = ChannelBuilder::new("/map")
+        .message_encoding("json")
+        .schema(Schema::new("schema_name", "jsonschema", b"{}"))
+        .latched(2)
+        .context(&ctx)
+        .build()
+        .expect("Failed to create channel");
+    assert_eq!(ch.latched(), 2);
+
+    let addr = server
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+
+    for log_time in 1..=3u64 {
+        let metadata = Metadata {
+            log_time,
+            ..Metadata::default()
+        };
+        server.log(&ch, &[log_time as u8], &metadata).unwrap();
+    }
+
+    let mut client = connect_client(addr).await;
+    let msg = next_json(&mut client).await;
+    assert_eq!(msg["op"], "serverInfo");
+    let msg = next_json(&mut client).await;
+    assert_eq!(msg["op"], "advertise");
+
+    let subscribe = json!({
+        "op": "subscribe",
+        "subscriptions": [{ "id": 7, "channelId": ch.id() }]
+    });
+    client
+        .send(Message::text(subscribe.to_string()))
+        .await
+        .expect("Failed to send");
+
+    // Returns the log time and payload of the next message data from the client.
+    async fn next_message_data(
+        client: &mut tokio_tungstenite::WebSocketStream<
+            tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+        >,
+    ) -> (u64, u8) {
+        let msg = client
+            .next()
+            .await
+            .expect("No message received")
+            .expect("Failed to parse message");
+        let data = msg.into_data();
+        assert_eq!(data[0], 0x01); // message data opcode
+        assert_eq!(u32::from_le_bytes(data[1..=4].try_into().unwrap()), 7);
+        let log_time = u64::from_le_bytes(data[5..=12].try_into().unwrap());
+        (log_time, data[13])
+    }
+
+    // The last two messages are replayed with their original log times.
+    assert_eq!(next_message_data(&mut client).await, (2, 2));
+    assert_eq!(next_message_data(&mut client).await, (3, 3));
+
+    // New messages are sent as usual.
+    let metadata = Metadata {
+        log_time: 4,
+        ..Metadata::default()
+    };
+    server.log(&ch, &[4], &metadata).unwrap();
+    assert_eq!(next_message_data(&mut client).await, (4, 4));
+
+    server.stop().await;
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_latched_channel_log() {
+    let ctx = Arc::new(LogContext::new());
+    let ch = ChannelBuilder::new("/map")
+        .message_encoding("json")
+        .latched(2)
+        .context(&ctx)
+        .build()
+        .expect("Failed to create channel");
+    let log = |log_time: u64| {
+        ch.log_with_meta(
+            &[log_time as u8],
+            PartialMetadata {
+                log_time: Some(log_time),
+                ..PartialMetadata::default()
+            },
+        );
+    };
+
+    // Messages logged before the server is started are not latched.
+    log(1);
+
+    let server = create_server(ServerOptions::default());
+    let addr = server
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+    ctx.add_sink(server.clone());
+    log(2);
+
+    let mut client = connect_client(addr).await;
+    let msg = next_json(&mut client).await;
+    assert_eq!(msg["op"], "serverInfo");
+    let msg = next_json(&mut client).await;
+    assert_eq!(msg["op"], "advertise");
+
+    let subscribe = json!({
+        "op": "subscribe",
+        "subscriptions": [{ "id": 1, "channelId": ch.id() }]
+    });
+    client
+        .send(Message::text(subscribe.to_string()))
+        .await
+        .expect("Failed to send");
+
+    // Returns the log time and payload of the next message data from the client.
+    async fn next_message_data(
+        client: &mut tokio_tungstenite::WebSocketStream<
+            tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+        >,
+    ) -> (u64, u8) {
+        let msg = client
+            .next()
+            .await
+            .expect("No message received")
+            .expect("Failed to parse message");
+        let data = msg.into_data();
+        assert_eq!(data[0], 0x01); // message data opcode
+        let log_time = u64::from_le_bytes(data[5..=12].try_into().unwrap());
+        (log_time, data[13])
+    }
+
+    // Only the message logged after the server was started is replayed.
+    assert_eq!(next_message_data(&mut client).await, (2, 2));
+
+    log(3);
+    assert_eq!(next_message_data(&mut client).await, (3, 3));
+
+    server.stop().await;
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_advertise_schemaless_and_invalid_channels() {
+    let server = create_server(ServerOptions::default());
+    let ctx = Arc::new(LogContext::new());
+    ctx.add_sink(server.clone());
+
+    let schemaless = ChannelBuilder::new("/schemaless")
+        .message_encoding("json")
+        .context(&ctx)
+        .build()
+        .unwrap();
+    // A jsonschema must be valid UTF-8, so this channel can't be advertised.
+    let _invalid = ChannelBuilder::new("/invalid")
+        .message_encoding("json")
+        .schema(Schema::new("invalid", "jsonschema", b"\xff\xfe".as_slice()))
+        .context(&ctx)
+        .build()
+        .unwrap();
+    // Protobuf isn't self-describing, so this channel requires a schema.
+    let _protobuf = ChannelBuilder::new("/protobuf")
+        .message_encoding("protobuf")
+        .context(&ctx)
+        .build()
+        .unwrap();
+    let _valid = new_channel("/valid", &ctx);
+
+    let addr = server
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+
+    let mut client = connect_client(addr).await;
+    let msg = next_json(&mut client).await;
+    assert_eq!(msg["op"], "serverInfo");
+
+    let mut advertised = HashMap::new();
+    for _ in 0..2 {
+        let msg = next_json(&mut client).await;
+        assert_eq!(msg["op"], "advertise");
+        let channel = &msg["channels"][0];
+        advertised.insert(
+            channel["topic"].as_str().unwrap().to_string(),
+            channel.clone(),
+        );
+    }
+    let mut topics: Vec<_> = advertised.keys().cloned().collect();
+    topics.sort();
+    assert_eq!(topics, ["/schemaless", "/valid"]);
+
+    let channel = &advertised["/schemaless"];
+    assert_eq!(channel["id"], json!(schemaless.id()));
+    assert_eq!(channel["schemaName"], "");
+    assert_eq!(channel["schema"], "");
+    assert!(channel.get("schemaEncoding").is_none());
+    assert!(logs_contain(
+        "Ignoring advertise channel for /protobuf: Schema is required"
+    ));
+
+    server.stop().await;
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_async_service_handler() {
+    let echo = Service::builder("/echo", ServiceSchema::new("plain"))
+        .with_id(ServiceId::new(1))
+        .async_handler_fn(|client, req| async move {
+            tokio::task::yield_now().await;
+            // The client snapshot is owned by the future.
+            if client.path() != "/" {
+                return Err(format!("unexpected path: {}", client.path()));
+            }
+            Ok::<_, String>(req.into_payload())
+        });
+    let slow = Service::builder("/slow", ServiceSchema::new("plain"))
+        .with_id(ServiceId::new(2))
+        .timeout(std::time::Duration::from_millis(100))
+        .async_handler_fn(|_, _| async {
+            tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+            Ok::<_, String>(Bytes::new())
+        });
+    let server = create_server(ServerOptions {
+        services: [echo, slow]
+            .into_iter()
+            .map(|s| (s.name().to_string(), s))
+            .collect(),
+        supported_encodings: Some(HashSet::from(["raw".to_string()])),
+        ..Default::default()
+    });
+    let addr = server
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+
+    let mut client = connect_client(addr).await;
+    let _ = client.next().await.expect("No serverInfo sent").unwrap();
+    let msg = next_json(&mut client).await;
+    assert_eq!(msg["op"], "advertiseServices");
+
+    // The slow call times out; the echo call completes in the meantime.
+    for msg in [
+        service_call_request(2, 1, b"slow"),
+        service_call_request(1, 2, b"payload"),
+    ] {
+        client.send(msg).await.expect("Failed to send");
+    }
+
+    let msg = client
+        .next()
+        .await
+        .expect("No service call response")
+        .expect("Failed to parse response");
+    let mut buf = BytesMut::new();
+    buf.put_u8(3); // opcode
+    buf.put_u32_le(1); // service id
+    buf.put_u32_le(2); // call id
+    buf.put_u32_le(3); // encoding length
+    buf.put(b"raw".as_slice());
+    buf.put(b"payload".as_slice());
+    assert_eq!(msg.into_data(), buf);
+
+    let msg = next_json(&mut client).await;
+    assert_eq!(
+        msg,
+        json!({
+            "op": "serviceCallFailure",
+            "serviceId": 2,
+            "callId": 1,
+            "message": "Service call timed out after 100 ms",
+        })
+    );
+
+    server.stop().await;
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_service_concurrency_limits() {
+    // Responders for calls in progress, in the order they were handled.
+    let pending = Arc::new(parking_lot::Mutex::new(Vec::new()));
+    let hold = |pending: &Arc<parking_lot::Mutex<Vec<_>>>| {
+        let pending = pending.clone();
+        move |_: Client, req: Request, responder| {
+            pending.lock().push((req.into_payload(), responder));
+        }
+    };
+    let serial = Service::builder("/serial", ServiceSchema::new("plain"))
+        .with_id(ServiceId::new(1))
+        .max_concurrent_calls(1)
+        .max_queued_calls(1)
+        .handler_fn(hold(&pending));
+    let per_client = Service::builder("/per_client", ServiceSchema::new("plain"))
+        .with_id(ServiceId::new(2))
+        .max_concurrent_calls_per_client(1)
+        .handler_fn(hold(&pending));
+    let server = create_server(ServerOptions {
+        services: [serial, per_client]
+            .into_iter()
+            .map(|s| (s.name().to_string(), s))
+            .collect(),
+        supported_encodings: Some(HashSet::from(["raw".to_string()])),
+        ..Default::default()
+    });
+    let addr = server
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+
+    let mut client1 = connect_client(addr.clone()).await;
+    let mut client2 = connect_client(addr).await;
+    for client in [&mut client1, &mut client2] {
+        let _ = client.next().await.expect("No serverInfo sent").unwrap();
+        let msg = next_json(client).await;
+        assert_eq!(msg["op"], "advertiseServices");
+    }
+
+    let respond = || {
+        let (payload, responder): (Bytes, Responder) = pending.lock().remove(0);
+        responder.respond(Ok(payload));
+    };
+    let expect_failure = |msg: Value, service_id: u32, call_id: u32| {
+        assert_eq!(
+            msg,
+            json!({
+                "op": "serviceCallFailure",
+                "serviceId": service_id,
+                "callId": call_id,
+                "message": "Too many requests",
+            })
+        );
+    };
+
+    // The first call is handled, the second is queued, and the third is rejected.
+    for call_id in 1..=3 {
+        client1
+            .send(service_call_request(1, call_id, b"serial"))
+            .await
+            .expect("Failed to send");
+    }
+    expect_failure(next_json(&mut client1).await, 1, 3);
+    assert_eq!(pending.lock().len(), 1);
+
+    // The queued call is handled when the first call completes.
+    respond();
+    let msg = client1.next().await.unwrap().unwrap();
+    assert_eq!(msg.into_data().slice(5..9), 1u32.to_le_bytes().as_slice());
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert_eq!(pending.lock().len(), 1);
+    respond();
+    let msg = client1.next().await.unwrap().unwrap();
+    assert_eq!(msg.into_data().slice(5..9), 2u32.to_le_bytes().as_slice());
+
+    // Each client may make one concurrent call to the per-client service.
+    for call_id in 4..=5 {
+        client1
+            .send(service_call_request(2, call_id, b"per client"))
+            .await
+            .expect("Failed to send");
+    }
+    expect_failure(next_json(&mut client1).await, 2, 5);
+    client2
+        .send(service_call_request(2, 6, b"per client"))
+        .await
+        .expect("Failed to send");
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert_eq!(pending.lock().len(), 2);
+
+    server.stop().await;
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_service_timeout() {
+    // The handler holds on to its responders without responding.
+    let pending = Arc::new(parking_lot::Mutex::new(Vec::new()));
+    let hold = Service::builder("/hold", ServiceSchema::new("plain"))
+        .with_id(ServiceId::new(1))
+        .timeout(std::time::Duration::from_millis(100))
+        .max_concurrent_calls(1)
+        .handler_fn({
+            let pending = pending.clone();
+            move |_, _, responder| pending.lock().push(responder)
+        });
+    let server = create_server(ServerOptions {
+        services: HashMap::from([(hold.name().to_string(), hold)]),
+        supported_encodings: Some(HashSet::from(["raw".to_string()])),
+        ..Default::default()
+    });
+    let addr = server
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+
+    let mut client = connect_client(addr).await;
+    let _ = client.next().await.expect("No serverInfo sent").unwrap();
+    let msg = next_json(&mut client).await;
+    assert_eq!(msg["op"], "advertiseServices");
+
+    // Each call times out, which frees up the service's concurrency limit for the next call.
+    for call_id in 1..=2 {
+        client
+            .send(service_call_request(1, call_id, b"hold"))
+            .await
+            .expect("Failed to send");
+        let msg = next_json(&mut client).await;
+        assert_eq!(
+            msg,
+            json!({
+                "op": "serviceCallFailure",
+                "serviceId": 1,
+                "callId": call_id,
+                "message": "Service call timed out after 100 ms",
+            })
+        );
+    }
+    assert_eq!(pending.lock().len(), 2);
+
+    // Late responses are discarded.
+    for responder in pending.lock().drain(..) {
+        responder.respond(Ok(Bytes::from_static(b"late")));
+    }
+    assert!(logs_contain(
+        "Discarding response for service 1 call 1 after timeout"
+    ));
+    let msg = tokio::time::timeout(std::time::Duration::from_millis(100), client.next()).await;
+    assert!(msg.is_err(), "Unexpected message: {msg:?}");
+
+    server.stop().await;
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_typed_service() {
+    #[derive(Deserialize, serde::Serialize, schemars::JsonSchema)]
+    struct AddRequest {
+        a: i64,
+        b: i64,
+    }
+
+    #[derive(Deserialize, serde::Serialize, schemars::JsonSchema)]
+    struct AddResponse {
+        sum: i64,
+    }
+
+    let add = Service::typed::<AddRequest, AddResponse>("/add", |_, req| {
+        let sum = req.a.checked_add(req.b).ok_or("overflow")?;
+        Ok(AddResponse { sum })
+    });
+    let server = create_server(ServerOptions {
+        services: HashMap::from([(add.name().to_string(), add)]),
+        ..Default::default()
+    });
+    let addr = server
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+
+    let mut client = connect_client(addr).await;
+    let _ = client.next().await.expect("No serverInfo sent").unwrap();
+    let msg = next_json(&mut client).await;
+    assert_eq!(msg["op"], "advertiseServices");
+    let service = &msg["services"][0];
+    assert_eq!(service["name"], "/add");
+    assert_eq!(service["type"], "/add");
+    assert_eq!(service["request"]["encoding"], "json");
+    assert_eq!(service["request"]["schemaEncoding"], "jsonschema");
+    assert_eq!(service["response"]["encoding"], "json");
+    let service_id = service["id"].as_u64().unwrap() as u32;
+
+    client
+        .send(encoded_service_call_request(
+            service_id,
+            1,
+            "json",
+            br#"{"a":1,"b":2}"#,
+        ))
+        .await
+        .expect("Failed to send");
+    let msg = client
+        .next()
+        .await
+        .expect("No service call response")
+        .expect("Failed to parse response");
+    let mut buf = BytesMut::new();
+    buf.put_u8(3); // opcode
+    buf.put_u32_le(service_id);
+    buf.put_u32_le(1); // call id
+    buf.put_u32_le(4); // encoding length
+    buf.put(b"json".as_slice());
+    buf.put(br#"{"sum":3}"#.as_slice());
+    assert_eq!(msg.into_data(), buf);
+
+    // Requests that can't be decoded, and handler errors, are reported as failures.
+    for (call_id, payload, message) in [
+        (
+            2,
+            br#"{"a":1}"#.as_slice(),
+            "Failed to decode request: missing field `b` at line 1 column 7",
+        ),
+        (
+            3,
+            br#"{"a":1,"b":9223372036854775807}"#.as_slice(),
+            "overflow",
+        ),
+    ] {
+        client
+            .send(encoded_service_call_request(
+                service_id, call_id, "json", payload,
+            ))
+            .await
+            .expect("Failed to send");
+        let msg = next_json(&mut client).await;
+        assert_eq!(
+            msg,
+            json!({
+                "op": "serviceCallFailure",
+                "serviceId": service_id,
+                "callId": call_id,
+                "message": message,
+            })
+        );
+    }
+
+    server.stop().await;
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_service_call_without_response() {
+    async fn panicking() -> Result<Bytes, String> {
+        panic!("oh noes")
+    }
+
+    let dropped = Service::builder("/dropped", ServiceSchema::new("plain"))
+        .with_id(ServiceId::new(1))
+        .handler_fn(|_, _, responder| drop(responder));
+    let panics = Service::builder("/panics", ServiceSchema::new("plain"))
+        .with_id(ServiceId::new(2))
+        .sync_handler_fn(|_, _| -> Result<Bytes, String> { panic!("oh noes") });
+    let async_panics = Service::builder("/async_panics", ServiceSchema::new("plain"))
+        .with_id(ServiceId::new(3))
+        .async_handler_fn(|_, _| panicking());
+    let server = create_server(ServerOptions {
+        services: [dropped, panics, async_panics]
+            .into_iter()
+            .map(|s| (s.name().to_string(), s))
+            .collect(),
+        supported_encodings: Some(HashSet::from(["raw".to_string()])),
+        ..Default::default()
+    });
+    let addr = server
+        .start("127.0.0.1", 0)
+        .await
+        .expect("Failed to start server");
+
+    let mut client = connect_client(addr).await;
+    let _ = client.next().await.expect("No serverInfo sent").unwrap();
+    let msg = next_json(&mut client).await;
+    assert_eq!(msg["op"], "advertiseServices");
+
+    // Each call fails, and the connection survives the panics.
+    for service_id in 1..=3 {
+        client
+            .send(service_call_request(service_id, 10 + service_id, b""))
+            .await
+            .expect("Failed to send");
+        let msg = next_json(&mut client).await;
+        assert_eq!(
+            msg,
+            json!({
+                "op": "serviceCallFailure",
+                "serviceId": service_id,
+                "callId": 10 + service_id,
+                "message": "internal error: no response",
+            })
+        );
+    }
+
+    server.stop().await;
 }
@@ -0,0 +1,88 @@
+//! TLS termination for the websocket server.
+use std::borrow::Cow;
+use std::fmt::Debug;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::{self, ServerConfig};
+use tokio_rustls::server::TlsStream;
+pub(crate) use tokio_rustls::TlsAcceptor;
+
+use crate::FoxgloveError;
+
+/// The maximum time to wait for a client to complete the TLS handshake.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// PEM-encoded data, either in memory or in a file.
+#[derive(Clone)]
+pub(crate) enum PemSource {
+    Data(Vec<u8>),
+    File(PathBuf),
+}
+
+impl PemSource {
+    fn read(&self) -> Result<Cow<'_, [u8]>, FoxgloveError> {
+        match self {
+            Self::Data(data) => Ok(Cow::Borrowed(data)),
+            Self::File(path) => std::fs::read(path).map(Cow::Owned).map_err(|err| {
+                FoxgloveError::TlsConfig(format!("failed to read {}: {err}", path.display()))
+            }),
+        }
+    }
+}
+
+impl Debug for PemSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Data(_) => f.write_str("Data"),
+            Self::File(path) => f.debug_tuple("File").field(path).finish(),
+        }
+    }
+}
+
+/// A certificate chain and private key used to terminate TLS connections.
+#[derive(Debug, Clone)]
+pub(crate) struct TlsIdentity {
+    pub cert_chain: PemSource,
+    pub private_key: PemSource,
+}
+
+impl TlsIdentity {
+    /// Loads the certificate chain and private key, and builds a TLS acceptor.
+    pub fn acceptor(&self) -> Result<TlsAcceptor, FoxgloveError> {
+        let certs = CertificateDer::pem_slice_iter(&self.cert_chain.read()?)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| FoxgloveError::TlsConfig(format!("invalid certificate chain: {err}")))?;
+        if certs.is_empty() {
+            return Err(FoxgloveError::TlsConfig(
+                "no certificates found in certificate chain".into(),
+            ));
+        }
+        let key = PrivateKeyDer::from_pem_slice(&self.private_key.read()?)
+            .map_err(|err| FoxgloveError::TlsConfig(format!("invalid private key: {err}")))?;
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+            .map_err(|err| FoxgloveError::TlsConfig(err.to_string()))?;
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+/// Performs the TLS handshake.
+///
+/// Fails with [`TimedOut`](io::ErrorKind::TimedOut) if the client does not complete the handshake
+/// within [`TLS_HANDSHAKE_TIMEOUT`].
+pub(crate) async fn accept(
+    acceptor: &TlsAcceptor,
+    stream: TcpStream,
+) -> io::Result<TlsStream<TcpStream>> {
+    tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "timed out"))?
+}
@@ -1,11 +1,19 @@
 //! Websocket server
 
 use std::fmt::Debug;
+#[cfg(feature = "tls")]
+use std::path::Path;
 use std::sync::Arc;
 
+use crate::websocket::assets::AssetHandler;
 use crate::websocket::service::{Service, ServiceId};
-use crate::websocket::{create_server, Capability, Parameter, Server, ServerOptions, Status};
-use crate::{get_runtime_handle, FoxgloveError, LogContext, LogSink};
+use crate::websocket::{
+    create_server, Authenticator, Capability, ClientId, ClientSnapshot, ConnectionGraph, Parameter,
+    ParameterStore, QueuePolicy, Server, ServerOptions, SlowClientPolicy, Status,
+};
+#[cfg(feature = "tls")]
+use crate::websocket::{PemSource, TlsIdentity};
+use crate::{get_runtime_handle, FoxgloveError, LogContext, LogSink, TopicFilter, TopicPattern};
 use tokio::runtime::Handle;
 use tracing::warn;
 
@@ -16,6 +24,7 @@ pub struct WebSocketServer {
     host: String,
     port: u16,
     options: ServerOptions,
+    context: Arc<LogContext>,
 }
 
 impl Default for WebSocketServer {
@@ -28,6 +37,7 @@ impl Default for WebSocketServer {
             host: "127.0.0.1".into(),
             port: 8765,
             options,
+            context: LogContext::global_arc().clone(),
         }
     }
 }
@@ -69,6 +79,17 @@ impl WebSocketServer {
         self
     }
 
+    /// Configure an authenticator to accept or reject clients during the websocket handshake.
+    ///
+    /// The identity returned by the authenticator is available to the server listener and to
+    /// service handlers via [`Client::identity`][crate::websocket::Client::identity].
+    ///
+    /// By default, all clients are accepted.
+    pub fn authenticator(mut self, authenticator: impl Authenticator + 'static) -> Self {
+        self.options.authenticator = Some(Arc::new(authenticator));
+        self
+    }
+
     /// Set the message backlog size.
     ///
     /// The server buffers outgoing log entries into a queue. If the backlog size is exceeded, the
@@ -80,6 +101,15 @@ impl WebSocketServer {
         self
     }
 
+    /// Configure how the server handles clients that can't keep up with outgoing messages.
+    ///
+    /// By default, the server drops messages for slow clients, and keeps them connected. See
+    /// [`SlowClientPolicy`] for alternatives.
+    pub fn slow_client_policy(mut self, policy: SlowClientPolicy) -> Self {
+        self.options.slow_client_policy = policy;
+        self
+    }
+
     /// Configure the set of services to advertise to clients.
     ///
     /// Automatically adds [`Capability::Services`] to the set of advertised capabilities.
@@ -97,6 +127,28 @@ impl WebSocketServer {
         self
     }
 
+    /// Configure a handler for asset requests from clients.
+    ///
+    /// Automatically adds [`Capability::Assets`] to the set of advertised capabilities.
+    ///
+    /// See [`FilesystemAssetHandler`][crate::websocket::assets::FilesystemAssetHandler] and
+    /// [`PackageAssetHandler`][crate::websocket::assets::PackageAssetHandler] for built-in
+    /// handlers.
+    pub fn asset_handler(mut self, handler: impl AssetHandler + 'static) -> Self {
+        self.options.asset_handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// Configure a store to answer parameter requests from clients.
+    ///
+    /// Automatically adds [`Capability::Parameters`] and [`Capability::ParametersSubscribe`] to
+    /// the set of advertised capabilities. Parameter changes made with [`ParameterStore::set`]
+    /// are published to subscribed clients.
+    pub fn parameter_store(mut self, store: ParameterStore) -> Self {
+        self.options.parameter_store = Some(store);
+        self
+    }
+
     /// Configure the set of supported encodings for client requests.
     ///
     /// This is used for both client-side publishing as well as service call request/responses.
@@ -119,6 +171,69 @@ impl WebSocketServer {
         self
     }
 
+    /// Sets the log context to serve.
+    ///
+    /// By default, the server advertises channels from the [global context](LogContext::global).
+    pub fn context(mut self, ctx: &Arc<LogContext>) -> Self {
+        self.context = ctx.clone();
+        self
+    }
+
+    /// Sets a filter to select the channels to advertise to clients.
+    ///
+    /// By default, the server advertises all channels in its log context.
+    pub fn topic_filter(mut self, filter: TopicFilter) -> Self {
+        self.options.topic_filter = Some(filter);
+        self
+    }
+
+    /// Sets a queue policy for channels whose topics match the pattern.
+    ///
+    /// By default, outgoing messages for all channels share a single queue per client. Channels
+    /// with a queue policy get their own queue, so that bursts on one channel don't push out
+    /// messages on another. See [`QueuePolicy`] for details.
+    ///
+    /// If a topic matches more than one pattern, the first matching policy applies.
+    pub fn queue_policy(mut self, pattern: TopicPattern, policy: QueuePolicy) -> Self {
+        self.options.queue_policies.push((pattern, policy));
+        self
+    }
+
+    /// Enables TLS, so that clients connect with `wss://`.
+    ///
+    /// The certificate chain and private key are PEM-encoded data. To load them from files, use
+    /// [`WebSocketServer::tls_files`].
+    ///
+    /// The certificate chain and private key are validated when the server is started.
+    ///
+    /// Requires the `tls` feature.
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, cert_chain: impl Into<Vec<u8>>, private_key: impl Into<Vec<u8>>) -> Self {
+        self.options.tls = Some(TlsIdentity {
+            cert_chain: PemSource::Data(cert_chain.into()),
+            private_key: PemSource::Data(private_key.into()),
+        });
+        self
+    }
+
+    /// Enables TLS, so that clients connect with `wss://`.
+    ///
+    /// The certificate chain and private key are read from PEM files when the server is started.
+    ///
+    /// Requires the `tls` feature.
+    #[cfg(feature = "tls")]
+    pub fn tls_files(
+        mut self,
+        cert_chain_path: impl AsRef<Path>,
+        private_key_path: impl AsRef<Path>,
+    ) -> Self {
+        self.options.tls = Some(TlsIdentity {
+            cert_chain: PemSource::File(cert_chain_path.as_ref().to_path_buf()),
+            private_key: PemSource::File(private_key_path.as_ref().to_path_buf()),
+        });
+        self
+    }
+
     /// Configure the tokio runtime for the server to use for async tasks.
     ///
     /// By default, the server will use either the current runtime (if started with
@@ -138,8 +253,11 @@ impl WebSocketServer {
     pub async fn start(self) -> Result<WebSocketServerHandle, FoxgloveError> {
         let server = create_server(self.options);
         server.start(&self.host, self.port).await?;
-        LogContext::global().add_sink(server.clone());
-        Ok(WebSocketServerHandle(server))
+        self.context.add_sink(server.clone());
+        Ok(WebSocketServerHandle {
+            server,
+            context: self.context,
+        })
     }
 
     /// Starts the websocket server.
@@ -163,7 +281,10 @@ impl WebSocketServer {
 /// A handle to the websocket server.
 ///
 /// This handle can safely be dropped and the server will run forever.
-pub struct WebSocketServerHandle(Arc<Server>);
+pub struct WebSocketServerHandle {
+    server: Arc<Server>,
+    context: Arc<LogContext>,
+}
 
 impl Debug for WebSocketServerHandle {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -174,7 +295,7 @@ impl Debug for WebSocketServerHandle {
 impl WebSocketServerHandle {
     /// Returns a handle to the async runtime.
     fn runtime(&self) -> &Handle {
-        self.0.runtime()
+        self.server.runtime()
     }
 
     /// Advertises support for the provided services.
@@ -187,30 +308,54 @@ impl WebSocketServerHandle {
         &self,
         services: impl IntoIterator<Item = Service>,
     ) -> Result<(), FoxgloveError> {
-        self.0.add_services(services.into_iter().collect())
+        self.server.add_services(services.into_iter().collect())
     }
 
     /// Removes services that were previously advertised.
     pub fn remove_services(&self, ids: impl IntoIterator<Item = ServiceId>) {
-        self.0.remove_services(&ids.into_iter().collect::<Vec<_>>());
+        self.server
+            .remove_services(&ids.into_iter().collect::<Vec<_>>());
     }
 
     /// Publishes the current server timestamp to all clients.
     #[doc(hidden)]
     #[cfg(feature = "unstable")]
     pub async fn broadcast_time(&self, timestamp_nanos: u64) {
-        self.0.broadcast_time(timestamp_nanos).await;
+        self.server.broadcast_time(timestamp_nanos).await;
     }
 
     /// Sets a new session ID and notifies all clients, causing them to reset their state.
     /// If no session ID is provided, generates a new one based on the current timestamp.
     pub fn clear_session(&self, new_session_id: Option<String>) {
-        self.0.clear_session(new_session_id);
+        self.server.clear_session(new_session_id);
+    }
+
+    /// Replaces the connection graph, and sends updates to clients that are subscribed to it.
+    ///
+    /// Only the differences from the previous graph are sent. Clients that subscribe later
+    /// receive the full graph.
+    ///
+    /// This method will fail if the server was not configured with
+    /// [`Capability::ConnectionGraph`].
+    pub fn replace_connection_graph(&self, graph: ConnectionGraph) -> Result<(), FoxgloveError> {
+        self.server.replace_connection_graph(graph)
+    }
+
+    /// Returns a snapshot of each connected client.
+    pub fn clients(&self) -> Vec<ClientSnapshot> {
+        self.server.clients()
+    }
+
+    /// Disconnects a client, sending it a close frame with the given reason.
+    ///
+    /// Returns false if there is no connected client with the given ID.
+    pub fn disconnect(&self, client_id: ClientId, reason: &str) -> bool {
+        self.server.disconnect_client(client_id, reason)
     }
 
     /// Publishes parameter values to all clients.
     pub fn publish_parameter_values(&self, parameters: Vec<Parameter>) {
-        self.0.publish_parameter_values(parameters);
+        self.server.publish_parameter_values(parameters);
     }
 
     /// Publishes a status message to all clients.
@@ -219,7 +364,7 @@ impl WebSocketServerHandle {
     ///
     /// [status]: https://github.com/foxglove/ws-protocol/blob/main/docs/spec.md#status
     pub fn publish_status(&self, status: Status) {
-        self.0.publish_status(status);
+        self.server.publish_status(status);
     }
 
     /// Removes status messages by id from all clients.
@@ -228,14 +373,14 @@ impl WebSocketServerHandle {
     ///
     /// [remove-status]: https://github.com/foxglove/ws-protocol/blob/main/docs/spec.md#remove-status
     pub fn remove_status(&self, status_ids: Vec<String>) {
-        self.0.remove_status(status_ids);
+        self.server.remove_status(status_ids);
     }
 
     /// Gracefully shutdown the websocket server.
     pub async fn stop(self) {
-        let sink = self.0.clone() as Arc<dyn LogSink>;
-        LogContext::global().remove_sink(&sink);
-        self.0.stop().await;
+        let sink = self.server.clone() as Arc<dyn LogSink>;
+        self.context.remove_sink(&sink);
+        self.server.stop().await;
     }
 }
 
@@ -277,6 +422,29 @@ impl WebSocketServerBlockingHandle {
         self.0.clear_session(new_session_id);
     }
 
+    /// Replaces the connection graph, and sends updates to clients that are subscribed to it.
+    ///
+    /// Only the differences from the previous graph are sent. Clients that subscribe later
+    /// receive the full graph.
+    ///
+    /// This method will fail if the server was not configured with
+    /// [`Capability::ConnectionGraph`].
+    pub fn replace_connection_graph(&self, graph: ConnectionGraph) -> Result<(), FoxgloveError> {
+        self.0.replace_connection_graph(graph)
+    }
+
+    /// Returns a snapshot of each connected client.
+    pub fn clients(&self) -> Vec<ClientSnapshot> {
+        self.0.clients()
+    }
+
+    /// Disconnects a client, sending it a close frame with the given reason.
+    ///
+    /// Returns false if there is no connected client with the given ID.
+    pub fn disconnect(&self, client_id: ClientId, reason: &str) -> bool {
+        self.0.disconnect(client_id, reason)
+    }
+
     /// Publishes parameter values to all clients.
     pub fn publish_parameter_values(&self, parameters: Vec<Parameter>) {
         self.0.publish_parameter_values(parameters)
@@ -305,3 +473,82 @@ impl WebSocketServerBlockingHandle {
         self.0.runtime().clone().block_on(self.0.stop());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use serde_json::{json, Value};
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::http::HeaderValue;
+    use tokio_tungstenite::tungstenite::{self, Message};
+
+    use super::*;
+    use crate::websocket::SUBPROTOCOL;
+
+    /// Returns a port which is likely to be available.
+    fn unused_port() -> u16 {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("Failed to bind");
+        listener.local_addr().expect("No local address").port()
+    }
+
+    /// Reads the next message from a client, and parses it as JSON.
+    fn next_json<S: std::io::Read + std::io::Write>(
+        client: &mut tungstenite::WebSocket<S>,
+    ) -> Value {
+        let msg = client.read().expect("Failed to read");
+        serde_json::from_str(msg.to_text().expect("Expected utf8")).expect("Failed to parse JSON")
+    }
+
+    #[test]
+    fn test_blocking_replace_connection_graph() {
+        let port = unused_port();
+        let server = WebSocketServer::new()
+            .capabilities([Capability::ConnectionGraph])
+            .bind("127.0.0.1", port)
+            .start_blocking()
+            .expect("Failed to start server");
+
+        let mut request = format!("ws://127.0.0.1:{port}/")
+            .into_client_request()
+            .expect("Failed to build request");
+        request.headers_mut().insert(
+            "sec-websocket-protocol",
+            HeaderValue::from_static(SUBPROTOCOL),
+        );
+        let (mut client, _) = tungstenite::connect(request).expect("Failed to connect");
+        assert_eq!(next_json(&mut client)["op"], "serverInfo");
+
+        client
+            .send(Message::text(r#"{"op":"subscribeConnectionGraph"}"#))
+            .expect("Failed to send");
+        assert_eq!(next_json(&mut client)["op"], "connectionGraphUpdate");
+
+        let mut graph = ConnectionGraph::new();
+        graph.set_published_topic("/a", ["pub1"]);
+        server
+            .replace_connection_graph(graph)
+            .expect("Failed to replace graph");
+        assert_eq!(
+            next_json(&mut client),
+            json!({
+                "op": "connectionGraphUpdate",
+                "publishedTopics": [{"name": "/a", "publisherIds": ["pub1"]}],
+                "subscribedTopics": [],
+                "advertisedServices": [],
+                "removedTopics": [],
+                "removedServices": [],
+            })
+        );
+        server.stop();
+
+        let server = WebSocketServer::new()
+            .bind("127.0.0.1", 0)
+            .start_blocking()
+            .expect("Failed to start server");
+        assert_matches!(
+            server.replace_connection_graph(ConnectionGraph::new()),
+            Err(FoxgloveError::ConnectionGraphNotSupported)
+        );
+        server.stop();
+    }
+}